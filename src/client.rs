@@ -1,26 +1,70 @@
-use frame::{StreamId, Headers, Pseudo, Settings};
+use frame::{StreamId, StreamDependency, Headers, Pseudo, Settings, DEFAULT_MAX_FRAME_SIZE};
 use frame::Reason::*;
 use codec::{Codec, RecvError};
 use proto::{self, Connection, WindowSize};
+use padding::Padding;
+use scheduler::{Scheduler, NewScheduler};
+use stats::FlowStats;
+use tunnel::Pipe;
+use {Priority, Protocol};
 
-use http::{Request, Response, HeaderMap};
-use futures::{Future, Poll, Sink, Async, AsyncSink, AndThen, MapErr};
+use rewind::Rewind;
+
+use http::{Request, Response, HeaderMap, Method};
+use futures::{Future, Poll, Sink, Async, AsyncSink, MapErr};
 use tokio_io::{AsyncRead, AsyncWrite};
-use tokio_io::io::WriteAll;
+use tokio_io::io::{WriteAll, write_all};
 use bytes::{Bytes, IntoBuf};
+use tokio_timer::Timer;
 
-use std::fmt;
-use std::io::Error as IoError;
+use std::{fmt, mem, str};
+use std::io::{self, Read, Write, Error as IoError};
+use std::marker::PhantomData;
+use std::time::Duration;
 
 /// In progress H2 connection binding
 pub struct Handshake<T: AsyncRead + AsyncWrite, B: IntoBuf = Bytes> {
-    inner:
-        AndThen<
-            MapErr<WriteAll<T, &'static [u8]>, fn(IoError) -> ::Error>,
-            Result<Client<T, B>, ::Error>,
-            fn((T, &'static [u8])) -> Result<Client<T, B>, ::Error>
-        >
+    inner: MapErr<WriteAll<T, &'static [u8]>, fn(IoError) -> ::Error>,
+    config: proto::Config,
+    _marker: PhantomData<B>,
+}
+
+/// In progress H2 connection binding upgraded from HTTP/1.1 (h2c)
+pub struct Upgrade<T: AsyncRead + AsyncWrite, B: IntoBuf = Bytes> {
+    state: UpgradeState<T, B>,
+    method: Method,
+    builder: Builder,
+}
+
+enum UpgradeState<T: AsyncRead + AsyncWrite, B: IntoBuf> {
+    /// Writing the HTTP/1.1 upgrade request
+    Request(WriteAll<T, Vec<u8>>),
+
+    /// Reading the response to the upgrade request
+    Response(Option<T>, Vec<u8>),
 
+    /// Performing the HTTP/2 handshake
+    Handshake(Handshake<T, B>),
+}
+
+/// The outcome of an h2c upgrade, see `Builder::upgrade`.
+pub enum Upgraded<T, B: IntoBuf = Bytes> {
+    /// The server switched protocols, yielding the connection and the
+    /// `Stream` of the upgrade request
+    Http2(Client<T, B>, Stream<B>),
+
+    /// The server kept speaking HTTP/1.1, its response is replayed by the
+    /// `Fallback` before the rest of the connection
+    Http1(Fallback<T>),
+}
+
+/// I/O handle of a connection whose h2c upgrade was refused.
+///
+/// Reads first replay the bytes of the response read while waiting for the
+/// server to switch protocols, then read from the underlying I/O.
+#[derive(Debug)]
+pub struct Fallback<T> {
+    inner: Rewind<T>,
 }
 
 /// Marker type indicating a client peer
@@ -38,15 +82,70 @@ pub struct Body<B: IntoBuf> {
     inner: proto::StreamRef<B::Buf, Peer>,
 }
 
+/// Bidirectional byte pipe over the stream of a CONNECT request.
+///
+/// Writes are sent as DATA frames as flow control allows, reads yield the
+/// data sent by the server and shutting down ends the stream.
+#[derive(Debug)]
+pub struct Tunnel {
+    inner: Pipe<Peer>,
+}
+
+/// A response pushed by the server, promised on one of the client's streams.
+#[derive(Debug)]
+pub struct PushPromise<B: IntoBuf> {
+    request: Request<()>,
+    inner: proto::StreamRef<B::Buf, Peer>,
+}
+
+/// A future resolving to the response of a server push.
+#[derive(Debug)]
+pub struct PushedResponse<B: IntoBuf> {
+    inner: proto::StreamRef<B::Buf, Peer>,
+}
+
+/// A future resolving to the round-trip time of a PING frame.
+#[derive(Debug)]
+pub struct Ping {
+    inner: proto::UserPing,
+}
+
+/// Build a Client.
+#[derive(Clone, Debug, Default)]
+pub struct Builder {
+    config: proto::Config,
+}
+
 #[derive(Debug)]
 pub(crate) struct Peer;
 
+/// Status line of a response accepting an h2c upgrade
+const SWITCHING_PROTOCOLS: &'static [u8] = b"HTTP/1.1 101 ";
+
+/// Max length of the response to an h2c upgrade request
+const MAX_UPGRADE_RESPONSE_LEN: usize = 8_192;
+
+impl Client<(), Bytes> {
+    /// Returns a client builder instance, used to configure the local
+    /// settings of the connection.
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+}
+
 impl<T> Client<T, Bytes>
     where T: AsyncRead + AsyncWrite,
 {
     pub fn handshake(io: T) -> Handshake<T, Bytes> {
         Client::handshake2(io)
     }
+
+    /// Bind an H2 client connection upgraded from HTTP/1.1 (h2c).
+    ///
+    /// See `Builder::upgrade`.
+    pub fn upgrade(io: T, request: Request<()>) -> Upgrade<T, Bytes> {
+        Builder::default().upgrade(io, request)
+    }
 }
 
 impl<T, B> Client<T, B>
@@ -61,40 +160,7 @@ impl<T, B> Client<T, B>
     /// It's important to note that this does not **flush** the outbound
     /// settings to the wire.
     pub fn handshake2(io: T) -> Handshake<T, B> {
-        use tokio_io::io;
-
-        debug!("binding client connection");
-
-        let bind: fn((T, &'static [u8]))
-                    -> Result<Client<T, B>, ::Error> =
-            |(io, _)| {
-                debug!("client connection bound");
-
-                // Create the codec
-                let mut codec = Codec::new(io);
-
-                // Create the initial SETTINGS frame
-                let settings = Settings::default();
-
-                // Send initial settings frame
-                match codec.start_send(settings.into()) {
-                    Ok(AsyncSink::Ready) => {
-                        let connection = Connection::new(codec);
-                        Ok(Client { connection })
-                    }
-                    Ok(_) => unreachable!(),
-                    Err(e) => Err(::Error::from(e)),
-                }
-            };
-
-        let msg: &'static [u8] =  b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
-        let handshake = io::write_all(io, msg)
-            .map_err(::Error::from as
-                fn(IoError) -> ::Error
-            )
-            .and_then(bind);
-
-        Handshake { inner: handshake }
+        Builder::default().handshake(io)
     }
 
     /// Returns `Ready` when the connection can initialize a new HTTP 2.0
@@ -104,15 +170,96 @@ impl<T, B> Client<T, B>
     }
 
     /// Send a request on a new HTTP 2.0 stream
+    ///
+    /// A CONNECT request without an authority, or a `Protocol` extension on a
+    /// request that is not CONNECT, results in an error.
     pub fn request(&mut self, request: Request<()>, end_of_stream: bool)
         -> Result<Stream<B>, ::Error>
     {
-        self.connection.send_request(request, end_of_stream)
+        self.send_request(request, None, end_of_stream)
+    }
+
+    /// Send a request on a new HTTP 2.0 stream with the given priority.
+    ///
+    /// The priority is included in the request's HEADERS frame.
+    pub fn request_with_priority(&mut self,
+                                 request: Request<()>,
+                                 priority: Priority,
+                                 end_of_stream: bool)
+        -> Result<Stream<B>, ::Error>
+    {
+        self.send_request(request, Some(priority.into()), end_of_stream)
+    }
+
+    fn send_request(&mut self,
+                    request: Request<()>,
+                    stream_dep: Option<StreamDependency>,
+                    end_of_stream: bool)
+        -> Result<Stream<B>, ::Error>
+    {
+        self.connection.send_request(request, stream_dep, end_of_stream)
             .map_err(Into::into)
             .map(|stream| Stream {
                 inner: stream,
             })
     }
+
+    /// Sets the initial window size of streams opened by the remote.
+    ///
+    /// A new SETTINGS frame is sent the next time the connection is polled.
+    /// The receive window of every open stream is adjusted by the difference
+    /// once the remote acknowledges the change.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is larger than 2^31-1.
+    pub fn set_initial_window_size(&mut self, size: u32) {
+        self.connection.set_initial_window_size(size);
+    }
+
+    /// Sets the max number of concurrent streams that the remote may open.
+    ///
+    /// A new SETTINGS frame is sent the next time the connection is polled.
+    /// The limit is enforced once the remote acknowledges the change. Streams
+    /// that are already open are not affected.
+    pub fn set_max_concurrent_streams(&mut self, max: u32) {
+        self.connection.set_max_concurrent_streams(max);
+    }
+
+    /// Sets opaque debug data to include in GOAWAY frames sent to the server.
+    ///
+    /// This applies to GOAWAY frames sent when closing the connection due to
+    /// an error. The data is meant for diagnostics only and is truncated to
+    /// fit in a single frame.
+    pub fn set_go_away_debug_data<D: Into<Bytes>>(&mut self, debug_data: D) {
+        self.connection.set_go_away_debug_data(debug_data.into());
+    }
+
+    /// Returns the connection's flow control state.
+    ///
+    /// The buffered and unreleased amounts are totals over all streams.
+    pub fn flow_stats(&mut self) -> FlowStats {
+        self.connection.flow_stats()
+    }
+
+    /// Returns true if the server accepts extended CONNECT requests (RFC
+    /// 8441).
+    ///
+    /// This is false until the server's SETTINGS frame enabling them is
+    /// received. A request with a `Protocol` extension fails to be sent
+    /// otherwise.
+    pub fn is_extended_connect_protocol_enabled(&self) -> bool {
+        self.connection.is_extended_connect_protocol_enabled()
+    }
+
+    /// Send a PING frame to the server.
+    ///
+    /// Returns a future which resolves to the round-trip time once the server
+    /// acknowledges the PING. The frame is sent the next time the connection
+    /// is polled.
+    pub fn ping(&mut self) -> Ping {
+        Ping { inner: self.connection.ping() }
+    }
 }
 
 impl<T, B> Future for Client<T, B>
@@ -142,6 +289,229 @@ impl<T, B> fmt::Debug for Client<T, B>
     }
 }
 
+// ===== impl Builder =====
+
+impl Builder {
+    /// Set the initial window size of streams opened by the remote.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is larger than 2^31-1.
+    pub fn initial_window_size(&mut self, size: u32) -> &mut Self {
+        self.config.settings.set_initial_window_size(Some(size));
+        self
+    }
+
+    /// Set the max number of concurrent streams that the remote may open.
+    pub fn max_concurrent_streams(&mut self, max: u32) -> &mut Self {
+        self.config.settings.set_max_concurrent_streams(Some(max));
+        self
+    }
+
+    /// Set the max size of frame payloads that the remote may send.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is not in the range [2^14, 2^24-1].
+    pub fn max_frame_size(&mut self, max: u32) -> &mut Self {
+        self.config.settings.set_max_frame_size(Some(max));
+        self
+    }
+
+    /// Set the max size of the HPACK table used to decode headers.
+    pub fn header_table_size(&mut self, size: u32) -> &mut Self {
+        self.config.settings.set_header_table_size(Some(size));
+        self
+    }
+
+    /// Set the max size of header lists that the remote may send.
+    pub fn max_header_list_size(&mut self, max: u32) -> &mut Self {
+        self.config.settings.set_max_header_list_size(Some(max));
+        self
+    }
+
+    /// Enable or disable server push.
+    pub fn enable_push(&mut self, enabled: bool) -> &mut Self {
+        self.config.settings.set_enable_push(enabled);
+        self
+    }
+
+    /// Enable or disable releasing capacity automatically.
+    ///
+    /// When enabled, the capacity of data received on a stream is released
+    /// back to the peer as soon as the data is yielded by `Body::poll`, and
+    /// WINDOW_UPDATE frames are batched. By default, capacity must be released
+    /// with `Body::release_capacity`, which allows applying backpressure.
+    ///
+    /// This can be overridden on each `Body`.
+    pub fn auto_release_capacity(&mut self, enabled: bool) -> &mut Self {
+        self.config.auto_release_capacity = enabled;
+        self
+    }
+
+    /// Enable or disable adaptive flow control.
+    ///
+    /// When enabled, the bandwidth-delay product of the connection is measured
+    /// using PING round trips and the rate at which data is received. The
+    /// connection and stream receive windows grow through WINDOW_UPDATE and
+    /// SETTINGS frames when they are too small to keep the link busy, up to
+    /// `max_adaptive_window_size`. Disabled by default.
+    pub fn adaptive_window(&mut self, enabled: bool) -> &mut Self {
+        self.config.adaptive_window = enabled;
+        self
+    }
+
+    /// Set the max size that adaptive flow control grows the receive windows
+    /// to.
+    ///
+    /// Defaults to 16 MiB. Values larger than the max window size allowed by
+    /// the protocol are capped.
+    pub fn max_adaptive_window_size(&mut self, max: u32) -> &mut Self {
+        self.config.max_adaptive_window_size = max;
+        self
+    }
+
+    /// Disable the RFC 7540 priority scheme in favor of RFC 9218 extensible
+    /// priorities.
+    ///
+    /// This is advertised to the server with SETTINGS_NO_RFC7540_PRIORITIES.
+    /// To schedule request bodies by the urgency and incremental parameters
+    /// of each request's `priority` header, set the `Urgency` scheduler.
+    pub fn no_rfc7540_priorities(&mut self, enabled: bool) -> &mut Self {
+        self.config.settings.set_no_rfc7540_priorities(enabled);
+        self
+    }
+
+    /// Set how long to wait for the remote to acknowledge the local settings.
+    ///
+    /// If the SETTINGS frame is not acknowledged in time, the connection is
+    /// closed with a GOAWAY frame carrying `SETTINGS_TIMEOUT`. By default, no
+    /// timeout is enforced.
+    pub fn settings_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.settings_timeout = Some(timeout);
+        self
+    }
+
+    /// Enable keep-alive PINGs, sent once the connection has not received
+    /// any frames for `interval`.
+    ///
+    /// If a keep-alive PING is not acknowledged within the keep-alive timeout,
+    /// the connection is considered dead. All streams are reset and the
+    /// connection fails with an I/O error of kind `TimedOut`.
+    pub fn keep_alive_interval(&mut self, interval: Duration) -> &mut Self {
+        self.config.keep_alive_interval = Some(interval);
+        self
+    }
+
+    /// Set how long to wait for a keep-alive PING to be acknowledged.
+    ///
+    /// Defaults to 20 seconds.
+    pub fn keep_alive_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.keep_alive_timeout = timeout;
+        self
+    }
+
+    /// Set whether keep-alive PINGs are sent when there are no open streams.
+    ///
+    /// By default, keep-alive PINGs are only sent while streams are open.
+    pub fn keep_alive_while_idle(&mut self, enabled: bool) -> &mut Self {
+        self.config.keep_alive_while_idle = enabled;
+        self
+    }
+
+    /// Set the scheduler deciding which stream sends the next frame.
+    ///
+    /// Each connection uses its own clone of `scheduler`. By default, `Fifo`
+    /// is used, which ignores priorities: ready streams take turns writing a
+    /// frame in the order they became ready.
+    pub fn scheduler<S>(&mut self, scheduler: S) -> &mut Self
+        where S: Scheduler + Clone + Sync,
+    {
+        self.config.scheduler = Some(NewScheduler::new(scheduler));
+        self
+    }
+
+    /// Set the padding added to outbound DATA and HEADERS frames.
+    ///
+    /// Padding hides the exact size of headers and body chunks to mitigate
+    /// traffic analysis. The padding of DATA frames counts against flow
+    /// control. By default, frames are not padded.
+    pub fn padding(&mut self, padding: Padding) -> &mut Self {
+        self.config.padding = Some(padding);
+        self
+    }
+
+    /// Set the timer used to track timeouts.
+    ///
+    /// If no timer is provided, a new one is created for each connection that
+    /// needs one, which spawns a thread. Sharing a timer across connections
+    /// avoids this.
+    pub fn timer(&mut self, timer: Timer) -> &mut Self {
+        self.config.timer = Some(timer);
+        self
+    }
+
+    /// Bind an H2 client connection.
+    ///
+    /// Returns a future which resolves to the connection value once the H2
+    /// handshake has been completed.
+    ///
+    /// It's important to note that this does not **flush** the outbound
+    /// settings to the wire.
+    pub fn handshake<T, B>(&self, io: T) -> Handshake<T, B>
+        where T: AsyncRead + AsyncWrite,
+              B: IntoBuf,
+    {
+        use tokio_io::io;
+
+        debug!("binding client connection");
+
+        let msg: &'static [u8] =  b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+        let handshake = io::write_all(io, msg)
+            .map_err(::Error::from as
+                fn(IoError) -> ::Error
+            );
+
+        Handshake {
+            inner: handshake,
+            config: self.config.clone(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Bind an H2 client connection upgraded from HTTP/1.1 (h2c).
+    ///
+    /// `request` is written to `io` as an HTTP/1.1 request without a body,
+    /// carrying `Upgrade: h2c` and the local settings in its `HTTP2-Settings`
+    /// header. Once the server responds with `101 Switching Protocols`, the
+    /// HTTP/2 handshake continues as with `handshake`.
+    ///
+    /// The returned future resolves to `Upgraded::Http2` with the connection
+    /// and the `Stream` of the upgrade request, which is stream 1. The server
+    /// sends the response to the request on this stream.
+    ///
+    /// If the server responds with any other status, it ignored the upgrade
+    /// and keeps speaking HTTP/1.1. The future then resolves to
+    /// `Upgraded::Http1` with a `Fallback` replaying the bytes of the response
+    /// read so far, so that the response can be read as HTTP/1.1. A `101`
+    /// response whose `Upgrade` header names any protocol other than h2c
+    /// fails the future with a protocol error.
+    pub fn upgrade<T, B>(&self, io: T, request: Request<()>) -> Upgrade<T, B>
+        where T: AsyncRead + AsyncWrite,
+              B: IntoBuf,
+    {
+        debug!("upgrading client connection");
+
+        let head = Peer::convert_upgrade_message(&request, &self.config.settings);
+
+        Upgrade {
+            state: UpgradeState::Request(write_all(io, head)),
+            method: request.method().clone(),
+            builder: self.clone(),
+        }
+    }
+}
+
 // ===== impl Handshake =====
 
 impl<T, B: IntoBuf> Future for Handshake<T, B>
@@ -150,7 +520,25 @@ where T: AsyncRead + AsyncWrite {
     type Error = ::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        self.inner.poll()
+        let (io, _) = try_ready!(self.inner.poll());
+
+        debug!("client connection bound");
+
+        let max_frame_size = self.config.settings.max_frame_size()
+            .unwrap_or(DEFAULT_MAX_FRAME_SIZE);
+
+        // Create the codec
+        let mut codec = Codec::with_max_recv_frame_size(io, max_frame_size as usize);
+
+        // Send initial settings frame
+        match codec.start_send(self.config.settings.clone().into()) {
+            Ok(AsyncSink::Ready) => {
+                let connection = Connection::new(codec, &self.config);
+                Ok(Async::Ready(Client { connection }))
+            }
+            Ok(_) => unreachable!(),
+            Err(e) => Err(::Error::from(e)),
+        }
     }
 }
 
@@ -165,6 +553,190 @@ impl<T, B> fmt::Debug for Handshake<T, B>
     }
 }
 
+// ===== impl Upgrade =====
+
+impl<T, B: IntoBuf> Upgrade<T, B>
+where T: AsyncRead + AsyncWrite {
+    /// Reads the response to the upgrade request, up to the end of its head.
+    ///
+    /// Resolves to `false` as soon as the status line shows that the server
+    /// did not switch protocols, leaving the rest of the response unread. A
+    /// server switching to any protocol other than h2c is a protocol error.
+    ///
+    /// The response is read one byte at a time, as the server's frames follow
+    /// it immediately and must be left for the codec.
+    fn read_response(io: &mut T, buf: &mut Vec<u8>) -> Poll<bool, ::Error> {
+        while !buf.ends_with(b"\r\n\r\n") {
+            if buf.len() >= MAX_UPGRADE_RESPONSE_LEN {
+                debug!("upgrade response too large");
+                return Err(ProtocolError.into());
+            }
+
+            let mut byte = [0; 1];
+
+            if try_nb!(io.read(&mut byte)) == 0 {
+                let err = io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "connection closed before upgrade response");
+                return Err(err.into());
+            }
+
+            buf.push(byte[0]);
+
+            if buf.len() <= SWITCHING_PROTOCOLS.len() && !SWITCHING_PROTOCOLS.starts_with(buf) {
+                debug!("server did not switch protocols");
+                return Ok(Async::Ready(false));
+            }
+        }
+
+        if !upgrades_to_h2c(buf) {
+            debug!("server switched to a protocol other than h2c");
+            return Err(ProtocolError.into());
+        }
+
+        Ok(Async::Ready(true))
+    }
+}
+
+/// Returns true if the `Upgrade` header of a response head names h2c.
+fn upgrades_to_h2c(head: &[u8]) -> bool {
+    // Skip the status line
+    for line in head.split(|&b| b == b'\n').skip(1) {
+        let colon = match line.iter().position(|&b| b == b':') {
+            Some(colon) => colon,
+            None => continue,
+        };
+
+        if !line[..colon].eq_ignore_ascii_case(b"upgrade") {
+            continue;
+        }
+
+        return str::from_utf8(&line[colon + 1..])
+            .map(|value| value.trim().eq_ignore_ascii_case("h2c"))
+            .unwrap_or(false);
+    }
+
+    false
+}
+
+impl<T, B: IntoBuf> Future for Upgrade<T, B>
+where T: AsyncRead + AsyncWrite {
+    type Item = Upgraded<T, B>;
+    type Error = ::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let next = match self.state {
+                UpgradeState::Request(ref mut write) => {
+                    let (io, _) = try_ready!(write.poll());
+                    UpgradeState::Response(Some(io), vec![])
+                }
+                UpgradeState::Response(ref mut io, ref mut buf) => {
+                    let switched = try_ready!(Self::read_response(io.as_mut().unwrap(), buf));
+
+                    if !switched {
+                        let pre = Bytes::from(mem::replace(buf, vec![]));
+                        let io = Fallback {
+                            inner: Rewind::new(io.take().unwrap(), pre),
+                        };
+
+                        return Ok(Async::Ready(Upgraded::Http1(io)));
+                    }
+
+                    debug!("client connection upgraded");
+                    UpgradeState::Handshake(self.builder.handshake(io.take().unwrap()))
+                }
+                UpgradeState::Handshake(ref mut handshake) => {
+                    let mut client = try_ready!(handshake.poll());
+
+                    // The upgrade request was sent on stream 1
+                    let stream = client.connection.send_upgrade(&self.method)?;
+                    let stream = Stream { inner: stream };
+
+                    return Ok(Async::Ready(Upgraded::Http2(client, stream)));
+                }
+            };
+
+            self.state = next;
+        }
+    }
+}
+
+impl<T, B> fmt::Debug for Upgrade<T, B>
+    where T: AsyncRead + AsyncWrite,
+          T: fmt::Debug,
+          B: fmt::Debug + IntoBuf,
+          B::Buf: fmt::Debug + IntoBuf,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "client::Upgrade")
+    }
+}
+
+// ===== impl Upgraded =====
+
+impl<T, B> fmt::Debug for Upgraded<T, B>
+    where T: AsyncRead + AsyncWrite,
+          T: fmt::Debug,
+          B: fmt::Debug + IntoBuf,
+          B::Buf: fmt::Debug,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Upgraded::Http2(ref client, ref stream) => {
+                fmt.debug_tuple("Http2").field(client).field(stream).finish()
+            }
+            Upgraded::Http1(ref io) => {
+                fmt.debug_tuple("Http1").field(io).finish()
+            }
+        }
+    }
+}
+
+// ===== impl Fallback =====
+
+impl<T> Fallback<T> {
+    /// Returns a reference to the underlying I/O.
+    pub fn get_ref(&self) -> &T {
+        self.inner.get_ref()
+    }
+
+    /// Returns a mutable reference to the underlying I/O.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+
+    /// Consumes the `Fallback`, returning the underlying I/O and the bytes of
+    /// the response that were not replayed yet.
+    pub fn into_parts(self) -> (T, Bytes) {
+        self.inner.into_parts()
+    }
+}
+
+impl<T: io::Read> io::Read for Fallback<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<T: AsyncRead> AsyncRead for Fallback<T> {}
+
+impl<T: io::Write> io::Write for Fallback<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: AsyncWrite> AsyncWrite for Fallback<T> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.inner.shutdown()
+    }
+}
+
 // ===== impl Stream =====
 
 impl<B: IntoBuf> Stream<B> {
@@ -176,6 +748,21 @@ impl<B: IntoBuf> Stream<B> {
         Ok(Response::from_parts(parts, body).into())
     }
 
+    /// Receive the next response pushed by the server on this stream.
+    ///
+    /// Returns `None` once the server has ended the stream, after which no
+    /// more pushes may be promised.
+    pub fn poll_pushed(&mut self) -> Poll<Option<PushPromise<B>>, ::Error> {
+        let pushed = try_ready!(self.inner.poll_pushed());
+
+        Ok(pushed.map(|(request, inner)| {
+            PushPromise {
+                request: request,
+                inner: inner,
+            }
+        }).into())
+    }
+
     /// Request capacity to send data
     pub fn reserve_capacity(&mut self, capacity: usize) {
         // TODO: Check for overflow
@@ -187,6 +774,25 @@ impl<B: IntoBuf> Stream<B> {
         self.inner.capacity() as usize
     }
 
+    /// Change the priority of the stream.
+    ///
+    /// A PRIORITY frame is sent to the server once any frames already queued
+    /// on the stream have been sent. A priority that makes the stream depend
+    /// on itself is ignored.
+    pub fn set_priority(&mut self, priority: Priority) {
+        self.inner.set_priority(priority.into())
+    }
+
+    /// Returns the stream's identifier.
+    pub fn stream_id(&self) -> StreamId {
+        self.inner.stream_id()
+    }
+
+    /// Returns the stream's flow control state.
+    pub fn flow_stats(&self) -> FlowStats {
+        self.inner.flow_stats()
+    }
+
     /// Request to be notified when the stream's capacity increases
     pub fn poll_capacity(&mut self) -> Poll<Option<usize>, ::Error> {
         let res = try_ready!(self.inner.poll_capacity());
@@ -210,6 +816,17 @@ impl<B: IntoBuf> Stream<B> {
     }
 }
 
+impl Stream<Bytes> {
+    /// Converts the stream of a CONNECT request into a tunnel.
+    ///
+    /// This must be called once `poll_response` has returned a successful
+    /// (2xx) response. The capacity of the data read from the tunnel is
+    /// released automatically.
+    pub fn into_tunnel(self) -> Tunnel {
+        Tunnel { inner: Pipe::new(self.inner) }
+    }
+}
+
 impl<B: IntoBuf> Future for Stream<B> {
     type Item = Response<Body<B>>;
     type Error = ::Error;
@@ -219,6 +836,33 @@ impl<B: IntoBuf> Future for Stream<B> {
     }
 }
 
+// ===== impl Tunnel =====
+
+impl io::Read for Tunnel {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl AsyncRead for Tunnel {}
+
+impl io::Write for Tunnel {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncWrite for Tunnel {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.inner.shutdown()?;
+        Ok(Async::Ready(()))
+    }
+}
+
 // ===== impl Body =====
 
 impl<B: IntoBuf> Body<B> {
@@ -232,6 +876,15 @@ impl<B: IntoBuf> Body<B> {
             .map_err(Into::into)
     }
 
+    /// Sets whether capacity is released when data is yielded by `poll`,
+    /// overriding the connection's default.
+    ///
+    /// Capacity of data already yielded must still be released with
+    /// `release_capacity`.
+    pub fn set_auto_release_capacity(&mut self, enabled: bool) {
+        self.inner.set_auto_release_capacity(enabled)
+    }
+
     /// Poll trailers
     ///
     /// This function **must** not be called until `Body::poll` returns `None`.
@@ -251,8 +904,106 @@ impl<B: IntoBuf> ::futures::Stream for Body<B> {
     }
 }
 
+// ===== impl PushPromise =====
+
+impl<B: IntoBuf> PushPromise<B> {
+    /// Returns the request promised by the server.
+    pub fn request(&self) -> &Request<()> {
+        &self.request
+    }
+
+    /// Consumes the push, returning the promised request and a future of the
+    /// pushed response.
+    pub fn into_parts(self) -> (Request<()>, PushedResponse<B>) {
+        (self.request, PushedResponse { inner: self.inner })
+    }
+
+    /// Refuse the push by resetting the promised stream with `CANCEL`.
+    pub fn refuse(mut self) {
+        self.inner.send_reset(Cancel);
+    }
+}
+
+// ===== impl PushedResponse =====
+
+impl<B: IntoBuf> Future for PushedResponse<B> {
+    type Item = Response<Body<B>>;
+    type Error = ::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let (parts, _) = try_ready!(self.inner.poll_response()).into_parts();
+        let body = Body { inner: self.inner.clone() };
+
+        Ok(Response::from_parts(parts, body).into())
+    }
+}
+
+// ===== impl Ping =====
+
+impl Future for Ping {
+    type Item = Duration;
+    type Error = ::Error;
+
+    fn poll(&mut self) -> Poll<Duration, ::Error> {
+        self.inner.poll_rtt()
+            .map_err(Into::into)
+    }
+}
+
 // ===== impl Peer =====
 
+impl Peer {
+    /// Encodes the HTTP/1.1 request of an h2c upgrade, carrying `settings` in
+    /// its `HTTP2-Settings` header.
+    pub fn convert_upgrade_message(request: &Request<()>, settings: &Settings) -> Vec<u8> {
+        use http::{header, uri};
+
+        fn put_header(dst: &mut Vec<u8>, name: &[u8], value: &[u8]) {
+            dst.extend_from_slice(name);
+            dst.extend_from_slice(b": ");
+            dst.extend_from_slice(value);
+            dst.extend_from_slice(b"\r\n");
+        }
+
+        let parts = uri::Parts::from(request.uri().clone());
+        let mut dst = vec![];
+
+        let path = parts.path_and_query
+            .map(Bytes::from)
+            .unwrap_or_else(|| Bytes::from_static(b"/"));
+
+        dst.extend_from_slice(request.method().as_str().as_bytes());
+        dst.push(b' ');
+        dst.extend_from_slice(&path);
+        dst.extend_from_slice(b" HTTP/1.1\r\n");
+
+        if !request.headers().contains_key(header::HOST) {
+            if let Some(authority) = parts.authority {
+                put_header(&mut dst, b"Host", &Bytes::from(authority));
+            }
+        }
+
+        for (name, value) in request.headers() {
+            // The upgrade headers are set below
+            match name.as_str() {
+                "connection" | "upgrade" | "http2-settings" => continue,
+                _ => {}
+            }
+
+            put_header(&mut dst, name.as_str().as_bytes(), value.as_bytes());
+        }
+
+        let settings = settings.encode_http2_settings();
+
+        put_header(&mut dst, b"Connection", b"Upgrade, HTTP2-Settings");
+        put_header(&mut dst, b"Upgrade", b"h2c");
+        put_header(&mut dst, b"HTTP2-Settings", settings.as_bytes());
+        dst.extend_from_slice(b"\r\n");
+
+        dst
+    }
+}
+
 impl proto::Peer for Peer {
     type Send = Request<()>;
     type Poll = Response<()>;
@@ -268,11 +1019,14 @@ impl proto::Peer for Peer {
     {
         use http::request::Parts;
 
-        let (Parts { method, uri, headers, .. }, _) = request.into_parts();
+        let (Parts { method, uri, headers, mut extensions, .. }, _) = request.into_parts();
+
+        // Extended CONNECT requests carry the protocol of the tunnel
+        let protocol = extensions.remove::<Protocol>();
 
-        // Build the set pseudo header set. All requests will include `method`
-        // and `path`.
-        let pseudo = Pseudo::request(method, uri);
+        // Build the set pseudo header set. All requests other than CONNECT
+        // will include `method` and `path`.
+        let pseudo = Pseudo::request_with_protocol(method, uri, protocol);
 
         // Create the HEADERS frame
         let mut frame = Headers::new(id, pseudo, headers);
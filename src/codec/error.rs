@@ -37,6 +37,25 @@ pub enum UserError {
 
     /// The application attempted to initiate too many streams to remote.
     Rejected,
+
+    /// The remote sent GOAWAY and no new streams may be initiated.
+    GoingAway,
+
+    /// The remote disabled server push with SETTINGS_ENABLE_PUSH.
+    PushDisabled,
+
+    /// The promised request of a server push must be safe and cacheable.
+    UnsafePushRequest,
+
+    /// The remote did not enable extended CONNECT with
+    /// SETTINGS_ENABLE_CONNECT_PROTOCOL.
+    ExtendedConnectDisabled,
+
+    /// A CONNECT request must include an authority.
+    MissingConnectAuthority,
+
+    /// The `:protocol` pseudo-header is only valid on CONNECT requests.
+    ProtocolWithoutConnect,
 }
 
 // ===== impl RecvError =====
@@ -109,6 +128,12 @@ impl error::Error for UserError {
             UnexpectedFrameType => "unexpected frame type",
             PayloadTooBig => "payload too big",
             Rejected => "rejected",
+            GoingAway => "connection going away",
+            PushDisabled => "server push disabled by remote",
+            UnsafePushRequest => "pushed request must be safe and cacheable",
+            ExtendedConnectDisabled => "extended CONNECT disabled by remote",
+            MissingConnectAuthority => "CONNECT request missing authority",
+            ProtocolWithoutConnect => "protocol requires a CONNECT request",
         }
     }
 }
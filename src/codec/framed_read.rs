@@ -9,9 +9,14 @@ use futures::*;
 
 use bytes::BytesMut;
 
+use std::usize;
+
 use tokio_io::AsyncRead;
 use tokio_io::codec::length_delimited;
 
+/// Bound on the buffered header block when no max header list size is set.
+const DEFAULT_MAX_HEADER_BLOCK_SIZE: usize = 16 << 20;
+
 #[derive(Debug)]
 pub struct FramedRead<T> {
     inner: length_delimited::FramedRead<T>,
@@ -19,6 +24,9 @@ pub struct FramedRead<T> {
     // hpack decoder state
     hpack: hpack::Decoder,
 
+    // Max size of the header lists accepted from the peer
+    max_header_list_size: usize,
+
     partial: Option<Partial>,
 }
 
@@ -37,7 +45,7 @@ enum Continuable {
     Headers(frame::Headers),
     // Decode the Continuation frame but ignore it...
     // Ignore(StreamId),
-    // PushPromise(frame::PushPromise),
+    PushPromise(frame::PushPromise),
 }
 
 impl<T> FramedRead<T> {
@@ -45,6 +53,7 @@ impl<T> FramedRead<T> {
         FramedRead {
             inner: inner,
             hpack: hpack::Decoder::new(DEFAULT_SETTINGS_HEADER_TABLE_SIZE),
+            max_header_list_size: usize::MAX,
             partial: None,
         }
     }
@@ -110,7 +119,7 @@ impl<T> FramedRead<T> {
 
                 if headers.is_end_headers() {
                     // Load the HPACK encoded headers & return the frame
-                    match headers.load_hpack(payload, &mut self.hpack) {
+                    match headers.load_hpack(payload, self.max_header_list_size, &mut self.hpack) {
                         Ok(_) => {}
                         Err(frame::Error::MalformedMessage) => {
                             return Err(Stream {
@@ -141,8 +150,37 @@ impl<T> FramedRead<T> {
                 res.map_err(|_| Connection(ProtocolError))?.into()
             }
             Kind::PushPromise => {
-                let res = frame::PushPromise::load(head, &bytes[frame::HEADER_LEN..]);
-                res.map_err(|_| Connection(ProtocolError))?.into()
+                // Drop the frame header
+                let _ = bytes.split_to(frame::HEADER_LEN);
+
+                let (mut push_promise, payload) =
+                    frame::PushPromise::load(head, bytes)
+                        .map_err(|_| Connection(ProtocolError))?;
+
+                if push_promise.is_end_headers() {
+                    // The header block must always be decoded to keep the HPACK
+                    // decoder state in sync, even if the push is refused.
+                    match push_promise.load_hpack(payload, self.max_header_list_size, &mut self.hpack) {
+                        Ok(_) => {}
+                        Err(frame::Error::MalformedMessage) => {
+                            return Err(Stream {
+                                id: push_promise.promised_id(),
+                                reason: ProtocolError,
+                            });
+                        }
+                        Err(_) => return Err(Connection(ProtocolError)),
+                    }
+
+                    push_promise.into()
+                } else {
+                    // Defer loading the frame
+                    self.partial = Some(Partial {
+                        frame: Continuable::PushPromise(push_promise),
+                        buf: payload,
+                    });
+
+                    return Ok(None);
+                }
             }
             Kind::Priority => {
                 if head.stream_id() == 0 {
@@ -164,6 +202,15 @@ impl<T> FramedRead<T> {
                     Err(_) => return Err(Connection(ProtocolError)),
                 }
             }
+            Kind::PriorityUpdate => {
+                match frame::PriorityUpdate::load(head, &bytes[frame::HEADER_LEN..]) {
+                    Ok(frame) => frame.into(),
+                    Err(frame::Error::InvalidPayloadLength) => {
+                        return Err(Connection(FrameSizeError));
+                    }
+                    Err(_) => return Err(Connection(ProtocolError)),
+                }
+            }
             Kind::Continuation => {
                 // TODO: Un-hack this
                 let end_of_headers = (head.flag() & 0x4) == 0x4;
@@ -176,6 +223,14 @@ impl<T> FramedRead<T> {
                 // Extend the buf
                 partial.buf.extend_from_slice(&bytes[frame::HEADER_LEN..]);
 
+                // Header blocks are only decoded once complete, so the buffer
+                // must be bounded or a peer could send CONTINUATION frames
+                // until memory runs out.
+                if partial.buf.len() > self.max_header_block_size() {
+                    debug!("header block too large; len={}", partial.buf.len());
+                    return Err(Connection(EnhanceYourCalm));
+                }
+
                 if !end_of_headers {
                     self.partial = Some(partial);
                     return Ok(None);
@@ -188,7 +243,7 @@ impl<T> FramedRead<T> {
                             return Err(Connection(ProtocolError));
                         }
 
-                        match frame.load_hpack(partial.buf, &mut self.hpack) {
+                        match frame.load_hpack(partial.buf, self.max_header_list_size, &mut self.hpack) {
                             Ok(_) => {}
                             Err(frame::Error::MalformedMessage) => {
                                 return Err(Stream {
@@ -199,6 +254,25 @@ impl<T> FramedRead<T> {
                             Err(_) => return Err(Connection(ProtocolError)),
                         }
 
+                        frame.into()
+                    }
+                    Continuable::PushPromise(mut frame) => {
+                        // The stream identifiers must match
+                        if frame.stream_id() != head.stream_id() {
+                            return Err(Connection(ProtocolError));
+                        }
+
+                        match frame.load_hpack(partial.buf, self.max_header_list_size, &mut self.hpack) {
+                            Ok(_) => {}
+                            Err(frame::Error::MalformedMessage) => {
+                                return Err(Stream {
+                                    id: frame.promised_id(),
+                                    reason: ProtocolError,
+                                });
+                            }
+                            Err(_) => return Err(Connection(ProtocolError)),
+                        }
+
                         frame.into()
                     }
                 }
@@ -212,6 +286,33 @@ impl<T> FramedRead<T> {
         Ok(Some(frame))
     }
 
+    /// Updates the max header table size that the peer's HPACK encoder is
+    /// allowed to use.
+    pub fn set_header_table_size(&mut self, val: usize) {
+        self.hpack.queue_size_update(val);
+    }
+
+    /// Updates the max size of header lists accepted from the peer. Larger
+    /// header lists result in a stream error.
+    pub fn set_max_header_list_size(&mut self, val: usize) {
+        self.max_header_list_size = val;
+    }
+
+    /// Returns the max size of a header block buffered across CONTINUATION
+    /// frames.
+    ///
+    /// An encoder never needs more bytes than the decoded header list size,
+    /// which counts 32 bytes of overhead per field. Twice that still lets a
+    /// header list slightly over the limit be decoded and refused with a
+    /// stream error.
+    fn max_header_block_size(&self) -> usize {
+        if self.max_header_list_size == usize::MAX {
+            DEFAULT_MAX_HEADER_BLOCK_SIZE
+        } else {
+            self.max_header_list_size.saturating_mul(2)
+        }
+    }
+
     pub fn get_ref(&self) -> &T {
         self.inner.get_ref()
     }
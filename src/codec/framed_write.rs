@@ -2,6 +2,7 @@ use codec::UserError;
 use codec::UserError::*;
 use frame::{self, Frame, FrameSize};
 use hpack;
+use padding::Padding;
 
 use futures::*;
 use tokio_io::{AsyncRead, AsyncWrite};
@@ -30,6 +31,9 @@ pub struct FramedWrite<T, B> {
 
     /// Max frame size, this is specified by the peer
     max_frame_size: FrameSize,
+
+    /// Padding added to HEADERS frames
+    padding: Option<Padding>,
 }
 
 #[derive(Debug)]
@@ -62,6 +66,7 @@ impl<T, B> FramedWrite<T, B>
             next: None,
             last_data_frame: None,
             max_frame_size: frame::DEFAULT_MAX_FRAME_SIZE,
+            padding: None,
         }
     }
 
@@ -97,19 +102,20 @@ impl<T, B> FramedWrite<T, B>
                 // Ensure that the payload is not greater than the max frame.
                 let len = v.payload().remaining();
 
-                if len > self.max_frame_size() {
+                if v.padded_len(len) > self.max_frame_size() {
                     return Err(PayloadTooBig);
                 }
 
                 if len >= CHAIN_THRESHOLD {
-                    let head = v.head();
-
                     // Encode the frame head to the buffer
-                    head.encode(len, self.buf.get_mut());
+                    v.encode_head(len, self.buf.get_mut());
 
                     // Save the data frame
                     self.next = Some(Next::Data(v));
                 } else {
+                    // The padding may not fit in the minimum buffer capacity
+                    self.buf.get_mut().reserve(frame::HEADER_LEN + v.padded_len(len));
+
                     v.encode_chunk(self.buf.get_mut());
 
                     // The chunk has been fully encoded, so there is no need to
@@ -121,19 +127,29 @@ impl<T, B> FramedWrite<T, B>
                 }
             }
             Frame::Headers(v) => {
-                if let Some(continuation) = v.encode(&mut self.hpack, self.buf.get_mut()) {
+                let max_len = self.max_frame_size();
+                let padding = self.padding.as_ref();
+
+                if let Some(continuation) = v.encode(&mut self.hpack, self.buf.get_mut(), max_len, padding) {
                     self.next = Some(Next::Continuation(continuation));
                 }
             }
             Frame::PushPromise(v) => {
-                debug!("unimplemented PUSH_PROMISE write; frame={:?}", v);
-                unimplemented!();
+                let max_len = self.max_frame_size();
+
+                if let Some(continuation) = v.encode(&mut self.hpack, self.buf.get_mut(), max_len) {
+                    self.next = Some(Next::Continuation(continuation));
+                }
             }
             Frame::Settings(v) => {
                 v.encode(self.buf.get_mut());
                 trace!("encoded settings; rem={:?}", self.buf.remaining());
             }
             Frame::GoAway(v) => {
+                // The debug data may not fit in the minimum buffer capacity
+                let len = frame::HEADER_LEN + 8 + v.debug_data().len();
+                self.buf.get_mut().reserve(len);
+
                 v.encode(self.buf.get_mut());
                 trace!("encoded go_away; rem={:?}", self.buf.remaining());
             }
@@ -146,12 +162,13 @@ impl<T, B> FramedWrite<T, B>
                 trace!("encoded window_update; rem={:?}", self.buf.remaining());
             }
 
-            Frame::Priority(_) => {
-                /*
+            Frame::Priority(v) => {
                 v.encode(self.buf.get_mut());
                 trace!("encoded priority; rem={:?}", self.buf.remaining());
-                */
-                unimplemented!();
+            }
+            Frame::PriorityUpdate(v) => {
+                v.encode(self.buf.get_mut());
+                trace!("encoded priority_update; rem={:?}", self.buf.remaining());
             }
             Frame::Reset(v) => {
                 v.encode(self.buf.get_mut());
@@ -166,27 +183,54 @@ impl<T, B> FramedWrite<T, B>
     pub fn flush(&mut self) -> Poll<(), io::Error> {
         trace!("flush");
 
-        while !self.is_empty() {
-            match self.next {
-                Some(Next::Data(ref mut frame)) => {
-                    let mut buf = Buf::by_ref(&mut self.buf).chain(frame.payload_mut());
-                    try_ready!(self.inner.write_buf(&mut buf));
-                }
-                _ => {
-                    try_ready!(self.inner.write_buf(&mut self.buf));
+        loop {
+            while !self.is_empty() {
+                match self.next {
+                    Some(Next::Data(ref mut frame)) => {
+                        let mut buf = Buf::by_ref(&mut self.buf).chain(frame.payload_mut());
+                        try_ready!(self.inner.write_buf(&mut buf));
+                    }
+                    _ => {
+                        try_ready!(self.inner.write_buf(&mut self.buf));
+                    }
                 }
             }
-        }
 
-        // The data frame has been written, so unset it
-        match self.next.take() {
-            Some(Next::Data(frame)) => {
-                self.last_data_frame = Some(frame);
-            }
-            Some(Next::Continuation(_)) => {
-                unimplemented!();
+            match self.next.take() {
+                // The data frame has been written, so unset it
+                Some(Next::Data(frame)) => {
+                    if frame.pad_len().is_some() {
+                        // Write the padding that follows the data
+                        self.buf.set_position(0);
+                        self.buf.get_mut().clear();
+
+                        frame.encode_padding(self.buf.get_mut());
+
+                        self.last_data_frame = Some(frame);
+                        continue;
+                    }
+
+                    self.last_data_frame = Some(frame);
+                }
+                // The header block is not complete. Encode the next
+                // CONTINUATION frame. No other frames may be buffered until
+                // the entire block has been written.
+                Some(Next::Continuation(frame)) => {
+                    self.buf.set_position(0);
+                    self.buf.get_mut().clear();
+
+                    let max_len = self.max_frame_size();
+
+                    if let Some(continuation) = frame.encode(self.buf.get_mut(), max_len) {
+                        self.next = Some(Next::Continuation(continuation));
+                    }
+
+                    continue;
+                }
+                None => {}
             }
-            None => {}
+
+            break;
         }
 
         trace!("flushing buffer");
@@ -230,6 +274,11 @@ impl<T, B> FramedWrite<T, B> {
         self.max_frame_size = val as FrameSize;
     }
 
+    /// Set the padding added to HEADERS frames.
+    pub fn set_padding(&mut self, padding: Option<Padding>) {
+        self.padding = padding;
+    }
+
     /// Retrieve the last data frame that has been sent
     pub fn take_last_data_frame(&mut self) -> Option<frame::Data<B>> {
         self.last_data_frame.take()
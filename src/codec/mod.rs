@@ -8,6 +8,7 @@ use self::framed_read::FramedRead;
 use self::framed_write::FramedWrite;
 
 use frame::{self, Frame, Data};
+use padding::Padding;
 
 use futures::*;
 
@@ -28,6 +29,12 @@ impl<T, B> Codec<T, B>
           B: Buf,
 {
     pub fn new(io: T) -> Self {
+        Self::with_max_recv_frame_size(io, frame::DEFAULT_MAX_FRAME_SIZE as usize)
+    }
+
+    /// Returns a new `Codec` that accepts frames of up to `max_frame_size`
+    /// bytes from the peer.
+    pub fn with_max_recv_frame_size(io: T, max_frame_size: usize) -> Self {
         // Wrap with writer
         let framed_write = FramedWrite::new(io);
 
@@ -37,9 +44,8 @@ impl<T, B> Codec<T, B>
             .length_field_length(3)
             .length_adjustment(9)
             .num_skip(0) // Don't skip the header
-            // TODO: make this configurable and allow it to be changed during
-            // runtime.
-            .max_frame_length(frame::DEFAULT_MAX_FRAME_SIZE as usize)
+            // TODO: allow this to be changed during runtime.
+            .max_frame_length(max_frame_size)
             .new_read(framed_write);
 
         let inner = FramedRead::new(delimited);
@@ -59,6 +65,21 @@ impl<T, B> Codec<T, B> {
         self.framed_write().set_max_frame_size(val)
     }
 
+    /// Set the padding added to HEADERS frames.
+    pub fn set_padding(&mut self, padding: Option<Padding>) {
+        self.framed_write().set_padding(padding)
+    }
+
+    /// Set the max header table size that the peer's encoder may use.
+    pub fn set_recv_header_table_size(&mut self, val: usize) {
+        self.inner.set_header_table_size(val)
+    }
+
+    /// Set the max size of header lists accepted from the peer.
+    pub fn set_max_recv_header_list_size(&mut self, val: usize) {
+        self.inner.set_max_header_list_size(val)
+    }
+
     /// Get a reference to the inner stream.
     #[cfg(feature = "unstable")]
     pub fn get_ref(&self) -> &T {
@@ -1,6 +1,8 @@
 use codec::{SendError, UserError};
 use proto;
 
+use bytes::Bytes;
+
 use std::{error, fmt, io};
 
 pub use frame::Reason;
@@ -19,16 +21,77 @@ enum Kind {
     /// action taken by the peer (i.e. a protocol error).
     Proto(Reason),
 
+    /// The remote peer closed the connection by sending a GOAWAY frame with
+    /// an error code, along with opaque debug data.
+    GoAway(Bytes, Reason),
+
+    /// The stream was not processed by the remote before it sent a GOAWAY
+    /// frame, carrying the frame's error code and debug data. The request may
+    /// be safely retried.
+    Refused(Bytes, Reason),
+
     /// An error resulting from an invalid action taken by the user of this
     /// library.
     User(UserError),
 
     /// An `io::Error` occurred while trying to read or write.
     Io(io::Error),
+
+    /// A keep-alive PING was not acknowledged before the keep-alive timeout
+    /// elapsed.
+    KeepAliveTimedOut,
 }
 
 // ===== impl Error =====
 
+impl Error {
+    /// Returns the reason, if the error was caused by the remote peer.
+    ///
+    /// A reason of `RefusedStream` indicates that the remote did not process
+    /// the stream, and the request may be safely retried.
+    pub fn reason(&self) -> Option<Reason> {
+        match self.kind {
+            Kind::Proto(reason) | Kind::GoAway(_, reason) => Some(reason),
+            Kind::Refused(..) => Some(Reason::RefusedStream),
+            _ => None,
+        }
+    }
+
+    /// Returns the error code of the GOAWAY frame, if the error was caused by
+    /// the remote peer sending one.
+    ///
+    /// For a stream that was refused by the GOAWAY frame, this differs from
+    /// `reason`, which is `RefusedStream`.
+    pub fn go_away_reason(&self) -> Option<Reason> {
+        match self.kind {
+            Kind::GoAway(_, reason) | Kind::Refused(_, reason) => Some(reason),
+            _ => None,
+        }
+    }
+
+    /// Returns the opaque debug data, if the error was caused by the remote
+    /// peer sending a GOAWAY frame.
+    ///
+    /// The debug data is intended for diagnostic purposes only and carries no
+    /// semantic value. It is empty if the peer did not send any.
+    pub fn go_away_debug_data(&self) -> Option<&Bytes> {
+        match self.kind {
+            Kind::GoAway(ref debug_data, _) |
+            Kind::Refused(ref debug_data, _) => Some(debug_data),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the connection failed because a keep-alive PING was
+    /// not acknowledged in time.
+    pub fn is_keep_alive_timeout(&self) -> bool {
+        match self.kind {
+            Kind::KeepAliveTimedOut => true,
+            _ => false,
+        }
+    }
+}
+
 impl From<proto::Error> for Error {
     fn from(src: proto::Error) -> Error {
         use proto::Error::*;
@@ -36,7 +99,9 @@ impl From<proto::Error> for Error {
         Error {
             kind: match src {
                 Proto(reason) => Kind::Proto(reason),
-                Io(e) => Kind::Io(e),
+                GoAway(debug_data, reason) => Kind::GoAway(debug_data, reason),
+                Refused(debug_data, reason) => Kind::Refused(debug_data, reason),
+                Io(e) => Kind::from(e),
             },
         }
     }
@@ -44,7 +109,7 @@ impl From<proto::Error> for Error {
 
 impl From<io::Error> for Error {
     fn from(src: io::Error) -> Error {
-        Error { kind: Kind::Io(src) }
+        Error { kind: Kind::from(src) }
     }
 }
 
@@ -69,14 +134,39 @@ impl From<UserError> for Error {
     }
 }
 
+impl From<Error> for io::Error {
+    fn from(src: Error) -> io::Error {
+        match src.kind {
+            Kind::Io(e) => e,
+            Kind::KeepAliveTimedOut => {
+                io::Error::new(io::ErrorKind::TimedOut, Error { kind: Kind::KeepAliveTimedOut })
+            }
+            kind => io::Error::new(io::ErrorKind::Other, Error { kind }),
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         use self::Kind::*;
 
         match self.kind {
             Proto(ref reason) => write!(fmt, "protocol error: {}", reason),
+            GoAway(ref debug_data, ref reason) if debug_data.is_empty() => {
+                write!(fmt, "protocol error: {}", reason)
+            }
+            GoAway(ref debug_data, ref reason) => {
+                write!(fmt, "protocol error: {}; debug_data={:?}", reason, debug_data)
+            }
+            Refused(ref debug_data, ref reason) if debug_data.is_empty() => {
+                write!(fmt, "stream refused by GOAWAY: {}", reason)
+            }
+            Refused(ref debug_data, ref reason) => {
+                write!(fmt, "stream refused by GOAWAY: {}; debug_data={:?}", reason, debug_data)
+            }
             User(ref e) => write!(fmt, "user error: {}", e),
             Io(ref e) => fmt::Display::fmt(e, fmt),
+            KeepAliveTimedOut => fmt.write_str("keep-alive timed out"),
         }
     }
 }
@@ -87,8 +177,22 @@ impl error::Error for Error {
 
         match self.kind {
             Io(ref e) => error::Error::description(e),
-            Proto(ref reason) => reason.description(),
+            Proto(ref reason) | GoAway(_, ref reason) => reason.description(),
+            Refused(..) => Reason::RefusedStream.description(),
             User(ref user) => user.description(),
+            KeepAliveTimedOut => "keep-alive timed out",
+        }
+    }
+}
+
+// ===== impl Kind =====
+
+impl From<io::Error> for Kind {
+    fn from(src: io::Error) -> Kind {
+        if proto::KeepAliveTimedOut::is(&src) {
+            Kind::KeepAliveTimedOut
+        } else {
+            Kind::Io(src)
         }
     }
 }
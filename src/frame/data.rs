@@ -63,6 +63,32 @@ impl<T> Data<T> {
         }
     }
 
+    /// Returns the length of the frame's padding, `None` if it is not padded.
+    pub fn pad_len(&self) -> Option<u8> {
+        self.pad_len
+    }
+
+    /// Sets the length of the padding sent with the data, removing the padding
+    /// if `None`.
+    pub fn set_pad_len(&mut self, pad_len: Option<u8>) {
+        if pad_len.is_some() {
+            self.flags.set_padded();
+        } else {
+            self.flags.unset_padded();
+        }
+
+        self.pad_len = pad_len;
+    }
+
+    /// Returns the length of a payload carrying `len` bytes of data, including
+    /// the padding and its length field.
+    pub(crate) fn padded_len(&self, len: usize) -> usize {
+        match self.pad_len {
+            Some(pad_len) => 1 + len + pad_len as usize,
+            None => len,
+        }
+    }
+
     /// Returns a reference to this frame's payload.
     ///
     /// This does **not** include any padding that might have been originally
@@ -132,12 +158,30 @@ impl<T: Buf> Data<T> {
     pub(crate) fn encode_chunk<U: BufMut>(&mut self, dst: &mut U) {
         let len = self.data.remaining() as usize;
 
-        if len > dst.remaining_mut() {
+        if self.padded_len(len) > dst.remaining_mut() {
             unimplemented!();
         }
 
-        self.head().encode(len, dst);
+        self.encode_head(len, dst);
         dst.put(&mut self.data);
+        self.encode_padding(dst);
+    }
+
+    /// Encodes the frame head for `len` bytes of data, followed by the pad
+    /// length if the frame is padded.
+    pub(crate) fn encode_head<U: BufMut>(&self, len: usize, dst: &mut U) {
+        self.head().encode(self.padded_len(len), dst);
+
+        if let Some(pad_len) = self.pad_len {
+            dst.put_u8(pad_len);
+        }
+    }
+
+    /// Encodes the padding that follows the data.
+    pub(crate) fn encode_padding<U: BufMut>(&self, dst: &mut U) {
+        if let Some(pad_len) = self.pad_len {
+            util::put_padding(dst, pad_len);
+        }
     }
 }
 
@@ -180,6 +224,14 @@ impl DataFlags {
     fn is_padded(&self) -> bool {
         self.0 & PADDED == PADDED
     }
+
+    fn set_padded(&mut self) {
+        self.0 |= PADDED
+    }
+
+    fn unset_padded(&mut self) {
+        self.0 &= !PADDED
+    }
 }
 
 impl Default for DataFlags {
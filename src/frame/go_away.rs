@@ -1,11 +1,12 @@
 use frame::{self, Head, Error, Kind, StreamId, Reason};
 
-use bytes::{BufMut, BigEndian};
+use bytes::{BufMut, BigEndian, Bytes};
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct GoAway {
     last_stream_id: StreamId,
     error_code: u32,
+    debug_data: Bytes,
 }
 
 impl GoAway {
@@ -13,13 +14,27 @@ impl GoAway {
         GoAway {
             last_stream_id,
             error_code: reason.into(),
+            debug_data: Bytes::new(),
         }
     }
 
+    /// Set the opaque debug data sent along with the frame.
+    pub fn set_debug_data(&mut self, debug_data: Bytes) {
+        self.debug_data = debug_data;
+    }
+
+    pub fn last_stream_id(&self) -> StreamId {
+        self.last_stream_id
+    }
+
     pub fn reason(&self) -> Reason {
         self.error_code.into()
     }
 
+    pub fn debug_data(&self) -> &Bytes {
+        &self.debug_data
+    }
+
     pub fn load(payload: &[u8]) -> Result<GoAway, Error> {
         if payload.len() < 8 {
             // Invalid payload len
@@ -29,19 +44,22 @@ impl GoAway {
 
         let (last_stream_id, _) = StreamId::parse(&payload[..4]);
         let error_code = unpack_octets_4!(payload, 4, u32);
+        let debug_data = Bytes::from(&payload[8..]);
 
         Ok(GoAway {
             last_stream_id: last_stream_id,
             error_code: error_code,
+            debug_data: debug_data,
         })
     }
 
     pub fn encode<B: BufMut>(&self, dst: &mut B) {
         trace!("encoding GO_AWAY; code={}", self.error_code);
         let head = Head::new(Kind::GoAway, 0, StreamId::zero());
-        head.encode(8, dst);
+        head.encode(8 + self.debug_data.len(), dst);
         dst.put_u32::<BigEndian>(self.last_stream_id.into());
         dst.put_u32::<BigEndian>(self.error_code);
+        dst.put_slice(&self.debug_data);
     }
 }
 
@@ -1,12 +1,14 @@
-use super::{StreamId, StreamDependency};
+use super::{StreamId, StreamDependency, PriorityParams};
 use hpack;
-use frame::{self, Frame, Head, Kind, Error};
+use frame::{self, util, Frame, Head, Kind, Error};
+use padding::{Padding, PaddedFrame};
+use protocol::Protocol;
 
 use http::{uri, Method, StatusCode, Uri, HeaderMap};
 use http::header::{self, HeaderName, HeaderValue};
 
-use bytes::{BytesMut, Bytes};
-use byteorder::{BigEndian, ByteOrder};
+use bytes::{BufMut, BytesMut, Bytes};
+use byteorder::BigEndian;
 use string::String;
 
 use std::fmt;
@@ -37,7 +39,7 @@ pub struct Headers {
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub struct HeadersFlag(u8);
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Eq, PartialEq)]
 pub struct PushPromise {
     /// The ID of the stream with which this frame is associated.
     stream_id: StreamId,
@@ -45,6 +47,12 @@ pub struct PushPromise {
     /// The ID of the stream being reserved by this PushPromise.
     promised_id: StreamId,
 
+    /// The header fields of the promised request
+    fields: HeaderMap,
+
+    /// Pseudo headers of the promised request
+    pseudo: Pseudo,
+
     /// The associated flags
     flags: PushPromiseFlag,
 }
@@ -57,11 +65,8 @@ pub struct Continuation {
     /// Stream ID of continuation frame
     stream_id: StreamId,
 
-    /// Argument to pass to the HPACK encoder to resume encoding
-    hpack: hpack::EncodeState,
-
-    /// remaining headers to encode
-    headers: Iter,
+    /// Remaining portion of the HPACK encoded header block
+    header_block: Bytes,
 }
 
 #[derive(Debug, Default, Eq, PartialEq)]
@@ -71,6 +76,7 @@ pub struct Pseudo {
     pub scheme: Option<String<Bytes>>,
     pub authority: Option<String<Bytes>>,
     pub path: Option<String<Bytes>>,
+    pub protocol: Option<String<Bytes>>,
 
     // Response
     pub status: Option<StatusCode>,
@@ -94,6 +100,9 @@ const ALL: u8 = END_STREAM
               | PADDED
               | PRIORITY;
 
+/// Initial capacity of the buffer a header block is HPACK encoded into
+const HPACK_BUFFER_CAPACITY: usize = 1_024;
+
 // ===== impl Headers =====
 
 impl Headers {
@@ -179,77 +188,26 @@ impl Headers {
 
     pub fn load_hpack(&mut self,
                       src: BytesMut,
+                      max_header_list_size: usize,
                       decoder: &mut hpack::Decoder)
         -> Result<(), Error>
     {
-        let mut reg = false;
-        let mut malformed = false;
-
-        macro_rules! set_pseudo {
-            ($field:ident, $val:expr) => {{
-                if reg {
-                    trace!("load_hpack; header malformed -- pseudo not at head of block");
-                    malformed = true;
-                } else if self.pseudo.$field.is_some() {
-                    trace!("load_hpack; header malformed -- repeated pseudo");
-                    malformed = true;
-                } else {
-                    self.pseudo.$field = Some($val);
-                }
-            }}
-        }
-
-        let mut src = Cursor::new(src.freeze());
-
-        // At this point, we're going to assume that the hpack encoded headers
-        // contain the entire payload. Later, we need to check for stream
-        // priority.
-        //
-        // TODO: Provide a way to abort decoding if an error is hit.
-        let res = decoder.decode(&mut src, |header| {
-            use hpack::Header::*;
-
-            match header {
-                Field { name, value } => {
-                    // Connection level header fields are not supported and must
-                    // result in a protocol error.
-
-                    if name == header::CONNECTION {
-                        trace!("load_hpack; connection level header");
-                        malformed = true;
-                    } else if name == header::TE && value != "trailers" {
-                        trace!("load_hpack; TE header not set to trailers; val={:?}", value);
-                        malformed = true;
-                    } else {
-                        reg = true;
-                        self.fields.append(name, value);
-                    }
-                }
-                Authority(v) => set_pseudo!(authority, v),
-                Method(v) => set_pseudo!(method, v),
-                Scheme(v) => set_pseudo!(scheme, v),
-                Path(v) => set_pseudo!(path, v),
-                Status(v) => set_pseudo!(status, v),
-            }
-        });
-
-        if let Err(e) = res {
-            trace!("hpack decoding error; err={:?}", e);
-            return Err(e.into());
-        }
-
-        if malformed {
-            trace!("malformed message");
-            return Err(Error::MalformedMessage.into());
-        }
-
-        Ok(())
+        load_hpack(&mut self.pseudo, &mut self.fields, src, max_header_list_size, decoder)
     }
 
     pub fn stream_id(&self) -> StreamId {
         self.stream_id
     }
 
+    pub fn stream_dep(&self) -> Option<&StreamDependency> {
+        self.stream_dep.as_ref()
+    }
+
+    pub fn set_stream_dep(&mut self, stream_dep: StreamDependency) {
+        self.stream_dep = Some(stream_dep);
+        self.flags.set_priority();
+    }
+
     pub fn is_end_headers(&self) -> bool {
         self.flags.is_end_headers()
     }
@@ -262,57 +220,107 @@ impl Headers {
         self.flags.set_end_stream()
     }
 
+    pub fn is_padded(&self) -> bool {
+        self.flags.is_padded()
+    }
+
     pub fn into_parts(self) -> (Pseudo, HeaderMap) {
         (self.pseudo, self.fields)
     }
 
+    pub fn pseudo(&self) -> &Pseudo {
+        &self.pseudo
+    }
+
     pub fn fields(&self) -> &HeaderMap {
         &self.fields
     }
 
+    /// Returns the extensible priority carried by the `priority` header, if
+    /// present.
+    pub fn priority(&self) -> Option<PriorityParams> {
+        let mut values = self.fields.get_all("priority").iter().peekable();
+
+        if values.peek().is_none() {
+            return None;
+        }
+
+        // Multiple field lines are combined into a single value
+        let value = values
+            .map(|value| value.as_bytes())
+            .collect::<Vec<_>>()
+            .join(&b","[..]);
+
+        Some(PriorityParams::parse(&value))
+    }
+
     pub fn into_fields(self) -> HeaderMap {
         self.fields
     }
 
-    pub fn encode(self, encoder: &mut hpack::Encoder, dst: &mut BytesMut)
+    /// Encodes the frame, returning a continuation if the header block does
+    /// not fit in a single frame of `max_len` bytes.
+    ///
+    /// The frame is padded as decided by `padding` for the size of the
+    /// encoded header block.
+    pub fn encode(self,
+                  encoder: &mut hpack::Encoder,
+                  dst: &mut BytesMut,
+                  max_len: usize,
+                  padding: Option<&Padding>)
         -> Option<Continuation>
     {
-        let head = self.head();
-        let pos = dst.len();
+        let mut flags = self.flags;
 
-        // At this point, we don't know how big the h2 frame will be.
-        // So, we write the head with length 0, then write the body, and
-        // finally write the length once we know the size.
-        head.encode(0, dst);
-
-        // Encode the frame
-        let mut headers = Iter {
+        // Encode the header block
+        let mut block = encode_hpack(Iter {
             pseudo: Some(self.pseudo),
             fields: self.fields.into_iter(),
-        };
+        }, encoder);
 
-        let ret = match encoder.encode(None, &mut headers, dst) {
-            hpack::Encode::Full => None,
-            hpack::Encode::Partial(state) => {
-                Some(Continuation {
-                    stream_id: self.stream_id,
-                    hpack: state,
-                    headers: headers,
-                })
-            }
-        };
+        let pad_len = padding.and_then(|padding| {
+            padding.pad_len(PaddedFrame::Headers, block.len())
+        });
 
-        // Compute the frame length
-        let len = (dst.len() - pos) - frame::HEADER_LEN;
+        // The pad length and the stream dependency precede the header block,
+        // the padding follows it
+        let pad_overhead = pad_len.map(|pad_len| 1 + pad_len as usize).unwrap_or(0);
+        let dep_len = if self.stream_dep.is_some() { 5 } else { 0 };
 
-        // Write the frame length
-        BigEndian::write_uint(&mut dst[pos..pos+3], len as u64, 3);
+        let ret = split_block(self.stream_id, &mut block, max_len - pad_overhead - dep_len);
 
-        ret
-    }
+        if ret.is_some() {
+            // The header block continues in CONTINUATION frames
+            flags.unset_end_headers();
+        }
+
+        if pad_len.is_some() {
+            flags.set_padded();
+        } else {
+            flags.unset_padded();
+        }
+
+        let head = Head::new(Kind::Headers, flags.into(), self.stream_id);
+        let len = pad_overhead + dep_len + block.len();
+
+        dst.reserve(frame::HEADER_LEN + len);
+        head.encode(len, dst);
 
-    fn head(&self) -> Head {
-        Head::new(Kind::Headers, self.flags.into(), self.stream_id)
+        if let Some(pad_len) = pad_len {
+            dst.put_u8(pad_len);
+        }
+
+        if let Some(ref stream_dep) = self.stream_dep {
+            stream_dep.encode(dst);
+        }
+
+        dst.put_slice(&block);
+
+        if let Some(pad_len) = pad_len {
+            util::put_padding(dst, pad_len);
+        }
+
+        ret
     }
 }
 
@@ -336,20 +344,81 @@ impl fmt::Debug for Headers {
 // ===== impl PushPromise =====
 
 impl PushPromise {
-    pub fn load(head: Head, payload: &[u8])
-        -> Result<Self, Error>
+    /// Create a new PUSH_PROMISE frame
+    pub fn new(stream_id: StreamId,
+               promised_id: StreamId,
+               pseudo: Pseudo,
+               fields: HeaderMap)
+        -> Self
+    {
+        PushPromise {
+            stream_id: stream_id,
+            promised_id: promised_id,
+            fields: fields,
+            pseudo: pseudo,
+            flags: PushPromiseFlag::default(),
+        }
+    }
+
+    /// Loads the push promise frame but doesn't actually do HPACK decoding.
+    ///
+    /// HPACK decoding is done in the `load_hpack` step.
+    pub fn load(head: Head, mut src: BytesMut)
+        -> Result<(Self, BytesMut), Error>
     {
         let flags = PushPromiseFlag(head.flag());
+        let mut pad = 0;
+
+        trace!("loading push promise; flags={:?}", flags);
+
+        // Read the padding length
+        if flags.is_padded() {
+            if src.is_empty() {
+                return Err(Error::InvalidPayloadLength);
+            }
+
+            pad = src[0] as usize;
+
+            // Drop the padding
+            let _ = src.split_to(1);
+        }
+
+        if src.len() < 4 {
+            return Err(Error::InvalidPayloadLength);
+        }
+
+        let (promised_id, _) = StreamId::parse(&src[..4]);
 
-        // TODO: Handle padding
+        // Drop the promised stream ID
+        let _ = src.split_to(4);
+
+        if pad > 0 {
+            if pad > src.len() {
+                return Err(Error::TooMuchPadding);
+            }
 
-        let (promised_id, _) = StreamId::parse(&payload[..4]);
+            let len = src.len() - pad;
+            src.truncate(len);
+        }
 
-        Ok(PushPromise {
+        let push_promise = PushPromise {
             stream_id: head.stream_id(),
             promised_id: promised_id,
+            fields: HeaderMap::new(),
+            pseudo: Pseudo::default(),
             flags: flags,
-        })
+        };
+
+        Ok((push_promise, src))
+    }
+
+    pub fn load_hpack(&mut self,
+                      src: BytesMut,
+                      max_header_list_size: usize,
+                      decoder: &mut hpack::Decoder)
+        -> Result<(), Error>
+    {
+        load_hpack(&mut self.pseudo, &mut self.fields, src, max_header_list_size, decoder)
     }
 
     pub fn stream_id(&self) -> StreamId {
@@ -359,6 +428,49 @@ impl PushPromise {
     pub fn promised_id(&self) -> StreamId {
         self.promised_id
     }
+
+    pub fn is_end_headers(&self) -> bool {
+        self.flags.is_end_headers()
+    }
+
+    pub fn into_parts(self) -> (Pseudo, HeaderMap) {
+        (self.pseudo, self.fields)
+    }
+
+    pub fn fields(&self) -> &HeaderMap {
+        &self.fields
+    }
+
+    /// Encodes the frame, returning a continuation if the header block does
+    /// not fit in a single frame of `max_len` bytes.
+    pub fn encode(self, encoder: &mut hpack::Encoder, dst: &mut BytesMut, max_len: usize)
+        -> Option<Continuation>
+    {
+        let mut flags = self.flags;
+
+        // Encode the promised request
+        let mut block = encode_hpack(Iter {
+            pseudo: Some(self.pseudo),
+            fields: self.fields.into_iter(),
+        }, encoder);
+
+        // The promised stream ID precedes the header block
+        let ret = split_block(self.stream_id, &mut block, max_len - 4);
+
+        if ret.is_some() {
+            // The header block continues in CONTINUATION frames
+            flags.unset_end_headers();
+        }
+
+        let head = Head::new(Kind::PushPromise, flags.into(), self.stream_id);
+
+        dst.reserve(frame::HEADER_LEN + 4 + block.len());
+        head.encode(4 + block.len(), dst);
+        dst.put_u32::<BigEndian>(self.promised_id.into());
+        dst.put_slice(&block);
+
+        ret
+    }
 }
 
 impl<T> From<PushPromise> for Frame<T> {
@@ -367,25 +479,75 @@ impl<T> From<PushPromise> for Frame<T> {
     }
 }
 
+impl fmt::Debug for PushPromise {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PushPromise")
+            .field("stream_id", &self.stream_id)
+            .field("promised_id", &self.promised_id)
+            .field("flags", &self.flags)
+            // `fields` and `pseudo` purposefully not included
+            .finish()
+    }
+}
+
+// ===== impl Continuation =====
+
+impl Continuation {
+    /// Encodes the next CONTINUATION frame of the header block, returning
+    /// another continuation if the rest of the block does not fit in `max_len`
+    /// bytes.
+    pub fn encode(self, dst: &mut BytesMut, max_len: usize) -> Option<Continuation> {
+        let mut block = self.header_block;
+        let ret = split_block(self.stream_id, &mut block, max_len);
+
+        let flags = if ret.is_some() { 0 } else { END_HEADERS };
+        let head = Head::new(Kind::Continuation, flags, self.stream_id);
+
+        dst.reserve(frame::HEADER_LEN + block.len());
+        head.encode(block.len(), dst);
+        dst.put_slice(&block);
+
+        ret
+    }
+}
+
 // ===== impl Pseudo =====
 
 impl Pseudo {
     pub fn request(method: Method, uri: Uri) -> Self {
+        Pseudo::request_with_protocol(method, uri, None)
+    }
+
+    /// Creates the pseudo headers of a request, which is an extended CONNECT
+    /// request (RFC 8441) if `protocol` is set.
+    ///
+    /// Other CONNECT requests only include `:method` and `:authority` (RFC
+    /// 7540 section 8.3).
+    pub fn request_with_protocol(method: Method, uri: Uri, protocol: Option<Protocol>) -> Self {
         let parts = uri::Parts::from(uri);
 
         fn to_string(src: Bytes) -> String<Bytes> {
             unsafe { String::from_utf8_unchecked(src) }
         }
 
-        let path = parts.path_and_query
-            .map(|v| v.into())
-            .unwrap_or_else(|| Bytes::from_static(b"/"));
+        let is_connect = method == Method::CONNECT && protocol.is_none();
+
+        let path = if is_connect {
+            None
+        } else {
+            let path = parts.path_and_query
+                .map(|v| v.into())
+                .unwrap_or_else(|| Bytes::from_static(b"/"));
+
+            Some(to_string(path))
+        };
 
         let mut pseudo = Pseudo {
             method: Some(method),
             scheme: None,
             authority: None,
-            path: Some(to_string(path)),
+            path: path,
+            protocol: protocol.map(Protocol::into_inner),
             status: None,
         };
 
@@ -393,7 +555,9 @@ impl Pseudo {
         //
         // TODO: Scheme must be set...
         if let Some(scheme) = parts.scheme {
-            pseudo.set_scheme(to_string(scheme.into()));
+            if !is_connect {
+                pseudo.set_scheme(to_string(scheme.into()));
+            }
         }
 
         // If the URI includes an authority component, add it to the pseudo
@@ -411,6 +575,7 @@ impl Pseudo {
             scheme: None,
             authority: None,
             path: None,
+            protocol: None,
             status: Some(status),
         }
     }
@@ -449,6 +614,10 @@ impl Iterator for Iter {
                 return Some(Path(path));
             }
 
+            if let Some(protocol) = pseudo.protocol.take() {
+                return Some(Protocol(protocol));
+            }
+
             if let Some(status) = pseudo.status.take() {
                 return Some(Status(status));
             }
@@ -486,13 +655,29 @@ impl HeadersFlag {
         self.0 & END_HEADERS == END_HEADERS
     }
 
+    pub fn unset_end_headers(&mut self) {
+        self.0 &= !END_HEADERS
+    }
+
     pub fn is_padded(&self) -> bool {
         self.0 & PADDED == PADDED
     }
 
+    pub fn set_padded(&mut self) {
+        self.0 |= PADDED
+    }
+
+    pub fn unset_padded(&mut self) {
+        self.0 &= !PADDED
+    }
+
     pub fn is_priority(&self) -> bool {
         self.0 & PRIORITY == PRIORITY
     }
+
+    pub fn set_priority(&mut self) {
+        self.0 |= PRIORITY
+    }
 }
 
 impl Default for HeadersFlag {
@@ -518,3 +703,161 @@ impl fmt::Debug for HeadersFlag {
             .finish()
     }
 }
+
+// ===== impl PushPromiseFlag =====
+
+impl PushPromiseFlag {
+    pub fn is_end_headers(&self) -> bool {
+        self.0 & END_HEADERS == END_HEADERS
+    }
+
+    pub fn unset_end_headers(&mut self) {
+        self.0 &= !END_HEADERS
+    }
+
+    pub fn is_padded(&self) -> bool {
+        self.0 & PADDED == PADDED
+    }
+}
+
+impl Default for PushPromiseFlag {
+    /// Returns a `PushPromiseFlag` value with `END_HEADERS` set.
+    fn default() -> Self {
+        PushPromiseFlag(END_HEADERS)
+    }
+}
+
+impl From<PushPromiseFlag> for u8 {
+    fn from(src: PushPromiseFlag) -> u8 {
+        src.0
+    }
+}
+
+// ===== util =====
+
+/// Decodes an HPACK encoded header block into the pseudo headers and fields.
+fn load_hpack(pseudo: &mut Pseudo,
+              fields: &mut HeaderMap,
+              src: BytesMut,
+              max_header_list_size: usize,
+              decoder: &mut hpack::Decoder)
+    -> Result<(), Error>
+{
+    let mut reg = false;
+    let mut malformed = false;
+
+    // Size of the decoded header list, as defined for
+    // SETTINGS_MAX_HEADER_LIST_SIZE.
+    let mut header_list_size = 0;
+
+    macro_rules! set_pseudo {
+        ($field:ident, $val:expr) => {{
+            if reg {
+                trace!("load_hpack; header malformed -- pseudo not at head of block");
+                malformed = true;
+            } else if pseudo.$field.is_some() {
+                trace!("load_hpack; header malformed -- repeated pseudo");
+                malformed = true;
+            } else {
+                pseudo.$field = Some($val);
+            }
+        }}
+    }
+
+    let mut src = Cursor::new(src.freeze());
+
+    // At this point, we're going to assume that the hpack encoded headers
+    // contain the entire payload. Later, we need to check for stream
+    // priority.
+    //
+    // TODO: Provide a way to abort decoding if an error is hit.
+    let res = decoder.decode(&mut src, |header| {
+        use hpack::Header::*;
+
+        header_list_size += header.len();
+
+        if header_list_size > max_header_list_size {
+            // The whole block must still be decoded to keep the HPACK decoder
+            // state in sync, but the fields are not stored.
+            if !malformed {
+                trace!("load_hpack; header list too large; max={}", max_header_list_size);
+            }
+
+            malformed = true;
+            return;
+        }
+
+        match header {
+            Field { name, value } => {
+                // Connection level header fields are not supported and must
+                // result in a protocol error.
+
+                if name == header::CONNECTION {
+                    trace!("load_hpack; connection level header");
+                    malformed = true;
+                } else if name == header::TE && value != "trailers" {
+                    trace!("load_hpack; TE header not set to trailers; val={:?}", value);
+                    malformed = true;
+                } else {
+                    reg = true;
+                    fields.append(name, value);
+                }
+            }
+            Authority(v) => set_pseudo!(authority, v),
+            Method(v) => set_pseudo!(method, v),
+            Scheme(v) => set_pseudo!(scheme, v),
+            Path(v) => set_pseudo!(path, v),
+            Protocol(v) => set_pseudo!(protocol, v),
+            Status(v) => set_pseudo!(status, v),
+        }
+    });
+
+    if let Err(e) = res {
+        trace!("hpack decoding error; err={:?}", e);
+        return Err(e.into());
+    }
+
+    if malformed {
+        trace!("malformed message");
+        return Err(Error::MalformedMessage.into());
+    }
+
+    Ok(())
+}
+
+/// HPACK encodes the entire header block.
+///
+/// The block is encoded up front so that it may be split across frames at any
+/// byte, even in the middle of a header field.
+fn encode_hpack(mut headers: Iter, encoder: &mut hpack::Encoder) -> Bytes {
+    let mut dst = BytesMut::with_capacity(HPACK_BUFFER_CAPACITY);
+    let mut resume = None;
+
+    loop {
+        match encoder.encode(resume.take(), &mut headers, &mut dst) {
+            hpack::Encode::Full => return dst.freeze(),
+            hpack::Encode::Partial(state) => {
+                // Out of buffer space, grow the buffer and resume encoding.
+                let additional = dst.capacity();
+                dst.reserve(additional);
+
+                resume = Some(state);
+            }
+        }
+    }
+}
+
+/// Splits off the portion of the header block that does not fit in a frame
+/// payload of `max_len` bytes.
+fn split_block(stream_id: StreamId, block: &mut Bytes, max_len: usize)
+    -> Option<Continuation>
+{
+    if block.len() <= max_len {
+        return None;
+    }
+
+    Some(Continuation {
+        stream_id: stream_id,
+        header_block: block.split_off(max_len),
+    })
+}
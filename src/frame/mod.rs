@@ -32,6 +32,7 @@ mod head;
 mod headers;
 mod ping;
 mod priority;
+mod priority_update;
 mod reason;
 mod reset;
 mod settings;
@@ -45,6 +46,7 @@ pub use self::head::{Head, Kind};
 pub use self::headers::{Headers, PushPromise, Continuation, Pseudo};
 pub use self::ping::Ping;
 pub use self::priority::{Priority, StreamDependency};
+pub use self::priority_update::{PriorityUpdate, PriorityParams, MAX_URGENCY};
 pub use self::reason::Reason;
 pub use self::reset::Reset;
 pub use self::settings::Settings;
@@ -68,6 +70,7 @@ pub enum Frame<T = Bytes> {
     Data(Data<T>),
     Headers(Headers),
     Priority(Priority),
+    PriorityUpdate(PriorityUpdate),
     PushPromise(PushPromise),
     Settings(Settings),
     Ping(Ping),
@@ -86,6 +89,7 @@ impl<T> Frame<T> {
             Data(frame) => frame.map(f).into(),
             Headers(frame) => frame.into(),
             Priority(frame) => frame.into(),
+            PriorityUpdate(frame) => frame.into(),
             PushPromise(frame) => frame.into(),
             Settings(frame) => frame.into(),
             Ping(frame) => frame.into(),
@@ -104,6 +108,7 @@ impl<T> fmt::Debug for Frame<T> {
             Data(ref frame) => write!(fmt, "Frame::Data({:?})", frame),
             Headers(ref frame) => write!(fmt, "Frame::Headers({:?})", frame),
             Priority(ref frame) => write!(fmt, "Frame::Priority({:?})", frame),
+            PriorityUpdate(ref frame) => write!(fmt, "Frame::PriorityUpdate({:?})", frame),
             PushPromise(ref frame) => write!(fmt, "Frame::PushPromise({:?})", frame),
             Settings(ref frame) => write!(fmt, "Frame::Settings({:?})", frame),
             Ping(ref frame) => write!(fmt, "Frame::Ping({:?})", frame),
@@ -152,6 +157,10 @@ pub enum Error {
     /// invalid stream identifier.
     InvalidDependencyId,
 
+    /// The `HTTP2-Settings` header of an h2c upgrade request is not valid
+    /// base64url.
+    InvalidHttp2Settings,
+
     /// Failed to perform HPACK decoding
     Hpack(hpack::DecoderError),
 }
@@ -19,6 +19,10 @@ impl Ping {
         }
     }
 
+    pub fn ping(payload: Payload) -> Ping {
+        Ping { ack: false, payload }
+    }
+
     pub fn pong(payload: Payload) -> Ping {
         Ping { ack: true, payload }
     }
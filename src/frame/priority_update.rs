@@ -0,0 +1,194 @@
+use frame::{Frame, Error, Head, Kind, StreamId};
+
+use bytes::{BufMut, BigEndian};
+
+use std::fmt;
+
+/// The PRIORITY_UPDATE frame, as described in RFC 9218 section 7.1.
+#[derive(Debug, Eq, PartialEq)]
+pub struct PriorityUpdate {
+    /// The stream being reprioritized
+    prioritized_id: StreamId,
+
+    /// The priority parsed from the frame's Priority Field Value
+    params: PriorityParams,
+}
+
+/// The urgency and incremental parameters of the extensible priority scheme,
+/// carried by the `priority` header field and PRIORITY_UPDATE frames.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PriorityParams {
+    /// The urgency, in the range [0, 7]. Lower values are more urgent.
+    urgency: u8,
+
+    /// True if the response can be processed incrementally
+    incremental: bool,
+}
+
+/// The default value of the urgency parameter
+pub const DEFAULT_URGENCY: u8 = 3;
+
+/// The least urgent value of the urgency parameter
+pub const MAX_URGENCY: u8 = 7;
+
+// ===== impl PriorityUpdate =====
+
+impl PriorityUpdate {
+    pub fn new(prioritized_id: StreamId, params: PriorityParams) -> Self {
+        PriorityUpdate {
+            prioritized_id: prioritized_id,
+            params: params,
+        }
+    }
+
+    pub fn load(head: Head, payload: &[u8]) -> Result<Self, Error> {
+        if !head.stream_id().is_zero() {
+            return Err(Error::InvalidStreamId);
+        }
+
+        if payload.len() < 4 {
+            return Err(Error::InvalidPayloadLength);
+        }
+
+        let (prioritized_id, _) = StreamId::parse(&payload[..4]);
+
+        if prioritized_id.is_zero() {
+            return Err(Error::InvalidStreamId);
+        }
+
+        Ok(PriorityUpdate {
+            prioritized_id: prioritized_id,
+            params: PriorityParams::parse(&payload[4..]),
+        })
+    }
+
+    pub fn prioritized_id(&self) -> StreamId {
+        self.prioritized_id
+    }
+
+    pub fn params(&self) -> PriorityParams {
+        self.params
+    }
+
+    pub fn encode<B: BufMut>(&self, dst: &mut B) {
+        trace!("encoding PRIORITY_UPDATE; id={:?}", self.prioritized_id);
+
+        let value = self.params.to_string();
+
+        let head = Head::new(Kind::PriorityUpdate, 0, StreamId::zero());
+        head.encode(4 + value.len(), dst);
+        dst.put_u32::<BigEndian>(self.prioritized_id.into());
+        dst.put_slice(value.as_bytes());
+    }
+}
+
+impl<B> From<PriorityUpdate> for Frame<B> {
+    fn from(src: PriorityUpdate) -> Self {
+        Frame::PriorityUpdate(src)
+    }
+}
+
+// ===== impl PriorityParams =====
+
+impl PriorityParams {
+    /// # Panics
+    ///
+    /// Panics if `urgency` is greater than 7.
+    pub fn new(urgency: u8, incremental: bool) -> Self {
+        assert!(urgency <= MAX_URGENCY, "invalid urgency");
+
+        PriorityParams {
+            urgency: urgency,
+            incremental: incremental,
+        }
+    }
+
+    /// Parses a Priority Field Value (RFC 9218 section 4).
+    ///
+    /// Unknown parameters and parameters with invalid values are ignored,
+    /// leaving the default value in place.
+    pub fn parse(src: &[u8]) -> Self {
+        let mut params = PriorityParams::default();
+
+        for member in src.split(|b| *b == b',') {
+            // Strip any parameters attached to the member
+            let member = member.split(|b| *b == b';').next().unwrap();
+            let member = trim(member);
+
+            let (key, value) = match member.iter().position(|b| *b == b'=') {
+                Some(pos) => (&member[..pos], Some(&member[pos + 1..])),
+                None => (member, None),
+            };
+
+            match key {
+                b"u" => {
+                    match value {
+                        Some(&[b]) if b >= b'0' && b <= b'0' + MAX_URGENCY => {
+                            params.urgency = b - b'0';
+                        }
+                        _ => {}
+                    }
+                }
+                b"i" => {
+                    match value {
+                        None | Some(b"?1") => params.incremental = true,
+                        Some(b"?0") => params.incremental = false,
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        params
+    }
+
+    pub fn urgency(&self) -> u8 {
+        self.urgency
+    }
+
+    pub fn is_incremental(&self) -> bool {
+        self.incremental
+    }
+}
+
+impl Default for PriorityParams {
+    fn default() -> Self {
+        PriorityParams {
+            urgency: DEFAULT_URGENCY,
+            incremental: false,
+        }
+    }
+}
+
+impl fmt::Display for PriorityParams {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "u={}", self.urgency)?;
+
+        if self.incremental {
+            write!(fmt, ", i")?;
+        }
+
+        Ok(())
+    }
+}
+
+fn trim(mut src: &[u8]) -> &[u8] {
+    while let Some((&b, rest)) = src.split_first() {
+        if b != b' ' && b != b'\t' {
+            break;
+        }
+
+        src = rest;
+    }
+
+    while let Some((&b, rest)) = src.split_last() {
+        if b != b' ' && b != b'\t' {
+            break;
+        }
+
+        src = rest;
+    }
+
+    src
+}
@@ -1,4 +1,4 @@
-use frame::{Frame, FrameSize, Error, Head, Kind, StreamId};
+use frame::{util, Frame, FrameSize, Error, Head, Kind, StreamId};
 use bytes::{BytesMut, BufMut, BigEndian};
 
 #[derive(Debug, Clone, Default, Eq, PartialEq)]
@@ -11,6 +11,8 @@ pub struct Settings {
     initial_window_size: Option<u32>,
     max_frame_size: Option<u32>,
     max_header_list_size: Option<u32>,
+    enable_connect_protocol: Option<u32>,
+    no_rfc7540_priorities: Option<u32>,
 }
 
 /// An enum that lists all valid settings that can be sent in a SETTINGS
@@ -25,6 +27,8 @@ pub enum Setting {
     InitialWindowSize(u32),
     MaxFrameSize(u32),
     MaxHeaderListSize(u32),
+    EnableConnectProtocol(u32),
+    NoRfc7540Priorities(u32),
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
@@ -63,14 +67,114 @@ impl Settings {
         self.initial_window_size
     }
 
+    /// # Panics
+    ///
+    /// Panics if `size` is larger than 2^31-1.
+    pub fn set_initial_window_size(&mut self, size: Option<u32>) {
+        if let Some(val) = size {
+            assert!(val as usize <= MAX_INITIAL_WINDOW_SIZE);
+        }
+
+        self.initial_window_size = size;
+    }
+
     pub fn max_concurrent_streams(&self) -> Option<u32> {
         self.max_concurrent_streams
     }
 
+    pub fn set_max_concurrent_streams(&mut self, max: Option<u32>) {
+        self.max_concurrent_streams = max;
+    }
+
     pub fn max_frame_size(&self) -> Option<u32> {
         self.max_frame_size
     }
 
+    /// # Panics
+    ///
+    /// Panics if `size` is not in the range [2^14, 2^24-1].
+    pub fn set_max_frame_size(&mut self, size: Option<u32>) {
+        if let Some(val) = size {
+            assert!(DEFAULT_MAX_FRAME_SIZE <= val && val as usize <= MAX_MAX_FRAME_SIZE);
+        }
+
+        self.max_frame_size = size;
+    }
+
+    pub fn header_table_size(&self) -> Option<u32> {
+        self.header_table_size
+    }
+
+    pub fn set_header_table_size(&mut self, size: Option<u32>) {
+        self.header_table_size = size;
+    }
+
+    pub fn max_header_list_size(&self) -> Option<u32> {
+        self.max_header_list_size
+    }
+
+    pub fn set_max_header_list_size(&mut self, size: Option<u32>) {
+        self.max_header_list_size = size;
+    }
+
+    pub fn enable_push(&self) -> Option<u32> {
+        self.enable_push
+    }
+
+    /// Returns true if the sender of the frame allows server push. This
+    /// defaults to `true` when the setting is omitted.
+    pub fn is_push_enabled(&self) -> bool {
+        self.enable_push.unwrap_or(1) != 0
+    }
+
+    pub fn set_enable_push(&mut self, enable: bool) {
+        self.enable_push = Some(enable as u32);
+    }
+
+    pub fn enable_connect_protocol(&self) -> Option<u32> {
+        self.enable_connect_protocol
+    }
+
+    /// Returns true if the sender of the frame accepts extended CONNECT
+    /// requests (RFC 8441). This defaults to `false` when the setting is
+    /// omitted.
+    pub fn is_extended_connect_protocol_enabled(&self) -> bool {
+        self.enable_connect_protocol.unwrap_or(0) != 0
+    }
+
+    pub fn set_enable_connect_protocol(&mut self, enable: bool) {
+        self.enable_connect_protocol = Some(enable as u32);
+    }
+
+    pub fn no_rfc7540_priorities(&self) -> Option<u32> {
+        self.no_rfc7540_priorities
+    }
+
+    pub fn set_no_rfc7540_priorities(&mut self, disabled: bool) {
+        self.no_rfc7540_priorities = Some(disabled as u32);
+    }
+
+    /// Loads the settings carried by the `HTTP2-Settings` header of an h2c
+    /// upgrade request, a base64url encoded SETTINGS frame payload.
+    pub fn load_http2_settings(value: &[u8]) -> Result<Settings, Error> {
+        let payload = match util::base64url_decode(value) {
+            Some(payload) => payload,
+            None => return Err(Error::InvalidHttp2Settings),
+        };
+
+        let head = Head::new(Kind::Settings, 0, StreamId::zero());
+        Settings::load(head, &payload)
+    }
+
+    /// Encodes the settings as the value of the `HTTP2-Settings` header of an
+    /// h2c upgrade request.
+    pub fn encode_http2_settings(&self) -> String {
+        let mut payload = BytesMut::with_capacity(self.payload_len());
+        self.for_each(|setting| setting.encode(&mut payload));
+
+        util::base64url_encode(&payload)
+    }
+
     pub fn load(head: Head, payload: &[u8]) -> Result<Settings, Error> {
         use self::Setting::*;
 
@@ -137,6 +241,26 @@ impl Settings {
                 Some(MaxHeaderListSize(val)) => {
                     settings.max_header_list_size = Some(val);
                 }
+                Some(EnableConnectProtocol(val)) => {
+                    match val {
+                        0 | 1 => {
+                            settings.enable_connect_protocol = Some(val);
+                        }
+                        _ => {
+                            return Err(Error::InvalidSettingValue);
+                        }
+                    }
+                }
+                Some(NoRfc7540Priorities(val)) => {
+                    match val {
+                        0 | 1 => {
+                            settings.no_rfc7540_priorities = Some(val);
+                        }
+                        _ => {
+                            return Err(Error::InvalidSettingValue);
+                        }
+                    }
+                }
                 None => {}
             }
         }
@@ -192,6 +316,14 @@ impl Settings {
         if let Some(v) = self.max_header_list_size {
             f(MaxHeaderListSize(v));
         }
+
+        if let Some(v) = self.enable_connect_protocol {
+            f(EnableConnectProtocol(v));
+        }
+
+        if let Some(v) = self.no_rfc7540_priorities {
+            f(NoRfc7540Priorities(v));
+        }
     }
 }
 
@@ -217,6 +349,8 @@ impl Setting {
             4 => Some(InitialWindowSize(val)),
             5 => Some(MaxFrameSize(val)),
             6 => Some(MaxHeaderListSize(val)),
+            8 => Some(EnableConnectProtocol(val)),
+            9 => Some(NoRfc7540Priorities(val)),
             _ => None,
         }
     }
@@ -248,6 +382,8 @@ impl Setting {
             InitialWindowSize(v) => (4, v),
             MaxFrameSize(v) => (5, v),
             MaxHeaderListSize(v) => (6, v),
+            EnableConnectProtocol(v) => (8, v),
+            NoRfc7540Priorities(v) => (9, v),
         };
 
         dst.put_u16::<BigEndian>(kind);
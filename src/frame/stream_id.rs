@@ -1,6 +1,9 @@
 use byteorder::{BigEndian, ByteOrder};
 use std::u32;
 
+/// A stream identifier, as described in [Section 5.1.1] of RFC 7540.
+///
+/// [Section 5.1.1]: https://tools.ietf.org/html/rfc7540#section-5.1.1
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct StreamId(u32);
 
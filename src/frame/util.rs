@@ -1,5 +1,5 @@
 use super::Error;
-use bytes::Bytes;
+use bytes::{BufMut, Bytes};
 
 /// Strip padding from the given payload.
 ///
@@ -30,7 +30,92 @@ pub fn strip_padding(payload: &mut Bytes) -> Result<u8, Error> {
     }
 
     let _ = payload.split_to(1);
-    let _ = payload.split_off(pad_len);
+    let len = payload.len() - pad_len;
+    let _ = payload.split_off(len);
 
     Ok(pad_len as u8)
 }
+
+/// Writes `pad_len` bytes of padding.
+pub fn put_padding<B: BufMut>(dst: &mut B, pad_len: u8) {
+    const ZEROS: [u8; 255] = [0; 255];
+
+    dst.put_slice(&ZEROS[..pad_len as usize]);
+}
+
+/// Encodes base64url without padding, as used by the `HTTP2-Settings` header
+/// (RFC 4648 section 5).
+pub fn base64url_encode(src: &[u8]) -> String {
+    const ALPHABET: &'static [u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+    let mut dst = String::with_capacity((src.len() * 4 + 2) / 3);
+    let mut acc = 0u32;
+    let mut bits = 0;
+
+    for &b in src {
+        acc = (acc << 8) | b as u32;
+        bits += 8;
+
+        while bits >= 6 {
+            bits -= 6;
+            dst.push(ALPHABET[(acc >> bits) as usize & 0x3f] as char);
+        }
+
+        acc &= (1 << bits) - 1;
+    }
+
+    if bits > 0 {
+        dst.push(ALPHABET[(acc << (6 - bits)) as usize & 0x3f] as char);
+    }
+
+    dst
+}
+
+/// Decodes base64url without padding, as used by the `HTTP2-Settings` header
+/// (RFC 4648 section 5). Trailing padding characters are tolerated.
+///
+/// Returns `None` if the input is not valid base64url.
+pub fn base64url_decode(src: &[u8]) -> Option<Vec<u8>> {
+    let mut src = src;
+
+    while let Some((&b'=', rest)) = src.split_last() {
+        src = rest;
+    }
+
+    let mut dst = Vec::with_capacity(src.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+
+    for &b in src {
+        let val = if b >= b'A' && b <= b'Z' {
+            b - b'A'
+        } else if b >= b'a' && b <= b'z' {
+            b - b'a' + 26
+        } else if b >= b'0' && b <= b'9' {
+            b - b'0' + 52
+        } else if b == b'-' {
+            62
+        } else if b == b'_' {
+            63
+        } else {
+            return None;
+        };
+
+        acc = (acc << 6) | val as u32;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            dst.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+
+    // A single trailing character does not encode a full byte
+    if bits >= 6 {
+        return None;
+    }
+
+    Some(dst)
+}
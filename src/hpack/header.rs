@@ -17,6 +17,7 @@ pub enum Header<T = HeaderName> {
     Method(Method),
     Scheme(String<Bytes>),
     Path(String<Bytes>),
+    Protocol(String<Bytes>),
     Status(StatusCode),
 }
 
@@ -28,6 +29,7 @@ pub enum Name<'a> {
     Method,
     Scheme,
     Path,
+    Protocol,
     Status,
 }
 
@@ -47,6 +49,7 @@ impl Header<Option<HeaderName>> {
             Method(v) => Method(v),
             Scheme(v) => Scheme(v),
             Path(v) => Path(v),
+            Protocol(v) => Protocol(v),
             Status(v) => Status(v),
         })
     }
@@ -72,6 +75,10 @@ impl Header {
                     let value = try!(String::try_from(value));
                     Ok(Header::Path(value))
                 }
+                b"protocol" => {
+                    let value = try!(String::try_from(value));
+                    Ok(Header::Protocol(value))
+                }
                 b"status" => {
                     let status = try!(StatusCode::from_bytes(&value));
                     Ok(Header::Status(status))
@@ -106,6 +113,9 @@ impl Header {
             Header::Path(ref v) => {
                 32 + 5 + v.len()
             }
+            Header::Protocol(ref v) => {
+                32 + 9 + v.len()
+            }
             Header::Status(_) => {
                 32 + 7 + 3
             }
@@ -120,6 +130,7 @@ impl Header {
             Header::Method(..) => Name::Method,
             Header::Scheme(..) => Name::Scheme,
             Header::Path(..) => Name::Path,
+            Header::Protocol(..) => Name::Protocol,
             Header::Status(..) => Name::Status,
         }
     }
@@ -131,6 +142,7 @@ impl Header {
             Header::Method(ref v) => v.as_ref().as_ref(),
             Header::Scheme(ref v) => v.as_ref(),
             Header::Path(ref v) => v.as_ref(),
+            Header::Protocol(ref v) => v.as_ref(),
             Header::Status(ref v) => v.as_str().as_ref(),
         }
     }
@@ -168,6 +180,12 @@ impl Header {
                     _ => false,
                 }
             }
+            Header::Protocol(ref a) => {
+                match *other {
+                    Header::Protocol(ref b) => a == b,
+                    _ => false,
+                }
+            }
             Header::Status(ref a) => {
                 match *other {
                     Header::Status(ref b) => a == b,
@@ -218,6 +236,7 @@ impl From<Header> for Header<Option<HeaderName>> {
             Header::Method(v) => Header::Method(v),
             Header::Scheme(v) => Header::Scheme(v),
             Header::Path(v) => Header::Path(v),
+            Header::Protocol(v) => Header::Protocol(v),
             Header::Status(v) => Header::Status(v),
         }
     }
@@ -244,6 +263,9 @@ impl<'a> Name<'a> {
             Name::Path => {
                 Ok(Header::Path(try!(String::try_from(value))))
             }
+            Name::Protocol => {
+                Ok(Header::Protocol(try!(String::try_from(value))))
+            }
             Name::Status => {
                 match StatusCode::from_bytes(&value) {
                     Ok(status) => Ok(Header::Status(status)),
@@ -261,6 +283,7 @@ impl<'a> Name<'a> {
             Name::Method => b":method",
             Name::Scheme => b":scheme",
             Name::Path => b":path",
+            Name::Protocol => b":protocol",
             Name::Status => b":status",
         }
     }
@@ -7,6 +7,6 @@ mod table;
 #[cfg(test)]
 mod test;
 
-pub use self::encoder::{Encoder, Encode, EncoderError, EncodeState};
+pub use self::encoder::{Encoder, Encode, EncoderError};
 pub use self::header::Header;
 pub use self::decoder::{Decoder, DecoderError};
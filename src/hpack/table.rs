@@ -741,6 +741,7 @@ fn index_static(header: &Header) -> Option<(usize, bool)> {
                 _ => Some((4, false)),
             }
         }
+        Header::Protocol(_) => None,
         Header::Status(ref v) => {
             match u16::from(*v) {
                 200 => Some((8, true)),
@@ -6,6 +6,9 @@ extern crate futures;
 #[macro_use]
 extern crate tokio_io;
 
+// Timeouts
+extern crate tokio_timer;
+
 // HTTP types
 extern crate http;
 
@@ -27,7 +30,13 @@ extern crate string;
 mod error;
 mod codec;
 mod hpack;
+mod padding;
+mod priority;
 mod proto;
+mod protocol;
+mod rewind;
+mod stats;
+mod tunnel;
 
 #[cfg(not(feature = "unstable"))]
 mod frame;
@@ -36,9 +45,15 @@ mod frame;
 pub mod frame;
 
 pub mod client;
+pub mod scheduler;
 pub mod server;
 
 pub use error::{Error, Reason};
+pub use frame::StreamId;
+pub use padding::{Padding, PaddedFrame};
+pub use priority::Priority;
+pub use protocol::{Protocol, InvalidProtocol};
+pub use stats::FlowStats;
 
 #[cfg(feature = "unstable")]
 pub use codec::{Codec, SendError, RecvError, UserError};
@@ -0,0 +1,86 @@
+use std::fmt;
+use std::sync::Arc;
+
+/// Decides how much padding is added to outbound DATA and HEADERS frames.
+///
+/// Padding hides the exact size of the frames written to the connection,
+/// which mitigates traffic analysis based on message lengths. The padding of
+/// DATA frames counts against flow control like the data itself.
+///
+/// When a DATA frame is limited by the max frame size or by the flow control
+/// window, it carries less data to make room for its padding. If the window is
+/// too small to fit any padding, the frame is sent unpadded.
+#[derive(Clone)]
+pub struct Padding {
+    inner: Inner,
+}
+
+/// The kind of frame being padded.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PaddedFrame {
+    /// A DATA frame
+    Data,
+
+    /// A HEADERS frame
+    Headers,
+}
+
+#[derive(Clone)]
+enum Inner {
+    Fixed(u8),
+    Policy(Arc<Fn(PaddedFrame, usize) -> u8 + Send + Sync>),
+}
+
+// ===== impl Padding =====
+
+impl Padding {
+    /// Pads every frame with `pad_len` bytes.
+    pub fn fixed(pad_len: u8) -> Self {
+        Padding {
+            inner: Inner::Fixed(pad_len),
+        }
+    }
+
+    /// Pads each frame with the number of bytes returned by `f`.
+    ///
+    /// `f` is called with the kind of frame and the length of its payload:
+    /// the data of a DATA frame or the encoded header block of a HEADERS
+    /// frame. Returning zero sends the frame unpadded.
+    ///
+    /// `f` is also called when data is queued on a stream, to request flow
+    /// control capacity for the padding of the frames that carry it.
+    pub fn policy<F>(f: F) -> Self
+        where F: Fn(PaddedFrame, usize) -> u8 + Send + Sync + 'static,
+    {
+        Padding {
+            inner: Inner::Policy(Arc::new(f)),
+        }
+    }
+
+    /// Returns the padding of a frame, `None` if it is not padded.
+    pub(crate) fn pad_len(&self, frame: PaddedFrame, len: usize) -> Option<u8> {
+        let pad_len = match self.inner {
+            Inner::Fixed(pad_len) => pad_len,
+            Inner::Policy(ref f) => f(frame, len),
+        };
+
+        if pad_len == 0 {
+            None
+        } else {
+            Some(pad_len)
+        }
+    }
+}
+
+impl fmt::Debug for Padding {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.inner {
+            Inner::Fixed(pad_len) => {
+                fmt.debug_tuple("Padding::Fixed").field(&pad_len).finish()
+            }
+            Inner::Policy(_) => {
+                fmt.debug_struct("Padding::Policy").finish()
+            }
+        }
+    }
+}
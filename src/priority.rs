@@ -0,0 +1,92 @@
+use client;
+use frame::{StreamId, StreamDependency};
+
+use bytes::IntoBuf;
+
+/// The priority of a stream, as described in RFC 7540 section 5.3.
+///
+/// Clients attach it to requests and streams, and schedulers are given the
+/// priorities sent by the remote. By default, a stream depends on no other
+/// stream and has a weight of 16.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Priority {
+    dependency_id: StreamId,
+    weight: u16,
+    is_exclusive: bool,
+}
+
+// ===== impl Priority =====
+
+impl Priority {
+    /// Returns a priority with the given weight.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `weight` is not in the range [1, 256].
+    pub fn new(weight: u16) -> Self {
+        assert!(weight >= 1 && weight <= 256, "invalid stream weight");
+
+        Priority {
+            dependency_id: StreamId::zero(),
+            weight: weight,
+            is_exclusive: false,
+        }
+    }
+
+    /// Makes the stream depend on `stream`.
+    pub fn depends_on<B: IntoBuf>(self, stream: &client::Stream<B>) -> Self {
+        self.depends_on_id(stream.stream_id())
+    }
+
+    pub(crate) fn depends_on_id(mut self, dependency_id: StreamId) -> Self {
+        self.dependency_id = dependency_id;
+        self
+    }
+
+    /// Makes the stream the sole dependency of its parent, with the parent's
+    /// other dependencies becoming dependencies of this stream.
+    pub fn exclusive(mut self) -> Self {
+        self.is_exclusive = true;
+        self
+    }
+
+    /// Returns the ID of the stream depended on, zero for the root.
+    pub fn dependency_id(&self) -> StreamId {
+        self.dependency_id
+    }
+
+    /// Returns the stream's weight, in the range [1, 256].
+    pub fn weight(&self) -> u16 {
+        self.weight
+    }
+
+    /// Returns true if the stream is the sole dependency of its parent.
+    pub fn is_exclusive(&self) -> bool {
+        self.is_exclusive
+    }
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::new(16)
+    }
+}
+
+impl From<Priority> for StreamDependency {
+    fn from(src: Priority) -> Self {
+        StreamDependency::new(
+            src.dependency_id,
+            (src.weight - 1) as u8,
+            src.is_exclusive)
+    }
+}
+
+impl<'a> From<&'a StreamDependency> for Priority {
+    fn from(src: &'a StreamDependency) -> Self {
+        Priority {
+            dependency_id: src.dependency_id(),
+            weight: src.weight() as u16 + 1,
+            is_exclusive: src.is_exclusive(),
+        }
+    }
+}
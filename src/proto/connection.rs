@@ -1,15 +1,21 @@
 use {client, frame, server, proto};
-use frame::Reason;
+use frame::{Reason, StreamId};
 use codec::{SendError, RecvError};
+use padding::Padding;
+use scheduler::NewScheduler;
+use stats::FlowStats;
 
 use proto::*;
 
-use http::Request;
+use http::{Method, Request};
 use futures::{Stream};
 use bytes::{Bytes, IntoBuf};
 use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_timer::{Sleep, Timer};
 
+use std::io;
 use std::marker::PhantomData;
+use std::time::Duration;
 
 /// An H2 connection
 #[derive(Debug)]
@@ -31,10 +37,89 @@ pub(crate) struct Connection<T, P, B: IntoBuf = Bytes>
     /// Stream state handler
     streams: Streams<B::Buf, P>,
 
+    /// Graceful shutdown state, if one has been initiated
+    shutdown: Option<Shutdown>,
+
+    /// GOAWAY frame received from the remote, if any
+    remote_go_away: Option<frame::GoAway>,
+
+    /// Opaque debug data included in GOAWAY frames sent to the remote
+    go_away_debug_data: Bytes,
+
+    /// Timer tracking the graceful shutdown PING. Created when the shutdown
+    /// starts if not provided by the config.
+    timer: Option<Timer>,
+
+    /// How long to wait for the graceful shutdown PING to be acknowledged
+    shutdown_ping_timeout: Duration,
+
     /// Client or server
     _phantom: PhantomData<P>,
 }
 
+/// Connection level configuration provided by the client / server builders.
+#[derive(Clone, Debug)]
+pub(crate) struct Config {
+    /// The local settings sent to the peer during the handshake
+    pub settings: frame::Settings,
+
+    /// How long to wait for the peer to acknowledge the local settings
+    pub settings_timeout: Option<Duration>,
+
+    /// How long the connection must be idle before sending a keep-alive PING.
+    /// Keep-alive is disabled if not set.
+    pub keep_alive_interval: Option<Duration>,
+
+    /// How long to wait for a keep-alive PING to be acknowledged
+    pub keep_alive_timeout: Duration,
+
+    /// Send keep-alive PINGs even if there are no open streams
+    pub keep_alive_while_idle: bool,
+
+    /// How long to wait for the graceful shutdown PING to be acknowledged
+    pub graceful_shutdown_timeout: Duration,
+
+    /// Timer used to track timeouts. If not set, a new timer is created when
+    /// needed.
+    pub timer: Option<Timer>,
+
+    /// Release recv capacity when data is polled
+    pub auto_release_capacity: bool,
+
+    /// Size the receive windows from the bandwidth-delay product
+    pub adaptive_window: bool,
+
+    /// Maximum size of the receive windows when adaptive flow control is
+    /// enabled
+    pub max_adaptive_window_size: WindowSize,
+
+    /// Creates the send scheduler of each connection. The default scheduler
+    /// is used if not set.
+    pub scheduler: Option<NewScheduler>,
+
+    /// Padding added to outbound DATA and HEADERS frames
+    pub padding: Option<Padding>,
+}
+
+/// Tracks a graceful shutdown initiated by the local endpoint.
+#[derive(Debug)]
+enum Shutdown {
+    /// The initial GOAWAY, advertising the max stream ID, must be sent.
+    Initiated,
+
+    /// Waiting for the PING sent after the initial GOAWAY to be acknowledged.
+    /// This gives streams already in flight one round-trip to arrive. If the
+    /// PING is not acknowledged before the sleep completes, the final GOAWAY
+    /// is sent anyway.
+    WaitingPing(UserPing, Sleep),
+
+    /// The final GOAWAY, with the last processed stream ID, must be sent.
+    GoAway,
+
+    /// Waiting for the remaining streams to complete.
+    Draining,
+}
+
 #[derive(Debug)]
 enum State {
     /// Currently open in a sane state
@@ -50,30 +135,274 @@ enum State {
     Error(Reason),
 }
 
+// ===== impl Config =====
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            settings: frame::Settings::default(),
+            settings_timeout: None,
+            keep_alive_interval: None,
+            keep_alive_timeout: Duration::from_secs(20),
+            keep_alive_while_idle: false,
+            graceful_shutdown_timeout: Duration::from_secs(1),
+            timer: None,
+            auto_release_capacity: false,
+            adaptive_window: false,
+            max_adaptive_window_size: DEFAULT_MAX_ADAPTIVE_WINDOW_SIZE,
+            scheduler: None,
+            padding: None,
+        }
+    }
+}
+
+// ===== impl Connection =====
+
 impl<T, P, B> Connection<T, P, B>
     where T: AsyncRead + AsyncWrite,
           P: Peer,
           B: IntoBuf,
 {
-    pub fn new(codec: Codec<T, Prioritized<B::Buf>>) -> Connection<T, P, B> {
-        // TODO: Actually configure
+    pub fn new(mut codec: Codec<T, Prioritized<B::Buf>>,
+               config: &Config)
+        -> Connection<T, P, B>
+    {
+        // Neither the remote settings nor the acknowledgement of the local
+        // settings have been received yet, so both ends start with the
+        // protocol defaults. The local settings are applied once the peer ACKs
+        // them.
+        //
+        // Push promises are only sent in response to requests, which the peer
+        // always receives after the SETTINGS frame, so disabling push takes
+        // effect immediately.
         let streams = Streams::new(streams::Config {
             max_remote_initiated: None,
             init_remote_window_sz: DEFAULT_INITIAL_WINDOW_SIZE,
             max_local_initiated: None,
             init_local_window_sz: DEFAULT_INITIAL_WINDOW_SIZE,
+            local_push_enabled: config.settings.is_push_enabled(),
+            local_extended_connect_protocol: config.settings.is_extended_connect_protocol_enabled(),
+            auto_release_capacity: config.auto_release_capacity,
+            adaptive_window: if config.adaptive_window {
+                Some(config.max_adaptive_window_size)
+            } else {
+                None
+            },
+            scheduler: config.scheduler.clone(),
+            padding: config.padding.clone(),
         });
 
+        // HEADERS frames are padded by the codec, once the size of the header
+        // block is known.
+        codec.set_padding(config.padding.clone());
+
+        // The max header list size is advisory, so the limit is enforced
+        // without waiting for the peer to acknowledge it.
+        if let Some(val) = config.settings.max_header_list_size() {
+            codec.set_max_recv_header_list_size(val as usize);
+        }
+
+        // All timeouts on the connection share a single timer.
+        let mut config = config.clone();
+
+        if config.timer.is_none() &&
+            (config.settings_timeout.is_some() || config.keep_alive_interval.is_some())
+        {
+            config.timer = Some(Timer::default());
+        }
+
         Connection {
             state: State::Open,
             codec: codec,
-            ping_pong: PingPong::new(),
-            settings: Settings::new(),
+            ping_pong: PingPong::new(&config),
+            settings: Settings::new(&config),
             streams: streams,
+            shutdown: None,
+            remote_go_away: None,
+            go_away_debug_data: Bytes::new(),
+            timer: config.timer.clone(),
+            shutdown_ping_timeout: config.graceful_shutdown_timeout,
             _phantom: PhantomData,
         }
     }
 
+    /// Queue an update to the local SETTINGS_INITIAL_WINDOW_SIZE.
+    ///
+    /// The windows of existing streams are adjusted once the peer acknowledges
+    /// the change.
+    pub fn set_initial_window_size(&mut self, size: WindowSize) {
+        self.settings.queued_mut().set_initial_window_size(Some(size));
+    }
+
+    /// Queue an update to the local SETTINGS_MAX_CONCURRENT_STREAMS.
+    pub fn set_max_concurrent_streams(&mut self, max: u32) {
+        self.settings.queued_mut().set_max_concurrent_streams(Some(max));
+    }
+
+    /// Set the opaque debug data included in any GOAWAY frame sent from now on.
+    ///
+    /// The data is truncated so that the frame fits in the smallest max frame
+    /// size a peer may advertise.
+    pub fn set_go_away_debug_data(&mut self, mut debug_data: Bytes) {
+        debug_data.truncate(frame::DEFAULT_MAX_FRAME_SIZE as usize - 8);
+        self.go_away_debug_data = debug_data;
+    }
+
+    /// Returns the connection's flow control state.
+    pub fn flow_stats(&mut self) -> FlowStats {
+        self.streams.flow_stats()
+    }
+
+    /// Accept the HTTP/1.1 request that upgraded the connection to HTTP/2
+    /// (h2c).
+    ///
+    /// The settings from the request's `HTTP2-Settings` header are applied as
+    /// the client's initial settings, the 101 response acknowledging them.
+    /// The request opens stream 1, half-closed (remote).
+    pub fn recv_upgrade(&mut self, settings: &frame::Settings, request: frame::Headers)
+        -> Result<(), proto::Error>
+    {
+        debug_assert!(P::is_server());
+
+        let res = Settings::apply_remote(settings, &mut self.codec, &mut self.streams)
+            .and_then(|_| self.streams.recv_headers(request));
+
+        match res {
+            Ok(()) => Ok(()),
+            Err(RecvError::Connection(reason)) => Err(reason.into()),
+            Err(RecvError::Stream { id, reason }) => {
+                // The client is waiting for the response on stream 1, so it
+                // must be reset rather than left open.
+                trace!("upgrade stream error; id={:?}; reason={:?}", id, reason);
+                self.streams.send_reset(id, reason);
+                Ok(())
+            }
+            Err(RecvError::Io(e)) => Err(e.into()),
+        }
+    }
+
+    /// Queue a PING frame, returning a handle to its round-trip time.
+    pub fn ping(&mut self) -> UserPing {
+        self.ping_pong.ping()
+    }
+
+    /// Start a graceful shutdown.
+    ///
+    /// A GOAWAY frame with the max stream ID is sent, followed by a PING. Once
+    /// the PING is acknowledged, a second GOAWAY with the last processed stream
+    /// ID is sent. The connection closes once all remaining streams complete.
+    pub fn graceful_shutdown(&mut self) {
+        if self.shutdown.is_none() {
+            self.shutdown = Some(Shutdown::Initiated);
+        }
+    }
+
+    /// Advances the graceful shutdown, if one has been initiated.
+    fn poll_shutdown(&mut self) -> Poll<(), io::Error> {
+        loop {
+            let next = match self.shutdown {
+                Some(Shutdown::Initiated) => {
+                    try_ready!(self.codec.poll_ready());
+
+                    let frame = self.go_away(StreamId::max(), Reason::NoError);
+                    self.codec.buffer(frame.into())
+                        .ok().expect("invalid GO_AWAY frame");
+
+                    let timeout = self.shutdown_ping_timeout;
+                    let sleep = self.timer.get_or_insert_with(Timer::default)
+                        .sleep(timeout);
+
+                    Shutdown::WaitingPing(self.ping_pong.ping(), sleep)
+                }
+                Some(Shutdown::WaitingPing(ref mut ping, ref mut sleep)) => {
+                    match ping.poll_rtt() {
+                        Ok(Async::NotReady) => {
+                            match sleep.poll() {
+                                Ok(Async::NotReady) => return Ok(Async::Ready(())),
+                                Ok(Async::Ready(())) => {
+                                    debug!("graceful shutdown ping timed out");
+                                }
+                                Err(e) => {
+                                    warn!("failed to track graceful shutdown; err={:?}", e);
+                                }
+                            }
+
+                            Shutdown::GoAway
+                        }
+                        // If the PING fails, the connection is closing anyway
+                        _ => Shutdown::GoAway,
+                    }
+                }
+                Some(Shutdown::GoAway) => {
+                    try_ready!(self.codec.poll_ready());
+
+                    let last_processed_id = self.streams.go_away();
+
+                    let frame = self.go_away(last_processed_id, Reason::NoError);
+                    self.codec.buffer(frame.into())
+                        .ok().expect("invalid GO_AWAY frame");
+
+                    Shutdown::Draining
+                }
+                Some(Shutdown::Draining) | None => return Ok(Async::Ready(())),
+            };
+
+            self.shutdown = Some(next);
+        }
+    }
+
+    fn go_away(&self, last_stream_id: StreamId, reason: Reason) -> frame::GoAway {
+        let mut frame = frame::GoAway::new(last_stream_id, reason);
+        frame.set_debug_data(self.go_away_debug_data.clone());
+        frame
+    }
+
+    /// Grows the receive windows when adaptive flow control finds them too
+    /// small for the bandwidth-delay product.
+    fn poll_window_tuning(&mut self) {
+        let ping_pong = &mut self.ping_pong;
+
+        if let Some(size) = self.streams.poll_window_tuning(|| ping_pong.ping()) {
+            self.settings.queued_mut().set_initial_window_size(Some(size));
+        }
+    }
+
+    /// Returns true if a graceful shutdown has completed sending GOAWAY frames
+    /// and there are no more open streams.
+    fn is_drained(&self) -> bool {
+        match self.shutdown {
+            Some(Shutdown::Draining) => !self.streams.has_open_streams(),
+            _ => false,
+        }
+    }
+
+    /// Returns true if the remote sent GOAWAY and all remaining streams have
+    /// completed.
+    fn is_remote_go_away_done(&self) -> bool {
+        self.remote_go_away.is_some() && !self.streams.has_open_streams()
+    }
+
+    /// Returns the result of a connection closed after the remote sent
+    /// GOAWAY. A GOAWAY carrying an error code results in an error, which
+    /// also fails the remaining streams.
+    ///
+    /// The debug data of a GOAWAY without an error code reaches the caller
+    /// through the errors of the streams it refused.
+    fn remote_go_away_result(&mut self) -> Poll<(), proto::Error> {
+        let err = match self.remote_go_away {
+            Some(ref frame) if frame.reason() != Reason::NoError => {
+                let debug_data = frame.debug_data().clone();
+                proto::Error::GoAway(debug_data, frame.reason())
+            }
+            _ => return Ok(().into()),
+        };
+
+        self.streams.recv_err(&err);
+        self.ping_pong.recv_err(&err);
+
+        Err(err)
+    }
+
     /// Returns `Ready` when the connection is ready to receive a frame.
     ///
     /// Returns `RecvError` as this may raise errors that are caused by delayed
@@ -81,8 +410,14 @@ impl<T, P, B> Connection<T, P, B>
     fn poll_ready(&mut self) -> Poll<(), RecvError> {
         // The order of these calls don't really matter too much as only one
         // should have pending work.
+        self.settings.poll_ack_timeout()?;
+        self.ping_pong.poll_keep_alive(self.streams.has_open_streams())?;
+        try_ready!(self.poll_shutdown());
+        self.poll_window_tuning();
         try_ready!(self.ping_pong.send_pending_pong(&mut self.codec));
+        try_ready!(self.ping_pong.send_pending_ping(&mut self.codec));
         try_ready!(self.settings.send_pending_ack(&mut self.codec, &mut self.streams));
+        try_ready!(self.settings.send_pending_local(&mut self.codec));
         try_ready!(self.streams.send_pending_refusal(&mut self.codec));
 
         Ok(().into())
@@ -99,7 +434,7 @@ impl<T, P, B> Connection<T, P, B>
                 State::Open => {
                     match self.poll2() {
                         // The connection has shutdown normally
-                        Ok(Async::Ready(())) => return Ok(().into()),
+                        Ok(Async::Ready(())) => return self.remote_go_away_result(),
                         // The connection is not ready to make progress
                         Ok(Async::NotReady) => {
                             // Ensure all window updates have been sent.
@@ -107,6 +442,20 @@ impl<T, P, B> Connection<T, P, B>
                             // This will also handle flushing `self.codec`
                             try_ready!(self.streams.poll_complete(&mut self.codec));
 
+                            // Once all streams complete after a graceful
+                            // shutdown, the connection is done.
+                            if self.is_drained() {
+                                debug!("graceful shutdown complete");
+                                return Ok(().into());
+                            }
+
+                            // Once the remote sent GOAWAY, the connection is
+                            // done when all remaining streams complete.
+                            if self.is_remote_go_away_done() {
+                                debug!("remote GOAWAY complete");
+                                return self.remote_go_away_result();
+                            }
+
                             return Ok(Async::NotReady);
                         }
                         // Attempting to read a frame resulted in a connection level
@@ -117,9 +466,10 @@ impl<T, P, B> Connection<T, P, B>
 
                             // Reset all active streams
                             let last_processed_id = self.streams.recv_err(&e.into());
+                            self.ping_pong.recv_err(&e.into());
 
                             // Create the GO_AWAY frame with the last_processed_id
-                            let frame = frame::GoAway::new(last_processed_id, e);
+                            let frame = self.go_away(last_processed_id, e);
 
                             // Transition to the going away state.
                             self.state = State::GoAway(frame);
@@ -140,22 +490,25 @@ impl<T, P, B> Connection<T, P, B>
 
                             // Reset all active streams
                             self.streams.recv_err(&e);
+                            self.ping_pong.recv_err(&e);
 
                             // Return the error
                             return Err(e);
                         }
                     }
                 },
-                State::GoAway(frame) => {
+                State::GoAway(ref frame) => {
                     // Ensure the codec is ready to accept the frame
                     try_ready!(self.codec.poll_ready());
 
+                    let reason = frame.reason();
+
                     // Buffer the GO_AWAY frame
-                    self.codec.buffer(frame.into())
+                    self.codec.buffer(frame.clone().into())
                         .ok().expect("invalid GO_AWAY frame");
 
                     // GO_AWAY sent, transition the connection to an errored state
-                    self.state = State::Flush(frame.reason());
+                    self.state = State::Flush(reason);
                 }
                 State::Flush(reason) => {
                     // Flush the codec
@@ -178,7 +531,13 @@ impl<T, P, B> Connection<T, P, B>
             // First, ensure that the `Connection` is able to receive a frame
             try_ready!(self.poll_ready());
 
-            match try_ready!(self.codec.poll()) {
+            let frame = try_ready!(self.codec.poll());
+
+            if frame.is_some() {
+                self.ping_pong.recv_frame();
+            }
+
+            match frame {
                 Some(Headers(frame)) => {
                     trace!("recv HEADERS; frame={:?}", frame);
                     try!(self.streams.recv_headers(frame));
@@ -197,13 +556,13 @@ impl<T, P, B> Connection<T, P, B>
                 }
                 Some(Settings(frame)) => {
                     trace!("recv SETTINGS; frame={:?}", frame);
-                    self.settings.recv_settings(frame);
+                    self.settings.recv_settings(
+                        frame, &mut self.codec, &mut self.streams)?;
                 }
-                Some(GoAway(_)) => {
-                    // TODO: handle the last_processed_id. Also, should this be
-                    // handled as an error?
-                    // let _ = RecvError::Proto(frame.reason());
-                    return Ok(().into());
+                Some(GoAway(frame)) => {
+                    trace!("recv GOAWAY; frame={:?}", frame);
+                    self.streams.recv_go_away(&frame);
+                    self.remote_go_away = Some(frame);
                 }
                 Some(Ping(frame)) => {
                     trace!("recv PING; frame={:?}", frame);
@@ -215,7 +574,11 @@ impl<T, P, B> Connection<T, P, B>
                 }
                 Some(Priority(frame)) => {
                     trace!("recv PRIORITY; frame={:?}", frame);
-                    // TODO: handle
+                    self.streams.recv_priority(frame)?;
+                }
+                Some(PriorityUpdate(frame)) => {
+                    trace!("recv PRIORITY_UPDATE; frame={:?}", frame);
+                    self.streams.recv_priority_update(frame)?;
                 }
                 None => {
                     // TODO: Is this correct?
@@ -237,10 +600,25 @@ impl<T, B> Connection<T, client::Peer, B>
     }
 
     /// Initialize a new HTTP/2.0 stream and send the message.
-    pub fn send_request(&mut self, request: Request<()>, end_of_stream: bool)
+    pub fn send_request(&mut self,
+                        request: Request<()>,
+                        stream_dep: Option<frame::StreamDependency>,
+                        end_of_stream: bool)
+        -> Result<StreamRef<B::Buf, client::Peer>, SendError>
+    {
+        self.streams.send_request(request, stream_dep, end_of_stream)
+    }
+
+    /// Returns true if the remote accepts extended CONNECT requests.
+    pub fn is_extended_connect_protocol_enabled(&self) -> bool {
+        self.streams.is_extended_connect_protocol_enabled()
+    }
+
+    /// Open stream 1 for the request of an h2c upgrade.
+    pub fn send_upgrade(&mut self, method: &Method)
         -> Result<StreamRef<B::Buf, client::Peer>, SendError>
     {
-        self.streams.send_request(request, end_of_stream)
+        self.streams.send_upgrade(method)
     }
 }
 
@@ -1,12 +1,22 @@
 use frame::Reason;
 use codec::RecvError;
 
+use bytes::Bytes;
+
 use std::io;
 
 /// Either an H2 reason  or an I/O error
 #[derive(Debug)]
 pub enum Error {
     Proto(Reason),
+
+    /// The remote sent GOAWAY with an error code and opaque debug data
+    GoAway(Bytes, Reason),
+
+    /// The stream was not processed by the remote before it sent GOAWAY with
+    /// the given error code and debug data. The stream may be retried.
+    Refused(Bytes, Reason),
+
     Io(io::Error),
 }
 
@@ -15,7 +25,8 @@ impl Error {
         use self::Error::*;
 
         match self {
-            Proto(reason) => RecvError::Connection(reason),
+            Proto(reason) | GoAway(_, reason) => RecvError::Connection(reason),
+            Refused(..) => RecvError::Connection(Reason::RefusedStream),
             Io(e) => RecvError::Io(e),
         }
     }
@@ -5,9 +5,10 @@ mod ping_pong;
 mod settings;
 mod streams;
 
-pub(crate) use self::connection::Connection;
+pub(crate) use self::connection::{Config, Connection};
 pub(crate) use self::error::Error;
 pub(crate) use self::peer::Peer;
+pub(crate) use self::ping_pong::{KeepAliveTimedOut, UserPing};
 pub(crate) use self::streams::{Streams, StreamRef};
 
 use codec::Codec;
@@ -33,3 +34,4 @@ pub type WindowSize = u32;
 // TODO: Move these into `frame`
 pub const DEFAULT_INITIAL_WINDOW_SIZE: WindowSize = 65_535;
 pub const MAX_WINDOW_SIZE: WindowSize = (1 << 31) - 1;
+pub const DEFAULT_MAX_ADAPTIVE_WINDOW_SIZE: WindowSize = 16 * 1024 * 1024;
@@ -1,9 +1,25 @@
-use frame::Ping;
+use proto;
+use frame::{Ping, Reason};
 use proto::*;
 
-use std::io;
+use byteorder::{BigEndian, ByteOrder};
+use futures::Future;
+use tokio_timer::{Sleep, Timer};
 
-/// Acknowledges ping requests from the remote.
+use std::{error, fmt, io};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Error failing the connection when a keep-alive PING is not acknowledged in
+/// time.
+///
+/// This is carried by the `io::Error` returned by the connection, and
+/// surfaced as a distinct kind of `h2::Error`.
+#[derive(Debug)]
+pub(crate) struct KeepAliveTimedOut;
+
+/// Acknowledges ping requests from the remote and tracks user initiated pings.
 #[derive(Debug)]
 pub struct PingPong<B> {
     // TODO: this doesn't need to save the entire frame
@@ -11,19 +27,134 @@ pub struct PingPong<B> {
     received_pong: Option<PingPayload>,
     // TODO: factor this out
     blocked_ping: Option<task::Task>,
+
+    /// Used to generate a unique payload for each user initiated ping
+    next_payload: u64,
+
+    /// User initiated pings waiting to be written to the socket
+    pending_pings: VecDeque<UserPing>,
+
+    /// User initiated pings waiting for the remote's acknowledgement
+    inflight_pings: Vec<UserPing>,
+
+    /// Keep-alive state, if enabled
+    keep_alive: Option<KeepAlive>,
+}
+
+/// Sends PINGs on idle connections and detects dead connections.
+#[derive(Debug)]
+struct KeepAlive {
+    /// How long the connection must be idle before sending a PING
+    interval: Duration,
+
+    /// How long to wait for the PING to be acknowledged
+    timeout: Duration,
+
+    /// Send PINGs even if there are no open streams
+    while_idle: bool,
+
+    /// Set when a frame is received, indicating the connection is alive
+    received_frame: bool,
+
+    timer: Timer,
+
+    state: KeepAliveState,
+}
+
+#[derive(Debug)]
+enum KeepAliveState {
+    /// Waiting for the interval to elapse
+    Idle(Sleep),
+
+    /// A PING has been queued and is waiting for the acknowledgement
+    Pinging(UserPing, Sleep),
+}
+
+/// A user initiated ping, shared between the connection and the handle
+/// waiting on the round-trip time.
+#[derive(Debug, Clone)]
+pub(crate) struct UserPing {
+    inner: Arc<Mutex<UserPingInner>>,
+}
+
+#[derive(Debug)]
+struct UserPingInner {
+    payload: PingPayload,
+    state: UserPingState,
+
+    /// Task waiting on the round-trip time
+    task: Option<task::Task>,
+}
+
+#[derive(Debug)]
+enum UserPingState {
+    /// Waiting to be written to the socket
+    Pending,
+
+    /// Written to the socket at the given instant
+    Sent(Instant),
+
+    /// Acknowledged by the remote
+    Acked(Duration),
+
+    /// The connection closed before the ping was acknowledged
+    Closed(Option<Reason>),
 }
 
 impl<B> PingPong<B>
     where B: Buf,
 {
-    pub fn new() -> Self {
+    pub fn new(config: &Config) -> Self {
+        let keep_alive = config.keep_alive_interval.map(|interval| {
+            let timer = config.timer.clone().unwrap_or_default();
+            let sleep = timer.sleep(interval);
+
+            KeepAlive {
+                interval: interval,
+                timeout: config.keep_alive_timeout,
+                while_idle: config.keep_alive_while_idle,
+                received_frame: false,
+                timer: timer,
+                state: KeepAliveState::Idle(sleep),
+            }
+        });
+
         PingPong {
             sending_pong: None,
             received_pong: None,
             blocked_ping: None,
+            next_payload: 1,
+            pending_pings: VecDeque::new(),
+            inflight_pings: Vec::new(),
+            keep_alive: keep_alive,
+        }
+    }
+
+    /// Called whenever a frame is received from the remote.
+    pub fn recv_frame(&mut self) {
+        if let Some(ref mut keep_alive) = self.keep_alive {
+            keep_alive.received_frame = true;
         }
     }
 
+    /// Queue a user initiated ping with a unique payload.
+    pub fn ping(&mut self) -> UserPing {
+        let mut payload = PingPayload::default();
+        BigEndian::write_u64(&mut payload, self.next_payload);
+        self.next_payload = self.next_payload.wrapping_add(1);
+
+        let ping = UserPing {
+            inner: Arc::new(Mutex::new(UserPingInner {
+                payload: payload,
+                state: UserPingState::Pending,
+                task: None,
+            })),
+        };
+
+        self.pending_pings.push_back(ping.clone());
+        ping
+    }
+
     /// Process a ping
     pub fn recv_ping(&mut self, ping: Ping) {
         // The caller should always check that `send_pongs` returns ready before
@@ -31,8 +162,20 @@ impl<B> PingPong<B>
         assert!(self.sending_pong.is_none());
 
         if ping.is_ack() {
+            let payload = ping.into_payload();
+
+            let pos = self.inflight_pings.iter()
+                .position(|user_ping| user_ping.payload() == payload);
+
+            if let Some(pos) = pos {
+                // The acknowledgement matches a user initiated ping
+                let user_ping = self.inflight_pings.swap_remove(pos);
+                user_ping.recv_ack();
+                return;
+            }
+
             // Save acknowledgements to be returned from take_pong().
-            self.received_pong = Some(ping.into_payload());
+            self.received_pong = Some(payload);
 
             if let Some(task) = self.blocked_ping.take() {
                 task.notify();
@@ -59,4 +202,209 @@ impl<B> PingPong<B>
 
         Ok(Async::Ready(()))
     }
+
+    /// Send any pending user initiated pings.
+    pub fn send_pending_ping<T>(&mut self, dst: &mut Codec<T, B>) -> Poll<(), io::Error>
+        where T: AsyncWrite,
+    {
+        while !self.pending_pings.is_empty() {
+            try_ready!(dst.poll_ready());
+
+            let user_ping = self.pending_pings.pop_front().unwrap();
+            let frame = Ping::ping(user_ping.payload());
+
+            dst.buffer(frame.into()).ok().expect("invalid ping frame");
+
+            user_ping.sent();
+            self.inflight_pings.push(user_ping);
+        }
+
+        Ok(Async::Ready(()))
+    }
+
+    /// Drive the keep-alive state, queuing a PING when the connection has been
+    /// idle for the keep-alive interval.
+    ///
+    /// Returns an error if a keep-alive PING was not acknowledged in time.
+    pub fn poll_keep_alive(&mut self, has_streams: bool) -> Result<(), io::Error> {
+        let mut keep_alive = match self.keep_alive.take() {
+            Some(keep_alive) => keep_alive,
+            None => return Ok(()),
+        };
+
+        match self.poll_keep_alive2(&mut keep_alive, has_streams) {
+            Ok(true) => {
+                self.keep_alive = Some(keep_alive);
+                Ok(())
+            }
+            // The timer failed, keep-alive is disabled
+            Ok(false) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns `Ok(false)` if keep-alive can no longer be tracked.
+    fn poll_keep_alive2(&mut self, keep_alive: &mut KeepAlive, has_streams: bool)
+        -> Result<bool, io::Error>
+    {
+        loop {
+            let next = match keep_alive.state {
+                KeepAliveState::Idle(ref mut sleep) => {
+                    match sleep.poll() {
+                        Ok(Async::NotReady) => return Ok(true),
+                        Ok(Async::Ready(())) => {}
+                        Err(e) => {
+                            warn!("failed to track keep-alive; err={:?}", e);
+                            return Ok(false);
+                        }
+                    }
+
+                    let received_frame = keep_alive.received_frame;
+                    keep_alive.received_frame = false;
+
+                    if received_frame || (!has_streams && !keep_alive.while_idle) {
+                        // The connection is not idle, or there is nothing to
+                        // keep alive. Wait another interval.
+                        let sleep = keep_alive.timer.sleep(keep_alive.interval);
+                        KeepAliveState::Idle(sleep)
+                    } else {
+                        trace!("sending keep-alive ping");
+                        let sleep = keep_alive.timer.sleep(keep_alive.timeout);
+                        KeepAliveState::Pinging(self.ping(), sleep)
+                    }
+                }
+                KeepAliveState::Pinging(ref mut ping, ref mut sleep) => {
+                    match ping.poll_rtt() {
+                        Ok(Async::Ready(_)) => {}
+                        Ok(Async::NotReady) => {
+                            match sleep.poll() {
+                                Ok(Async::NotReady) => return Ok(true),
+                                Ok(Async::Ready(())) => {
+                                    debug!("keep-alive ping timed out");
+                                    return Err(io::Error::new(
+                                        io::ErrorKind::TimedOut,
+                                        KeepAliveTimedOut));
+                                }
+                                Err(e) => {
+                                    warn!("failed to track keep-alive; err={:?}", e);
+                                    return Ok(false);
+                                }
+                            }
+                        }
+                        // The connection is already failing
+                        Err(_) => return Ok(true),
+                    }
+
+                    let sleep = keep_alive.timer.sleep(keep_alive.interval);
+                    KeepAliveState::Idle(sleep)
+                }
+            };
+
+            keep_alive.state = next;
+        }
+    }
+
+    /// Fail all user initiated pings that have not been acknowledged.
+    pub fn recv_err(&mut self, err: &proto::Error) {
+        let reason = match *err {
+            proto::Error::Proto(reason) |
+            proto::Error::GoAway(_, reason) => Some(reason),
+            proto::Error::Refused(..) => Some(Reason::RefusedStream),
+            proto::Error::Io(..) => None,
+        };
+
+        for user_ping in self.pending_pings.drain(..) {
+            user_ping.close(reason);
+        }
+
+        for user_ping in self.inflight_pings.drain(..) {
+            user_ping.close(reason);
+        }
+    }
+}
+
+// ===== impl KeepAliveTimedOut =====
+
+impl KeepAliveTimedOut {
+    /// Returns true if `err` was caused by a keep-alive timeout.
+    pub fn is(err: &io::Error) -> bool {
+        err.get_ref()
+            .map(|e| e.is::<KeepAliveTimedOut>())
+            .unwrap_or(false)
+    }
+}
+
+impl fmt::Display for KeepAliveTimedOut {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(error::Error::description(self))
+    }
+}
+
+impl error::Error for KeepAliveTimedOut {
+    fn description(&self) -> &str {
+        "keep-alive timed out"
+    }
+}
+
+impl<B> Drop for PingPong<B> {
+    fn drop(&mut self) {
+        // Any remaining pings will never be acknowledged
+        for user_ping in self.pending_pings.drain(..) {
+            user_ping.close(None);
+        }
+
+        for user_ping in self.inflight_pings.drain(..) {
+            user_ping.close(None);
+        }
+    }
+}
+
+// ===== impl UserPing =====
+
+impl UserPing {
+    /// Returns the round-trip time once the ping has been acknowledged.
+    pub fn poll_rtt(&mut self) -> Poll<Duration, proto::Error> {
+        let mut me = self.inner.lock().unwrap();
+
+        match me.state {
+            UserPingState::Acked(rtt) => Ok(Async::Ready(rtt)),
+            UserPingState::Closed(Some(reason)) => Err(proto::Error::Proto(reason)),
+            UserPingState::Closed(None) => {
+                Err(proto::Error::Io(io::ErrorKind::BrokenPipe.into()))
+            }
+            _ => {
+                me.task = Some(task::current());
+                Ok(Async::NotReady)
+            }
+        }
+    }
+
+    fn payload(&self) -> PingPayload {
+        self.inner.lock().unwrap().payload
+    }
+
+    fn sent(&self) {
+        self.inner.lock().unwrap().state = UserPingState::Sent(Instant::now());
+    }
+
+    fn recv_ack(&self) {
+        let mut me = self.inner.lock().unwrap();
+
+        if let UserPingState::Sent(sent_at) = me.state {
+            me.state = UserPingState::Acked(sent_at.elapsed());
+        }
+
+        if let Some(task) = me.task.take() {
+            task.notify();
+        }
+    }
+
+    fn close(&self, reason: Option<Reason>) {
+        let mut me = self.inner.lock().unwrap();
+        me.state = UserPingState::Closed(reason);
+
+        if let Some(task) = me.task.take() {
+            task.notify();
+        }
+    }
 }
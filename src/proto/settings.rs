@@ -1,30 +1,167 @@
 use frame;
+use frame::Reason::*;
 use codec::RecvError;
 use proto::*;
 
+use futures::Future;
+use tokio_timer::{Sleep, Timer};
+
+use std::{io, mem};
+use std::time::Duration;
+
 #[derive(Debug)]
 pub(crate) struct Settings {
     /// Received SETTINGS frame pending processing. The ACK must be written to
     /// the socket first then the settings applied **before** receiving any
     /// further frames.
     pending: Option<frame::Settings>,
+
+    /// Tracks the acknowledgement of the local settings.
+    local: Local,
+
+    /// Local settings changes that have not been sent yet. These are sent once
+    /// the in-flight local settings have been acknowledged.
+    queued: Option<frame::Settings>,
+
+    /// Timer and duration used to enforce SETTINGS_TIMEOUT, if enabled.
+    settings_timeout: Option<(Timer, Duration)>,
+
+    /// Fires if the peer does not acknowledge the local settings in time.
+    ack_timeout: Option<Sleep>,
+}
+
+#[derive(Debug)]
+enum Local {
+    /// The SETTINGS frame has been sent and is waiting for the peer's ACK.
+    /// The settings are only applied once the ACK is received.
+    WaitingAck(frame::Settings),
+
+    /// All local settings have been acknowledged.
+    Synced,
 }
 
 impl Settings {
-    pub fn new() -> Self {
+    /// Create a new `Settings`, tracking the initial SETTINGS frame sent as
+    /// part of the handshake.
+    pub fn new(config: &Config) -> Self {
+        let settings_timeout = config.settings_timeout.map(|timeout| {
+            let timer = config.timer.clone().unwrap_or_default();
+            (timer, timeout)
+        });
+
+        let ack_timeout = settings_timeout.as_ref()
+            .map(|&(ref timer, timeout)| timer.sleep(timeout));
+
         Settings {
             pending: None,
+            local: Local::WaitingAck(config.settings.clone()),
+            queued: None,
+            settings_timeout: settings_timeout,
+            ack_timeout: ack_timeout,
         }
     }
 
-    pub fn recv_settings(&mut self, frame: frame::Settings) {
+    /// Returns the local settings changes to send to the peer.
+    ///
+    /// Changes made through the returned value are merged with any other
+    /// changes that have not been sent yet.
+    pub fn queued_mut(&mut self) -> &mut frame::Settings {
+        self.queued.get_or_insert_with(frame::Settings::default)
+    }
+
+    pub fn recv_settings<T, B, C, P>(&mut self,
+                                     frame: frame::Settings,
+                                     codec: &mut Codec<T, B>,
+                                     streams: &mut Streams<C, P>)
+        -> Result<(), RecvError>
+        where T: AsyncWrite,
+              B: Buf,
+              C: Buf,
+              P: Peer,
+    {
         if frame.is_ack() {
-            debug!("received remote settings ack");
-            // TODO: handle acks
+            let local = match mem::replace(&mut self.local, Local::Synced) {
+                Local::WaitingAck(local) => local,
+                Local::Synced => {
+                    // The peer acknowledged settings that were never sent.
+                    debug!("received unexpected settings ack");
+                    return Err(RecvError::Connection(ProtocolError));
+                }
+            };
+
+            debug!("received remote settings ack; applying {:?}", local);
+
+            self.ack_timeout = None;
+
+            if let Some(val) = local.header_table_size() {
+                codec.set_recv_header_table_size(val as usize);
+            }
+
+            streams.apply_local_settings(&local)?;
         } else {
             assert!(self.pending.is_none());
             self.pending = Some(frame);
         }
+
+        Ok(())
+    }
+
+    /// Send any queued local settings changes.
+    ///
+    /// Only one local SETTINGS frame is in flight at a time, so this does
+    /// nothing until the previous frame has been acknowledged.
+    pub fn send_pending_local<T, B>(&mut self, dst: &mut Codec<T, B>)
+        -> Poll<(), io::Error>
+        where T: AsyncWrite,
+              B: Buf,
+    {
+        if let Local::WaitingAck(_) = self.local {
+            return Ok(Async::Ready(()));
+        }
+
+        if let Some(settings) = self.queued.take() {
+            if !dst.poll_ready()?.is_ready() {
+                self.queued = Some(settings);
+                return Ok(Async::NotReady);
+            }
+
+            trace!("sending local settings; settings={:?}", settings);
+
+            // Buffer the settings frame
+            dst.buffer(settings.clone().into()).ok().expect("invalid settings frame");
+
+            self.ack_timeout = self.settings_timeout.as_ref()
+                .map(|&(ref timer, timeout)| timer.sleep(timeout));
+
+            self.local = Local::WaitingAck(settings);
+        }
+
+        Ok(Async::Ready(()))
+    }
+
+    /// Returns an error if the local settings have not been acknowledged
+    /// before the SETTINGS_TIMEOUT elapsed.
+    pub fn poll_ack_timeout(&mut self) -> Result<(), RecvError> {
+        let res = match self.ack_timeout {
+            Some(ref mut sleep) => sleep.poll(),
+            None => return Ok(()),
+        };
+
+        match res {
+            Ok(Async::NotReady) => Ok(()),
+            Ok(Async::Ready(())) => {
+                debug!("settings ack timed out");
+                self.ack_timeout = None;
+                Err(RecvError::Connection(SettingsTimeout))
+            }
+            Err(e) => {
+                // The timer is unable to track the timeout. Rather than
+                // failing the connection, stop enforcing it.
+                warn!("failed to track settings timeout; err={:?}", e);
+                self.ack_timeout = None;
+                Ok(())
+            }
+        }
     }
 
     pub fn send_pending_ack<T, B, C, P>(&mut self,
@@ -52,15 +189,26 @@ impl Settings {
 
             trace!("ACK sent; applying settings");
 
-            if let Some(val) = settings.max_frame_size() {
-                dst.set_max_send_frame_size(val as usize);
-            }
-
-            streams.apply_remote_settings(settings)?;
+            Settings::apply_remote(settings, dst, streams)?;
         }
 
         self.pending = None;
 
         Ok(().into())
     }
+
+    /// Apply settings received from the peer.
+    pub fn apply_remote<T, B, C, P>(settings: &frame::Settings,
+                                    dst: &mut Codec<T, B>,
+                                    streams: &mut Streams<C, P>)
+        -> Result<(), RecvError>
+        where C: Buf,
+              P: Peer,
+    {
+        if let Some(val) = settings.max_frame_size() {
+            dst.set_max_send_frame_size(val as usize);
+        }
+
+        streams.apply_remote_settings(settings)
+    }
 }
@@ -0,0 +1,150 @@
+use proto::*;
+
+use std::cmp;
+use std::time::Duration;
+
+/// Estimates the bandwidth-delay product of the connection to size the
+/// receive windows.
+///
+/// A sample starts when DATA is received: a PING is sent and the data received
+/// until the PING is acknowledged is counted. When a sample shows that the
+/// peer filled most of the window in a round trip at the highest bandwidth
+/// measured so far, the window is too small to keep the link busy and is set
+/// to twice the amount of data received during the sample, up to the
+/// configured maximum.
+///
+/// The stream window follows the initial window size set locally, while the
+/// connection window only ever grows.
+#[derive(Debug)]
+pub(super) struct Bdp {
+    /// Current initial window size of streams
+    window_sz: WindowSize,
+
+    /// Size the connection window has grown to
+    conn_window_sz: WindowSize,
+
+    /// The window never grows larger than this
+    max_window_sz: WindowSize,
+
+    /// Number of bytes received during the current sample
+    bytes: usize,
+
+    /// PING measuring the round-trip time of the current sample
+    ping: Option<UserPing>,
+
+    /// Highest bandwidth measured, in bytes per second
+    max_bandwidth: f64,
+
+    /// Smoothed round-trip time, in seconds
+    rtt: f64,
+}
+
+impl Bdp {
+    pub fn new(window_sz: WindowSize, max_window_sz: WindowSize) -> Self {
+        Bdp {
+            window_sz: window_sz,
+            conn_window_sz: window_sz,
+            max_window_sz: cmp::min(max_window_sz, MAX_WINDOW_SIZE),
+            bytes: 0,
+            ping: None,
+            max_bandwidth: 0.0,
+            rtt: 0.0,
+        }
+    }
+
+    /// Returns the size the connection window has grown to
+    pub fn conn_window_sz(&self) -> WindowSize {
+        self.conn_window_sz
+    }
+
+    /// Follow a change to the initial window size of streams made through
+    /// the local settings.
+    pub fn set_window_sz(&mut self, window_sz: WindowSize) {
+        self.window_sz = window_sz;
+    }
+
+    /// Count received data towards the current sample.
+    pub fn recv_data(&mut self, sz: WindowSize) {
+        if self.window_sz < self.max_window_sz {
+            self.bytes += sz as usize;
+        }
+    }
+
+    /// Returns true if data was received and a PING should be sent to start a
+    /// new sample.
+    pub fn needs_ping(&self) -> bool {
+        self.ping.is_none() && self.bytes > 0
+    }
+
+    pub fn start_sample(&mut self, ping: UserPing) {
+        self.ping = Some(ping);
+    }
+
+    /// Completes the current sample once its PING is acknowledged, returning
+    /// the new window size if the window should grow.
+    pub fn poll_sample(&mut self) -> Option<WindowSize> {
+        let res = match self.ping {
+            Some(ref mut ping) => ping.poll_rtt(),
+            None => return None,
+        };
+
+        let rtt = match res {
+            Ok(Async::Ready(rtt)) => rtt,
+            Ok(Async::NotReady) => return None,
+            Err(_) => {
+                // The connection is closing
+                self.ping = None;
+                return None;
+            }
+        };
+
+        self.ping = None;
+
+        let bytes = self.bytes;
+        self.bytes = 0;
+
+        self.update_rtt(rtt);
+
+        let bandwidth = bytes as f64 / self.rtt;
+
+        trace!("bdp sample; bytes={}; rtt={}; bandwidth={}; window={}",
+               bytes, self.rtt, bandwidth, self.window_sz);
+
+        if bandwidth < self.max_bandwidth {
+            return None;
+        }
+
+        self.max_bandwidth = bandwidth;
+
+        // Grow the window if the peer was limited by it
+        if bytes < self.window_sz as usize * 2 / 3 {
+            return None;
+        }
+
+        let window_sz = cmp::min(bytes * 2, self.max_window_sz as usize) as WindowSize;
+
+        if window_sz <= self.window_sz {
+            return None;
+        }
+
+        debug!("growing receive window; old={}; new={}", self.window_sz, window_sz);
+
+        self.window_sz = window_sz;
+        self.conn_window_sz = cmp::max(self.conn_window_sz, window_sz);
+
+        Some(window_sz)
+    }
+
+    fn update_rtt(&mut self, rtt: Duration) {
+        let sample = rtt.as_secs() as f64 + rtt.subsec_nanos() as f64 / 1e9;
+
+        // Avoid dividing by zero on very fast links
+        let sample = sample.max(1e-6);
+
+        if self.rtt == 0.0 {
+            self.rtt = sample;
+        } else {
+            self.rtt += (sample - self.rtt) / 8.0;
+        }
+    }
+}
@@ -7,8 +7,9 @@ pub struct FlowControl {
     /// Window size as indicated by the peer. This can go negative.
     window_size: i32,
 
-    /// The amount of the window that is currently available to consume.
-    available: WindowSize,
+    /// The amount of the window that is currently available to consume. This
+    /// can go negative when the local INITIAL_WINDOW_SIZE is decreased.
+    available: i32,
 }
 
 impl FlowControl {
@@ -30,7 +31,11 @@ impl FlowControl {
 
     /// Returns the window size available to the consumer
     pub fn available(&self) -> WindowSize {
-        self.available
+        if self.available < 0 {
+            0
+        } else {
+            self.available as WindowSize
+        }
     }
 
     /// Returns true if there is unavailable window capacity
@@ -39,29 +44,27 @@ impl FlowControl {
             return false;
         }
 
-        self.window_size as WindowSize > self.available
+        self.window_size > self.available
     }
 
     pub fn claim_capacity(&mut self, capacity: WindowSize) {
-        assert!(self.available >= capacity);
-        self.available -= capacity;
+        assert!(self.available >= capacity as i32);
+        self.available -= capacity as i32;
     }
 
     pub fn assign_capacity(&mut self, capacity: WindowSize) {
-        self.available += capacity;
+        self.available += capacity as i32;
     }
 
     /// Returns the number of bytes available but not assigned to the window.
     ///
     /// This represents pending outbound WINDOW_UPDATE frames.
     pub fn unclaimed_capacity(&self) -> WindowSize {
-        let available = self.available as i32;
-
-        if self.window_size >= available {
+        if self.window_size >= self.available {
             return 0;
         }
 
-        (available - self.window_size) as WindowSize
+        (self.available - self.window_size) as WindowSize
     }
 
     /// Increase the window size.
@@ -93,6 +96,15 @@ impl FlowControl {
         self.window_size -= sz as i32;
     }
 
+    /// Decrement both the window size and the available capacity.
+    ///
+    /// This is called when a lower local INITIAL_WINDOW_SIZE value has been
+    /// acknowledged by the peer.
+    pub fn dec_recv_window(&mut self, sz: WindowSize) {
+        self.window_size -= sz as i32;
+        self.available -= sz as i32;
+    }
+
     /// Decrements the window reflecting data has actually been sent. The caller
     /// must ensure that the window has capacity.
     pub fn send_data(&mut self, sz: WindowSize) {
@@ -104,6 +116,6 @@ impl FlowControl {
 
         // Update values
         self.window_size -= sz as i32;
-        self.available -= sz;
+        self.available -= sz as i32;
     }
 }
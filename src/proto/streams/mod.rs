@@ -1,3 +1,4 @@
+mod bdp;
 mod buffer;
 mod flow_control;
 mod prioritize;
@@ -21,6 +22,8 @@ use self::store::{Store, Entry};
 use self::stream::Stream;
 
 use frame::StreamId;
+use padding::Padding;
+use scheduler::NewScheduler;
 use proto::*;
 use error::Reason::*;
 
@@ -40,4 +43,24 @@ pub struct Config {
 
     /// Initial window size of locally initiated streams
     pub init_local_window_sz: WindowSize,
+
+    /// If the local peer is willing to receive push promises
+    pub local_push_enabled: bool,
+
+    /// If the local peer accepts extended CONNECT requests
+    pub local_extended_connect_protocol: bool,
+
+    /// If recv capacity is released when data is polled
+    pub auto_release_capacity: bool,
+
+    /// Maximum size of the receive windows when they are sized from the
+    /// bandwidth-delay product. Adaptive flow control is disabled if `None`.
+    pub adaptive_window: Option<WindowSize>,
+
+    /// Creates the scheduler picking the stream that sends the next frame.
+    /// The default scheduler is used if `None`.
+    pub scheduler: Option<NewScheduler>,
+
+    /// Padding added to outbound DATA frames
+    pub padding: Option<Padding>,
 }
@@ -1,7 +1,10 @@
 use super::*;
 use super::store::Resolve;
 
-use frame::Reason;
+use frame::{Reason, StreamDependency, PriorityParams};
+use padding::{Padding, PaddedFrame};
+use Priority;
+use scheduler::{Scheduler, Ready, Fifo};
 
 use codec::UserError;
 use codec::UserError::*;
@@ -10,13 +13,28 @@ use bytes::buf::Take;
 
 use std::io;
 use std::{fmt, cmp};
+use std::collections::{HashMap, VecDeque};
 
 #[derive(Debug)]
 pub(super) struct Prioritize<B, P>
     where P: Peer,
 {
-    /// Queue of streams waiting for socket capacity to send a frame
-    pending_send: store::Queue<B, stream::NextSend, P>,
+    /// PRIORITY frames waiting to be sent. These are not subject to flow
+    /// control, so they are sent ahead of the streams' frames, but never
+    /// before the stream's HEADERS.
+    pending_priority: VecDeque<(store::Key, frame::Priority)>,
+
+    /// Decides which stream sends the next frame
+    scheduler: Box<Scheduler>,
+
+    /// Streams with frames ready to be sent, in the order they became ready
+    ready: VecDeque<(StreamId, store::Key)>,
+
+    /// The ready streams passed to the scheduler, reused for every frame
+    ready_buf: Vec<Ready>,
+
+    /// Extensible priorities received for streams that are not open yet
+    pending_priority_updates: HashMap<StreamId, PriorityParams>,
 
     /// Queue of streams waiting for window capacity to produce data.
     pending_capacity: store::Queue<B, stream::NextSendCapacity, P>,
@@ -24,10 +42,20 @@ pub(super) struct Prioritize<B, P>
     /// Connection level flow control governing sent data
     flow: FlowControl,
 
+    /// Padding added to DATA frames
+    padding: Option<Padding>,
+
     /// Holds frames that are waiting to be written to the socket
     buffer: Buffer<Frame<B>>,
 }
 
+/// Max number of PRIORITY_UPDATE frames buffered for streams that are not
+/// open yet
+const MAX_PENDING_PRIORITY_UPDATES: usize = 16;
+
+/// Max number of ready streams passed to the scheduler for each frame
+const MAX_SCHEDULER_READY: usize = 64;
+
 pub(crate) struct Prioritized<B> {
     // The buffer
     inner: Take<B>,
@@ -54,10 +82,20 @@ impl<B, P> Prioritize<B, P>
 
         trace!("Prioritize::new; flow={:?}", flow);
 
+        let scheduler: Box<Scheduler> = match config.scheduler {
+            Some(ref scheduler) => scheduler.new_scheduler(),
+            None => Box::new(Fifo::new()),
+        };
+
         Prioritize {
-            pending_send: store::Queue::new(),
+            pending_priority: VecDeque::new(),
+            scheduler: scheduler,
+            ready: VecDeque::new(),
+            ready_buf: vec![],
+            pending_priority_updates: HashMap::new(),
             pending_capacity: store::Queue::new(),
             flow: flow,
+            padding: config.padding.clone(),
             buffer: Buffer::new(),
         }
     }
@@ -71,8 +109,39 @@ impl<B, P> Prioritize<B, P>
         // Queue the frame in the buffer
         stream.pending_send.push_back(&mut self.buffer, frame);
 
-        // Queue the stream
-        self.pending_send.push(stream);
+        // Schedule the stream
+        self.schedule(stream);
+
+        // Notify the connection.
+        if let Some(task) = task.take() {
+            task.notify();
+        }
+    }
+
+    /// Track a stream reserved for a server push. Its frames are held back
+    /// until the PUSH_PROMISE reserving it has been sent.
+    pub fn reserve_push(&mut self,
+                        stream: &mut store::Ptr<B, P>,
+                        associated_id: StreamId)
+    {
+        // The promised stream depends on the associated stream with the
+        // default weight (RFC 7540 section 5.3.5).
+        let priority = Priority::default().depends_on_id(associated_id);
+
+        self.scheduler.open(stream.id);
+        self.scheduler.reprioritize(stream.id, &priority);
+
+        stream.is_pending_push = true;
+    }
+
+    /// Queue a PRIORITY frame, which is sent even if the stream is blocked on
+    /// flow control.
+    pub fn queue_priority(&mut self,
+                          frame: frame::Priority,
+                          stream: &mut store::Ptr<B, P>,
+                          task: &mut Option<Task>)
+    {
+        self.pending_priority.push_back((stream.key(), frame));
 
         // Notify the connection.
         if let Some(task) = task.take() {
@@ -80,6 +149,125 @@ impl<B, P> Prioritize<B, P>
         }
     }
 
+    /// Pop the first PRIORITY frame whose stream already sent its HEADERS.
+    fn pop_priority(&mut self, store: &Store<B, P>) -> Option<frame::Priority> {
+        let buffer = &self.buffer;
+
+        let pos = self.pending_priority.iter().position(|&(key, _)| {
+            match store[key].pending_send.peek_front(buffer) {
+                Some(&Frame::Headers(_)) => false,
+                _ => true,
+            }
+        });
+
+        pos.and_then(|pos| self.pending_priority.remove(pos))
+            .map(|(_, frame)| frame)
+    }
+
+    /// Track a newly opened stream, with its initial priority.
+    pub fn open(&mut self, id: StreamId, dependency: Option<&StreamDependency>) {
+        self.scheduler.open(id);
+
+        if let Some(dependency) = dependency {
+            self.reprioritize(id, dependency);
+        }
+    }
+
+    /// Update the priority of a stream.
+    pub fn reprioritize(&mut self, id: StreamId, dependency: &StreamDependency) {
+        self.scheduler.reprioritize(id, &dependency.into());
+    }
+
+    /// Update the extensible priority of a stream.
+    pub fn set_priority(&mut self, params: PriorityParams, stream: &mut store::Ptr<B, P>) {
+        stream.priority = params;
+    }
+
+    /// Save the extensible priority of a stream that is not open yet, to be
+    /// applied once it opens.
+    pub fn buffer_priority_update(&mut self, id: StreamId, params: PriorityParams) {
+        if self.pending_priority_updates.len() >= MAX_PENDING_PRIORITY_UPDATES &&
+            !self.pending_priority_updates.contains_key(&id)
+        {
+            trace!("dropping priority update; id={:?}", id);
+            return;
+        }
+
+        self.pending_priority_updates.insert(id, params);
+    }
+
+    /// Take the extensible priority received before the stream opened.
+    pub fn take_priority_update(&mut self, id: StreamId) -> Option<PriorityParams> {
+        self.pending_priority_updates.remove(&id)
+    }
+
+    /// Track a closed stream.
+    pub fn close(&mut self, id: StreamId) {
+        self.scheduler.close(id);
+    }
+
+    /// Schedule the stream to send its pending frames.
+    fn schedule(&mut self, stream: &mut store::Ptr<B, P>) {
+        if stream.is_pending_push {
+            // Still waiting to send the PUSH_PROMISE, the stream is scheduled
+            // once the promise has been sent.
+            return;
+        }
+
+        if stream.is_scheduled {
+            return;
+        }
+
+        stream.is_scheduled = true;
+        self.ready.push_back((stream.id, stream.key()));
+    }
+
+    /// Ask the scheduler which ready stream sends the next frame, returning
+    /// the stream and the max amount of data it may send.
+    fn pop_ready<'a>(&mut self, store: &'a mut Store<B, P>)
+        -> Option<(store::Ptr<'a, B, P>, usize)>
+    {
+        if self.ready.is_empty() {
+            return None;
+        }
+
+        self.ready_buf.clear();
+
+        // Only the streams at the front are passed to the scheduler, so that
+        // the work done for each frame does not grow with the number of ready
+        // streams. Streams move to the back once they send a frame.
+        for &(id, key) in self.ready.iter().take(MAX_SCHEDULER_READY) {
+            let stream = store.resolve(key);
+
+            let len = cmp::min(
+                stream.buffered_send_data,
+                stream.send_flow.available());
+
+            self.ready_buf.push(Ready::new(id, len as usize, stream.priority));
+        }
+
+        let selection = self.scheduler.select(&self.ready_buf);
+
+        let pos = self.ready_buf.iter()
+            .position(|ready| ready.stream_id() == selection.stream_id());
+
+        let pos = match pos {
+            Some(pos) => pos,
+            None => {
+                warn!("scheduler selected a stream that is not ready; id={:?}",
+                      selection.stream_id());
+                0
+            }
+        };
+
+        let (_, key) = self.ready.remove(pos).unwrap();
+
+        let mut stream = store.resolve(key);
+        stream.is_scheduled = false;
+
+        Some((stream, selection.max_data_len()))
+    }
+
     /// Send a data frame
     pub fn send_data(&mut self,
                      frame: frame::Data<B>,
@@ -107,14 +295,20 @@ impl<B, P> Prioritize<B, P>
         // Update the buffered data counter
         stream.buffered_send_data += sz;
 
-        trace!("send_data; sz={}; buffered={}; requested={}",
-               sz, stream.buffered_send_data, stream.requested_send_capacity);
+        // The padding sent along with the data consumes capacity as well
+        stream.buffered_send_padding += self.data_padding(sz as usize);
+
+        trace!("send_data; sz={}; buffered={}; padding={}; requested={}",
+               sz, stream.buffered_send_data, stream.buffered_send_padding,
+               stream.requested_send_capacity);
+
+        let buffered = stream.buffered_send_data + stream.buffered_send_padding;
 
         // Implicitly request more send capacity if not enough has been
         // requested yet.
-        if stream.requested_send_capacity < stream.buffered_send_data {
+        if stream.requested_send_capacity < buffered {
             // Update the target requested capacity
-            stream.requested_send_capacity = stream.buffered_send_data;
+            stream.requested_send_capacity = buffered;
 
             self.try_assign_capacity(stream);
         }
@@ -141,17 +335,34 @@ impl<B, P> Prioritize<B, P>
         Ok(())
     }
 
+    /// Returns the connection's send window
+    pub fn window_size(&self) -> WindowSize {
+        self.flow.window_size()
+    }
+
     /// Request capacity to send data
     pub fn reserve_capacity(&mut self, capacity: WindowSize, stream: &mut store::Ptr<B, P>) {
-        // Actual capacity is `capacity` + the current amount of buffered data.
-        // It it were less, then we could never send out the buffered data.
-        let capacity = capacity + stream.buffered_send_data;
+        // Actual capacity is `capacity` + the current amount of buffered data
+        // and its padding. It it were less, then we could never send out the
+        // buffered data.
+        let capacity = capacity + stream.buffered_send_data + stream.buffered_send_padding;
 
         if capacity == stream.requested_send_capacity {
             // Nothing to do
         } else if capacity < stream.requested_send_capacity {
-            // TODO: release capacity
-            unimplemented!();
+            // Update the target requested capacity
+            stream.requested_send_capacity = capacity;
+
+            // Return the capacity assigned beyond the new target to the
+            // connection, so that other streams may use it.
+            let available = stream.send_flow.available();
+
+            if available > capacity {
+                let diff = available - capacity;
+
+                stream.send_flow.claim_capacity(diff);
+                self.assign_connection_capacity(diff, stream);
+            }
         } else {
             // Update the target requested capacity
             stream.requested_send_capacity = capacity;
@@ -200,22 +411,45 @@ impl<B, P> Prioritize<B, P>
     {
         self.flow.assign_capacity(inc);
 
-        // Assign newly acquired capacity to streams pending capacity.
+        // Assign newly acquired capacity to streams pending capacity. Each
+        // stream gets a share of the capacity proportional to its priority.
         while self.flow.available() > 0 {
-            let mut stream = match self.pending_capacity.pop(store) {
-                Some(stream) => stream,
-                None => return,
-            };
-
-            // Try to assign capacity to the stream. This will also re-queue the
-            // stream if there isn't enough connection level capacity to fulfill
-            // the capacity request.
-            self.try_assign_capacity(&mut stream);
+            let mut waiting = vec![];
+
+            while let Some(stream) = self.pending_capacity.pop(store) {
+                waiting.push((stream.key(), self.scheduler.share(stream.id)));
+            }
+
+            if waiting.is_empty() {
+                return;
+            }
+
+            let available = self.flow.available() as f64;
+            let total: f64 = waiting.iter().map(|&(_, share)| share).sum();
+
+            for (key, share) in waiting {
+                let assign = cmp::max((available * share / total) as WindowSize, 1);
+
+                // Try to assign capacity to the stream. This will also re-queue
+                // the stream if it did not get enough capacity to fulfill the
+                // capacity request.
+                self.assign_capacity_up_to(assign, &mut store.resolve(key));
+            }
         }
     }
 
     /// Request capacity to send data
     fn try_assign_capacity(&mut self, stream: &mut store::Ptr<B, P>) {
+        // There should be no streams pending capacity if the connection has
+        // capacity available.
+        debug_assert!(self.flow.available() == 0 || self.pending_capacity.is_empty());
+
+        let available = self.flow.available();
+        self.assign_capacity_up_to(available, stream);
+    }
+
+    /// Assign up to `max` of the connection's available capacity to the stream
+    fn assign_capacity_up_to(&mut self, max: WindowSize, stream: &mut store::Ptr<B, P>) {
         let total_requested = stream.requested_send_capacity;
 
         // Total requested should never go below actual assigned
@@ -242,15 +476,11 @@ impl<B, P> Prioritize<B, P>
         debug_assert!(stream.state.is_send_streaming() || stream.buffered_send_data > 0);
 
         // The amount of currently available capacity on the connection
-        let conn_available = self.flow.available();
+        let conn_available = cmp::min(self.flow.available(), max);
 
         // First check if capacity is immediately available
         if conn_available > 0 {
-            // There should be no streams pending capacity
-            debug_assert!(self.pending_capacity.is_empty());
-
             // The amount of capacity to assign to the stream
-            // TODO: Should prioritization factor into this?
             let assign = cmp::min(conn_available, additional);
 
             // Assign the capacity to the stream
@@ -279,9 +509,8 @@ impl<B, P> Prioritize<B, P>
         }
 
         // If data is buffered, then schedule the stream for execution
-        if stream.buffered_send_data > 0 {
-            debug_assert!(stream.send_flow.available() > 0);
-            self.pending_send.push(stream);
+        if stream.buffered_send_data > 0 && stream.send_flow.available() > 0 {
+            self.schedule(stream);
         }
     }
 
@@ -308,6 +537,10 @@ impl<B, P> Prioritize<B, P>
                 Some(frame) => {
                     trace!("writing frame={:?}", frame);
 
+                    if let Frame::PushPromise(ref frame) = frame {
+                        self.schedule_pushed(store, frame.promised_id());
+                    }
+
                     dst.buffer(frame).ok().expect("invalid frame");
 
                     // Ensure the codec is ready to try the loop again.
@@ -332,6 +565,18 @@ impl<B, P> Prioritize<B, P>
         }
     }
 
+    /// Schedule a pushed stream once its PUSH_PROMISE is written, so that the
+    /// pushed response never precedes the promise.
+    fn schedule_pushed(&mut self, store: &mut Store<B, P>, id: StreamId) {
+        if let Some(mut stream) = store.find_mut(&id) {
+            stream.is_pending_push = false;
+
+            if !stream.pending_send.is_empty() {
+                self.schedule(&mut stream);
+            }
+        }
+    }
+
     /// Tries to reclaim a pending data frame from the codec.
     ///
     /// Returns true if a frame was reclaimed.
@@ -365,7 +610,13 @@ impl<B, P> Prioritize<B, P>
                     frame.set_end_stream(true);
                 }
 
-                self.push_back_frame(frame.into(), &mut stream);
+                // Push the frame to the front of the stream's deque
+                stream.pending_send.push_front(&mut self.buffer, frame.into());
+
+                // If needed, schedule the sender
+                if stream.send_flow.available() > 0 {
+                    self.schedule(&mut stream);
+                }
 
                 return true;
             }
@@ -374,18 +625,6 @@ impl<B, P> Prioritize<B, P>
         false
     }
 
-    /// Push the frame to the front of the stream's deque, scheduling the
-    /// steream if needed.
-    fn push_back_frame(&mut self, frame: Frame<B>, stream: &mut store::Ptr<B, P>) {
-        // Push the frame to the front of the stream's deque
-        stream.pending_send.push_front(&mut self.buffer, frame);
-
-        // If needed, schedule the sender
-        if stream.send_flow.available() > 0 {
-            self.pending_send.push(stream);
-        }
-    }
-
     pub fn clear_queue(&mut self, stream: &mut store::Ptr<B, P>) {
         trace!("clear_queue; stream-id={:?}", stream.id);
 
@@ -395,14 +634,57 @@ impl<B, P> Prioritize<B, P>
         }
     }
 
+    /// Returns the capacity consumed by the padding of a DATA frame carrying
+    /// `len` bytes of data, including the pad length field.
+    fn data_padding(&self, len: usize) -> WindowSize {
+        let pad_len = self.padding.as_ref().and_then(|padding| {
+            padding.pad_len(PaddedFrame::Data, len)
+        });
+
+        pad_len.map(|pad_len| 1 + pad_len as WindowSize).unwrap_or(0)
+    }
+
+    /// Returns the padding of a DATA frame carrying `len` bytes of data.
+    ///
+    /// The data is shortened to fit the padding if the padded payload exceeds
+    /// `max_len`. If even that leaves no room, the frame is not padded.
+    fn pad_data(&self, len: &mut usize, max_len: usize) -> Option<u8> {
+        let pad_len = self.padding.as_ref().and_then(|padding| {
+            padding.pad_len(PaddedFrame::Data, *len)
+        });
+
+        let pad_len = match pad_len {
+            Some(pad_len) => pad_len,
+            None => return None,
+        };
+
+        // The pad length field precedes the data
+        let overhead = 1 + pad_len as usize;
+
+        if *len + overhead > max_len {
+            if max_len <= overhead {
+                return None;
+            }
+
+            *len = max_len - overhead;
+        }
+
+        Some(pad_len)
+    }
+
     fn pop_frame(&mut self, store: &mut Store<B, P>, max_len: usize)
         -> Option<Frame<Prioritized<B>>>
     {
         trace!("pop_frame");
 
+        if let Some(frame) = self.pop_priority(store) {
+            trace!("pop_frame; frame={:?}", frame);
+            return Some(frame.into());
+        }
+
         loop {
-            match self.pending_send.pop(store) {
-                Some(mut stream) => {
+            match self.pop_ready(store) {
+                Some((mut stream, max_data_len)) => {
                     trace!("pop_frame; stream={:?}", stream.id);
 
                     let frame = match stream.pending_send.pop_front(&mut self.buffer).unwrap() {
@@ -442,27 +724,51 @@ impl<B, P> Prioritize<B, P>
                                 continue;
                             }
 
-                            // Only send up to the max frame length
+                            // Only send up to the max frame length and the
+                            // amount allowed by the scheduler
                             let len = cmp::min(sz, max_len);
+                            let len = cmp::min(len, max_data_len);
 
                             // Only send up to the stream's window capacity
-                            let len = cmp::min(len, stream_capacity as usize);
+                            let mut len = cmp::min(len, stream_capacity as usize);
+
+                            // The padding counts against the max frame length
+                            // and the stream's window capacity as well
+                            let max_padded_len = cmp::min(max_len, stream_capacity as usize);
+                            let pad_len = self.pad_data(&mut len, max_padded_len);
+
+                            frame.set_pad_len(pad_len);
+
+                            let padded_len = frame.padded_len(len) as WindowSize;
 
                             // There *must* be be enough connection level
                             // capacity at this point.
-                            debug_assert!(len <= self.flow.window_size() as usize);
+                            debug_assert!(padded_len <= self.flow.window_size());
 
                             // Update the flow control
                             trace!(" -- updating stream flow --");
-                            stream.send_flow.send_data(len as WindowSize);
+                            stream.send_flow.send_data(padded_len);
+
+                            // Decrement the stream's buffered data counter
+                            debug_assert!(stream.buffered_send_data >= len as WindowSize);
+                            stream.buffered_send_data -= len as WindowSize;
+                            stream.requested_send_capacity -= padded_len;
+
+                            let padding = padded_len - len as WindowSize;
+
+                            stream.buffered_send_padding = if stream.buffered_send_data > 0 {
+                                stream.buffered_send_padding.saturating_sub(padding)
+                            } else {
+                                0
+                            };
 
                             // Assign the capacity back to the connection that
                             // was just consumed from the stream in the previous
                             // line.
-                            self.flow.assign_capacity(len as WindowSize);
+                            self.flow.assign_capacity(padded_len);
 
                             trace!(" -- updating connection flow --");
-                            self.flow.send_data(len as WindowSize);
+                            self.flow.send_data(padded_len);
 
                             // Wrap the frame's data payload to ensure that the
                             // correct amount of data gets written.
@@ -486,12 +792,30 @@ impl<B, P> Prioritize<B, P>
 
                     trace!("pop_frame; frame={:?}", frame);
 
+                    // Charge the stream for the slot it used
+                    let len = match frame {
+                        Frame::Data(ref frame) => frame.padded_len(frame.payload().remaining()),
+                        _ => 0,
+                    };
+
+                    self.scheduler.sent(stream.id, frame::HEADER_LEN + len);
+
                     if !stream.pending_send.is_empty() {
                         // TODO: Only requeue the sender IF it is ready to send
                         // the next frame. i.e. don't requeue it if the next
                         // frame is a data frame and the stream does not have
                         // any more capacity.
-                        self.pending_send.push(&mut stream);
+                        self.schedule(&mut stream);
+                    }
+
+                    // The padding requested when the data was buffered does
+                    // not cover the data being split across several padded
+                    // frames.
+                    let buffered = stream.buffered_send_data + stream.buffered_send_padding;
+
+                    if stream.requested_send_capacity < buffered {
+                        stream.requested_send_capacity = buffered;
+                        self.try_assign_capacity(&mut stream);
                     }
 
                     return Some(frame);
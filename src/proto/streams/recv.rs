@@ -3,10 +3,13 @@ use frame::Reason;
 use codec::{RecvError, UserError};
 use proto::*;
 use super::*;
+use super::Config;
+use super::bdp::Bdp;
+use super::store::Resolve;
 
-use http::HeaderMap;
+use http::{HeaderMap, Method};
 
-use std::io;
+use std::{cmp, io};
 use std::marker::PhantomData;
 
 #[derive(Debug)]
@@ -31,6 +34,10 @@ pub(super) struct Recv<B, P>
     /// The stream ID of the last processed stream
     last_processed_id: StreamId,
 
+    /// The highest stream ID the remote may open. This is lowered once a
+    /// GOAWAY frame is sent.
+    max_stream_id: StreamId,
+
     /// Streams that have pending window updates
     pending_window_updates: store::Queue<B, stream::NextWindowUpdate, P>,
 
@@ -43,12 +50,27 @@ pub(super) struct Recv<B, P>
     /// Refused StreamId, this represents a frame that must be sent out.
     refused: Option<StreamId>,
 
+    /// If push promises are allowed to be recevied.
+    is_push_enabled: bool,
+
+    /// If extended CONNECT requests are allowed to be received.
+    is_extended_connect_protocol_enabled: bool,
+
+    /// If capacity is released when data is polled, unless overridden on the
+    /// stream
+    is_auto_release: bool,
+
+    /// Sizes the receive windows from the bandwidth-delay product, if adaptive
+    /// flow control is enabled.
+    bdp: Option<Bdp>,
+
     _p: PhantomData<B>,
 }
 
 #[derive(Debug)]
 pub(super) enum Event<T> {
     Headers(T),
+    PushPromise(Request<()>),
     Data(Bytes),
     Trailers(HeaderMap),
 }
@@ -84,9 +106,16 @@ impl<B, P> Recv<B, P>
             next_stream_id: next_stream_id.into(),
             pending_window_updates: store::Queue::new(),
             last_processed_id: StreamId::zero(),
+            max_stream_id: StreamId::max(),
             pending_accept: store::Queue::new(),
             buffer: Buffer::new(),
             refused: None,
+            is_push_enabled: config.local_push_enabled,
+            is_extended_connect_protocol_enabled: config.local_extended_connect_protocol,
+            is_auto_release: config.auto_release_capacity,
+            bdp: config.adaptive_window.map(|max| {
+                Bdp::new(config.init_remote_window_sz, max)
+            }),
             _p: PhantomData,
         }
     }
@@ -96,6 +125,71 @@ impl<B, P> Recv<B, P>
         self.init_window_sz
     }
 
+    /// Returns the connection's receive window
+    pub fn window_size(&self) -> WindowSize {
+        self.flow.window_size()
+    }
+
+    /// Applies local settings once they have been acknowledged by the peer.
+    pub fn apply_local_settings(&mut self,
+                                settings: &frame::Settings,
+                                store: &mut Store<B, P>)
+        -> Result<(), RecvError>
+    {
+        if let Some(val) = settings.max_concurrent_streams() {
+            self.max_streams = Some(val as usize);
+        }
+
+        // Per RFC 7540 §6.9.2, a change to SETTINGS_INITIAL_WINDOW_SIZE adjusts
+        // the windows of all existing streams by the difference between the
+        // new value and the old value. The peer has already applied the change
+        // by the time the ACK is received.
+        if let Some(val) = settings.initial_window_size() {
+            let old_val = self.init_window_sz;
+            self.init_window_sz = val;
+
+            if let Some(ref mut bdp) = self.bdp {
+                bdp.set_window_sz(val);
+            }
+
+            if val < old_val {
+                let dec = old_val - val;
+
+                trace!("decrementing all recv windows; dec={}", dec);
+
+                store.for_each(|mut stream| {
+                    stream.recv_flow.dec_recv_window(dec);
+                    Ok::<_, RecvError>(())
+                })?;
+            } else if val > old_val {
+                let inc = val - old_val;
+
+                trace!("incrementing all recv windows; inc={}", inc);
+
+                store.for_each(|mut stream| {
+                    stream.recv_flow.inc_window(inc)
+                        .map_err(RecvError::Connection)?;
+                    stream.recv_flow.assign_capacity(inc);
+                    Ok::<_, RecvError>(())
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stop accepting streams initiated by the remote, returning the ID of the
+    /// last processed stream to send in the GOAWAY frame.
+    pub fn go_away(&mut self) -> StreamId {
+        self.max_stream_id = self.last_processed_id;
+        self.last_processed_id
+    }
+
+    /// Returns the number of open, remotely initiated streams
+    pub fn num_streams(&self) -> usize {
+        self.num_streams
+    }
+
     /// Returns the ID of the last processed stream
     pub fn last_processed_id(&self) -> StreamId {
         self.last_processed_id
@@ -111,6 +205,14 @@ impl<B, P> Recv<B, P>
 
         try!(self.ensure_can_open(id));
 
+        if id > self.max_stream_id {
+            // A GOAWAY frame has been sent and the stream will never be
+            // processed.
+            trace!("refusing stream after GOAWAY; id={:?}", id);
+            self.refused = Some(id);
+            return Ok(None);
+        }
+
         if !self.can_inc_num_streams() {
             self.refused = Some(id);
             return Ok(None);
@@ -147,8 +249,11 @@ impl<B, P> Recv<B, P>
                 self.last_processed_id = frame.stream_id();
             }
 
-            // Increment the number of concurrent streams
-            self.inc_num_streams();
+            // Increment the number of concurrent streams. A pushed stream that
+            // is closed by its first HEADERS frame is never counted.
+            if stream.state.is_counted() {
+                self.inc_num_streams();
+            }
         }
 
         if !stream.content_length.is_head() {
@@ -167,6 +272,16 @@ impl<B, P> Recv<B, P>
             }
         }
 
+        // The :protocol pseudo header may only be received once extended
+        // CONNECT is enabled (RFC 8441 section 3).
+        if frame.pseudo().protocol.is_some() && !self.is_extended_connect_protocol_enabled {
+            debug!("extended CONNECT not enabled; stream={:?}", frame.stream_id());
+            return Err(RecvError::Stream {
+                id: frame.stream_id(),
+                reason: ProtocolError,
+            });
+        }
+
         let message = P::convert_poll_message(frame)?;
 
         // Push the frame onto the stream's recv buffer
@@ -236,6 +351,16 @@ impl<B, P> Recv<B, P>
         Ok(())
     }
 
+    /// Sets whether capacity is released when the stream's data is polled.
+    pub fn set_auto_release(&mut self, enabled: bool, stream: &mut store::Ptr<B, P>) {
+        stream.is_auto_release = Some(enabled);
+    }
+
+    /// Returns true if capacity is released when the stream's data is polled.
+    fn is_auto_release(&self, stream: &Stream<B, P>) -> bool {
+        stream.is_auto_release.unwrap_or(self.is_auto_release)
+    }
+
     pub fn body_is_empty(&self, stream: &store::Ptr<B, P>) -> bool {
         if !stream.state.is_recv_closed() {
             return false;
@@ -277,6 +402,10 @@ impl<B, P> Recv<B, P>
         // Update connection level flow control
         self.flow.send_data(sz);
 
+        if let Some(ref mut bdp) = self.bdp {
+            bdp.recv_data(sz);
+        }
+
         // Update stream level flow control
         stream.recv_flow.send_data(sz);
 
@@ -319,6 +448,13 @@ impl<B, P> Recv<B, P>
                              store: &mut Store<B, P>)
         -> Result<(), RecvError>
     {
+        use super::stream::ContentLength;
+
+        if !self.is_push_enabled {
+            // The remote sent a push promise even though it was told not to.
+            return Err(RecvError::Connection(ProtocolError));
+        }
+
         // First, make sure that the values are legit
         self.ensure_can_reserve(frame.promised_id())?;
 
@@ -339,20 +475,48 @@ impl<B, P> Recv<B, P>
 
         // TODO: All earlier stream IDs should be implicitly closed.
 
+        let promised_id = frame.promised_id();
+
         // Now, create a new entry for the stream
         let mut new_stream = Stream::new(
-            frame.promised_id(),
+            promised_id,
             send.init_window_sz(),
             self.init_window_sz);
 
         new_stream.state.reserve_remote()?;
 
+        // Store the stream before validating the promised request, so that it
+        // can be reset if the request is malformed.
+        let key = store.insert(promised_id, new_stream).key();
+
+        let (pseudo, fields) = frame.into_parts();
+        let headers = frame::Headers::new(promised_id, pseudo, fields);
+        let request = server::Peer::convert_poll_message(headers)?;
+
+        // Promised requests must be safe and cacheable
+        let is_head = match *request.method() {
+            Method::GET => false,
+            Method::HEAD => true,
+            _ => {
+                return Err(RecvError::Stream {
+                    id: promised_id,
+                    reason: ProtocolError,
+                });
+            }
+        };
+
         let mut ppp = store[stream].pending_push_promises.take();
 
         {
-            // Store the stream
-            let mut new_stream = store
-                .insert(frame.promised_id(), new_stream);
+            let mut new_stream = store.resolve(key);
+
+            if is_head {
+                new_stream.content_length = ContentLength::Head;
+            }
+
+            // The promised request is taken when the push is polled
+            new_stream.pending_recv.push_back(
+                &mut self.buffer, Event::PushPromise(request));
 
             ppp.push(&mut new_stream);
         }
@@ -453,6 +617,42 @@ impl<B, P> Recv<B, P>
         Ok(())
     }
 
+    /// Drive adaptive flow control.
+    ///
+    /// `ping` is called to send a PING when a new bandwidth-delay product
+    /// sample starts. When the sample shows the windows are too small, the
+    /// connection window grows and the new initial window size for streams is
+    /// returned, to be sent to the peer in a SETTINGS frame.
+    pub fn poll_window_tuning<F>(&mut self, ping: F) -> Option<WindowSize>
+        where F: FnOnce() -> UserPing,
+    {
+        let bdp = match self.bdp {
+            Some(ref mut bdp) => bdp,
+            None => return None,
+        };
+
+        let prev = bdp.conn_window_sz();
+
+        if let Some(window_sz) = bdp.poll_sample() {
+            // The WINDOW_UPDATE frame is sent with the next window updates
+            let inc = bdp.conn_window_sz() - prev;
+
+            if inc > 0 {
+                self.flow.assign_capacity(inc);
+            }
+
+            if window_sz > self.init_window_sz {
+                return Some(window_sz);
+            }
+        }
+
+        if bdp.needs_ping() {
+            bdp.start_sample(ping());
+        }
+
+        None
+    }
+
     /// Send any pending refusals.
     pub fn send_pending_refusal<T>(&mut self, dst: &mut Codec<T, Prioritized<B>>)
         -> Poll<(), io::Error>
@@ -493,7 +693,7 @@ impl<B, P> Recv<B, P>
         -> Poll<(), io::Error>
         where T: AsyncWrite,
     {
-        let incr = self.flow.unclaimed_capacity();
+        let incr = window_update_incr(&self.flow, self.is_auto_release);
 
         if incr > 0 {
             let frame = frame::WindowUpdate::new(StreamId::zero(), incr);
@@ -524,7 +724,7 @@ impl<B, P> Recv<B, P>
             try_ready!(dst.poll_ready());
 
             // Get the next stream
-            let stream = match self.pending_window_updates.pop(store) {
+            let mut stream = match self.pending_window_updates.pop(store) {
                 Some(stream) => stream,
                 None => return Ok(().into()),
             };
@@ -536,7 +736,7 @@ impl<B, P> Recv<B, P>
             }
 
             // TODO: de-dup
-            let incr = stream.recv_flow.unclaimed_capacity();
+            let incr = window_update_incr(&stream.recv_flow, self.is_auto_release(&stream));
 
             if incr > 0 {
                 // Create the WINDOW_UPDATE frame
@@ -544,6 +744,9 @@ impl<B, P> Recv<B, P>
 
                 // Buffer it
                 dst.buffer(frame.into()).ok().expect("invalid WINDOW_UPDATE frame");
+
+                // Update flow control
+                stream.recv_flow.inc_window(incr).ok().expect("unexpected flow control state");
             }
         }
     }
@@ -553,12 +756,23 @@ impl<B, P> Recv<B, P>
             .map(|ptr| ptr.key())
     }
 
-    pub fn poll_data(&mut self, stream: &mut Stream<B, P>)
+    pub fn poll_data(&mut self,
+                     stream: &mut store::Ptr<B, P>,
+                     task: &mut Option<Task>)
         -> Poll<Option<Bytes>, proto::Error>
     {
         // TODO: Return error when the stream is reset
         match stream.pending_recv.pop_front(&mut self.buffer) {
             Some(Event::Data(payload)) => {
+                if self.is_auto_release(stream) {
+                    // Data that was already released by the user is not
+                    // released again.
+                    let sz = cmp::min(payload.len() as WindowSize, stream.in_flight_recv_data);
+
+                    self.release_capacity(sz, stream, task)
+                        .ok().expect("invalid capacity release");
+                }
+
                 Ok(Some(payload).into())
             }
             Some(event) => {
@@ -625,6 +839,44 @@ impl<B> Recv<B, server::Peer>
 impl<B> Recv<B, client::Peer>
     where B: Buf,
 {
+    /// Returns the next stream pushed by the server along with its promised
+    /// request, or `None` once no more pushes may be promised on the stream.
+    pub fn poll_pushed(&mut self, stream: &mut store::Ptr<B, client::Peer>)
+        -> Poll<Option<(Request<()>, store::Key)>, proto::Error>
+    {
+        let mut ppp = stream.pending_push_promises.take();
+
+        let pushed = ppp.pop(stream).map(|mut pushed| {
+            match pushed.pending_recv.pop_front(&mut self.buffer) {
+                Some(Event::PushPromise(request)) => Ok((request, pushed.key())),
+                _ => Err(pushed.id),
+            }
+        });
+
+        stream.pending_push_promises = ppp;
+
+        match pushed {
+            Some(Ok(pushed)) => return Ok(Some(pushed).into()),
+            Some(Err(id)) => {
+                // The promised request is always the first event queued on a
+                // pushed stream.
+                debug!("pushed stream missing promised request; id={:?}", id);
+                return Err(Reason::InternalError.into());
+            }
+            None => {}
+        }
+
+        // PUSH_PROMISE frames are only received while the remote has not
+        // ended the stream.
+        if stream.state.is_recv_closed() {
+            stream.state.ensure_recv_open()?;
+            return Ok(None.into());
+        }
+
+        stream.recv_task = Some(task::current());
+        Ok(Async::NotReady)
+    }
+
     pub fn poll_response(&mut self, stream: &mut store::Ptr<B, client::Peer>)
         -> Poll<Response<()>, proto::Error> {
         // If the buffer is not empty, then the first frame must be a HEADERS
@@ -657,6 +909,20 @@ impl<T> Event<T> {
 
 // ===== util =====
 
+/// Returns the capacity to claim with a WINDOW_UPDATE frame.
+///
+/// When capacity is released automatically, updates are batched: none is sent
+/// until at least half of the window can be claimed.
+fn window_update_incr(flow: &FlowControl, is_batched: bool) -> WindowSize {
+    let incr = flow.unclaimed_capacity();
+
+    if is_batched && incr < flow.available() / 2 {
+        return 0;
+    }
+
+    incr
+}
+
 fn parse_u64(src: &[u8]) -> Result<u64, ()> {
     if src.len() > 19 {
         // At danger for overflow...
@@ -4,6 +4,7 @@ use codec::{RecvError, UserError};
 use codec::UserError::*;
 use proto::*;
 use super::*;
+use super::Config;
 
 use bytes::Buf;
 
@@ -29,6 +30,19 @@ pub(super) struct Send<B, P>
     /// Task awaiting notification to open a new stream.
     blocked_open: Option<task::Task>,
 
+    /// Set once the remote sent GOAWAY. No new streams may be opened.
+    is_going_away: bool,
+
+    /// If the remote is willing to receive push promises
+    is_push_enabled: bool,
+
+    /// The remote's SETTINGS_NO_RFC7540_PRIORITIES, set once the remote's
+    /// first SETTINGS frame has been applied.
+    remote_no_rfc7540_priorities: Option<u32>,
+
+    /// If the remote accepts extended CONNECT requests
+    is_extended_connect_protocol_enabled: bool,
+
     /// Prioritization layer
     prioritize: Prioritize<B, P>,
 }
@@ -47,15 +61,34 @@ where B: Buf,
             next_stream_id: next_stream_id.into(),
             init_window_sz: config.init_local_window_sz,
             blocked_open: None,
+            is_going_away: false,
+            is_push_enabled: true,
+            remote_no_rfc7540_priorities: None,
+            is_extended_connect_protocol_enabled: false,
             prioritize: Prioritize::new(config),
         }
     }
 
+    /// Returns true if the remote accepts extended CONNECT requests
+    pub fn is_extended_connect_protocol_enabled(&self) -> bool {
+        self.is_extended_connect_protocol_enabled
+    }
+
     /// Returns the initial send window size
     pub fn init_window_sz(&self) -> WindowSize {
         self.init_window_sz
     }
 
+    /// Returns the connection's send window
+    pub fn window_size(&self) -> WindowSize {
+        self.prioritize.window_size()
+    }
+
+    /// Returns the number of open, locally initiated streams
+    pub fn num_streams(&self) -> usize {
+        self.num_streams
+    }
+
     /// Update state reflecting a new, locally opened stream
     ///
     /// Returns the stream state if successful. `None` if refused
@@ -79,6 +112,59 @@ where B: Buf,
         Ok(ret)
     }
 
+    /// Reserve a locally initiated stream for a server push.
+    ///
+    /// Returns the promised stream ID if successful.
+    pub fn reserve_local(&mut self) -> Result<StreamId, UserError> {
+        debug_assert!(P::is_server());
+
+        if !self.is_push_enabled {
+            return Err(PushDisabled);
+        }
+
+        if self.is_going_away {
+            return Err(GoingAway);
+        }
+
+        if let Some(max) = self.max_streams {
+            if max <= self.num_streams {
+                return Err(Rejected.into());
+            }
+        }
+
+        let ret = self.next_stream_id;
+        self.next_stream_id.increment();
+
+        Ok(ret)
+    }
+
+    /// Reserve the promised stream of a server push, associated with the
+    /// stream `associated_id`.
+    pub fn reserve_push(&mut self,
+                        stream: &mut store::Ptr<B, P>,
+                        associated_id: StreamId)
+        -> Result<(), UserError>
+    {
+        stream.state.reserve_local()?;
+        self.prioritize.reserve_push(stream, associated_id);
+
+        Ok(())
+    }
+
+    pub fn send_push_promise(&mut self,
+                             frame: frame::PushPromise,
+                             stream: &mut store::Ptr<B, P>,
+                             task: &mut Option<Task>)
+    {
+        trace!("send_push_promise; frame={:?}", frame);
+
+        // The frame is queued on the associated stream, after the frames
+        // already sent on it and before any frame sent on it later, as
+        // required by RFC 7540 section 8.2.1. The promised stream is held back
+        // until the frame is written.
+        self.prioritize.queue_frame(frame.into(), stream, task);
+    }
+
     pub fn send_headers(&mut self,
                         frame: frame::Headers,
                         stream: &mut store::Ptr<B, P>,
@@ -86,15 +172,64 @@ where B: Buf,
         -> Result<(), UserError>
     {
         trace!("send_headers; frame={:?}; init_window={:?}", frame, self.init_window_sz);
+        let is_reserved = stream.state.is_reserved_local();
+
+        // Reserved streams don't count against the concurrency limit until
+        // the pushed response is sent, so the limit is checked once the
+        // stream opens.
+        if is_reserved && !frame.is_end_stream() {
+            if let Some(max) = self.max_streams {
+                if max <= self.num_streams {
+                    return Err(Rejected.into());
+                }
+            }
+        }
+
         // Update the state
         stream.state.send_open(frame.is_end_stream())?;
 
+        if is_reserved && stream.state.is_counted() {
+            self.num_streams += 1;
+        }
+
+        // The RFC 7540 dependency sent with the headers is a hint for the
+        // remote's scheduler, not ours, so only the stream is tracked.
+        self.prioritize.open(frame.stream_id(), None);
+
+        if let Some(params) = frame.priority() {
+            self.prioritize.set_priority(params, stream);
+        }
+
         // Queue the frame for sending
         self.prioritize.queue_frame(frame.into(), stream, task);
 
         Ok(())
     }
 
+    /// Open the stream of an h2c upgrade request, which was sent over
+    /// HTTP/1.1 in full.
+    pub fn send_upgrade(&mut self, stream: &mut store::Ptr<B, P>)
+        -> Result<(), UserError>
+    {
+        stream.state.send_open(true)?;
+
+        self.prioritize.open(stream.id, None);
+
+        Ok(())
+    }
+
+    /// Change the priority of a stream, notifying the remote with a PRIORITY
+    /// frame.
+    pub fn send_priority(&mut self,
+                         frame: frame::Priority,
+                         stream: &mut store::Ptr<B, P>,
+                         task: &mut Option<Task>)
+    {
+        trace!("send_priority; frame={:?}", frame);
+
+        self.prioritize.queue_priority(frame, stream, task);
+    }
+
     pub fn send_reset(&mut self,
                       reason: Reason,
                       stream: &mut store::Ptr<B, P>,
@@ -181,6 +316,7 @@ where B: Buf,
         }
 
         if !stream.send_capacity_inc {
+            stream.send_task = Some(task::current());
             return Ok(Async::NotReady);
         }
 
@@ -192,7 +328,7 @@ where B: Buf,
     /// Current available stream send capacity
     pub fn capacity(&self, stream: &mut store::Ptr<B, P>) -> WindowSize {
         let available = stream.send_flow.available();
-        let buffered = stream.buffered_send_data;
+        let buffered = stream.buffered_send_data + stream.buffered_send_padding;
 
         if available <= buffered {
             0
@@ -235,6 +371,36 @@ where B: Buf,
             self.max_streams = Some(val as usize);
         }
 
+        if let Some(val) = settings.enable_push() {
+            self.is_push_enabled = val != 0;
+        }
+
+        // Extended CONNECT may not be disabled once it was enabled (RFC 8441
+        // section 3).
+        if let Some(val) = settings.enable_connect_protocol() {
+            if self.is_extended_connect_protocol_enabled && val == 0 {
+                debug!("remote disabled SETTINGS_ENABLE_CONNECT_PROTOCOL");
+                return Err(RecvError::Connection(ProtocolError));
+            }
+
+            self.is_extended_connect_protocol_enabled = val != 0;
+        }
+
+        // The value of SETTINGS_NO_RFC7540_PRIORITIES is fixed by the first
+        // SETTINGS frame (RFC 9218 section 2.1).
+        match self.remote_no_rfc7540_priorities {
+            Some(prev) => {
+                if settings.no_rfc7540_priorities().map_or(false, |val| val != prev) {
+                    debug!("remote changed SETTINGS_NO_RFC7540_PRIORITIES");
+                    return Err(RecvError::Connection(ProtocolError));
+                }
+            }
+            None => {
+                let val = settings.no_rfc7540_priorities().unwrap_or(0);
+                self.remote_no_rfc7540_priorities = Some(val);
+            }
+        }
+
         // Applies an update to the remote endpoint's initial window size.
         //
         // Per RFC 7540 §6.9.2:
@@ -290,6 +456,41 @@ where B: Buf,
         Ok(())
     }
 
+    /// Track the priority of a stream opened by the remote's HEADERS frame.
+    pub fn recv_open(&mut self, frame: &frame::Headers, stream: &mut store::Ptr<B, P>) {
+        self.prioritize.open(frame.stream_id(), frame.stream_dep());
+
+        // A PRIORITY_UPDATE received before the stream opened takes precedence
+        // over the `priority` header.
+        let params = self.prioritize.take_priority_update(stream.id)
+            .or_else(|| frame.priority());
+
+        if let Some(params) = params {
+            self.prioritize.set_priority(params, stream);
+        }
+    }
+
+    pub fn recv_priority_update(&mut self,
+                                params: frame::PriorityParams,
+                                stream: &mut store::Ptr<B, P>)
+    {
+        self.prioritize.set_priority(params, stream);
+    }
+
+    /// Save a PRIORITY_UPDATE received for a stream that is not open yet.
+    pub fn recv_idle_priority_update(&mut self, frame: &frame::PriorityUpdate) {
+        self.prioritize.buffer_priority_update(frame.prioritized_id(), frame.params());
+    }
+
+    pub fn recv_priority(&mut self, frame: &frame::Priority) {
+        self.prioritize.reprioritize(frame.stream_id(), frame.dependency());
+    }
+
+    /// Called once the stream is closed
+    pub fn stream_closed(&mut self, id: StreamId) {
+        self.prioritize.close(id);
+    }
+
     pub fn ensure_not_idle(&self, id: StreamId) -> Result<(), Reason> {
         if id >= self.next_stream_id {
             return Err(ProtocolError);
@@ -298,6 +499,17 @@ where B: Buf,
         Ok(())
     }
 
+    /// Stop opening new streams after receiving GOAWAY.
+    pub fn recv_go_away(&mut self) {
+        self.is_going_away = true;
+
+        // Wake any task waiting to open a stream so that it observes the
+        // error.
+        if let Some(task) = self.blocked_open.take() {
+            task.notify();
+        }
+    }
+
     pub fn dec_num_streams(&mut self) {
         self.num_streams -= 1;
 
@@ -315,6 +527,10 @@ where B: Buf,
             return Err(UnexpectedFrameType);
         }
 
+        if self.is_going_away {
+            return Err(GoingAway);
+        }
+
         // TODO: Handle StreamId overflow
 
         Ok(())
@@ -325,6 +541,11 @@ impl<B> Send<B, client::Peer>
 where B: Buf,
 {
     pub fn poll_open_ready(&mut self) -> Async<()> {
+        if self.is_going_away {
+            // Opening a stream will fail immediately
+            return Async::Ready(());
+        }
+
         if let Some(max) = self.max_streams {
             if max <= self.num_streams {
                 self.blocked_open = Some(task::current());
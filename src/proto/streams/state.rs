@@ -4,6 +4,8 @@ use codec::{RecvError, UserError};
 use codec::UserError::*;
 use proto;
 
+use bytes::Bytes;
+
 use self::Inner::*;
 use self::Peer::*;
 
@@ -52,11 +54,11 @@ pub struct State {
     inner: Inner,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 enum Inner {
     Idle,
     // TODO: these states shouldn't count against concurrency limits:
-    //ReservedLocal,
+    ReservedLocal,
     ReservedRemote,
     Open {
         local: Peer,
@@ -74,9 +76,14 @@ enum Peer {
     Streaming,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 enum Cause {
     Proto(Reason),
+    /// The remote sent GOAWAY with an error code and debug data
+    GoAway(Bytes, Reason),
+    /// Not processed by the remote before it sent GOAWAY, carrying the
+    /// GOAWAY's error code and debug data
+    Refused(Bytes, Reason),
     Io,
 }
 
@@ -113,6 +120,14 @@ impl State {
                     HalfClosedRemote(local)
                 }
             }
+            ReservedLocal => {
+                // The remote never sends frames on a pushed stream
+                if eos {
+                    Closed(None)
+                } else {
+                    HalfClosedRemote(local)
+                }
+            }
             _ => {
                 // All other transitions result in a protocol error
                 return Err(UnexpectedFrameType);
@@ -181,6 +196,17 @@ impl State {
         return Ok(initial);
     }
 
+    /// Transition from Idle -> ReservedLocal
+    pub fn reserve_local(&mut self) -> Result<(), UserError> {
+        match self.inner {
+            Idle => {
+                self.inner = ReservedLocal;
+                Ok(())
+            }
+            _ => Err(UnexpectedFrameType),
+        }
+    }
+
     /// Transition from Idle -> ReservedRemote
     pub fn reserve_remote(&mut self) -> Result<(), RecvError> {
         match self.inner {
@@ -219,6 +245,12 @@ impl State {
                 trace!("recv_err; err={:?}", err);
                 self.inner = Closed(match *err {
                     Proto(reason) => Some(Cause::Proto(reason)),
+                    GoAway(ref debug_data, reason) => {
+                        Some(Cause::GoAway(debug_data.clone(), reason))
+                    }
+                    Refused(ref debug_data, reason) => {
+                        Some(Cause::Refused(debug_data.clone(), reason))
+                    }
                     Io(..) => Some(Cause::Io),
                 });
             }
@@ -266,6 +298,22 @@ impl State {
         }
     }
 
+    pub fn is_reserved_local(&self) -> bool {
+        match self.inner {
+            ReservedLocal => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the local side has not yet ended the stream.
+    pub fn is_local_open(&self) -> bool {
+        match self.inner {
+            Open { .. } => true,
+            HalfClosedRemote(..) => true,
+            _ => false,
+        }
+    }
+
     pub fn is_send_streaming(&self) -> bool {
         match self.inner {
             Open { local: Peer::Streaming, .. } => true,
@@ -278,6 +326,7 @@ impl State {
     pub fn is_recv_headers(&self) -> bool {
         match self.inner {
             Idle => true,
+            ReservedRemote => true,
             Open { remote: AwaitingHeaders, .. } => true,
             HalfClosedLocal(AwaitingHeaders) => true,
             _ => false,
@@ -314,6 +363,12 @@ impl State {
             Closed(Some(Cause::Proto(reason))) => {
                 Err(proto::Error::Proto(reason))
             }
+            Closed(Some(Cause::GoAway(ref debug_data, reason))) => {
+                Err(proto::Error::GoAway(debug_data.clone(), reason))
+            }
+            Closed(Some(Cause::Refused(ref debug_data, reason))) => {
+                Err(proto::Error::Refused(debug_data.clone(), reason))
+            }
             Closed(Some(Cause::Io)) => {
                 Err(proto::Error::Io(io::ErrorKind::BrokenPipe.into()))
             }
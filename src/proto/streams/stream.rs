@@ -12,12 +12,6 @@ pub(super) struct Stream<B, P>
 
     // ===== Fields related to sending =====
 
-    /// Next node in the accept linked list
-    pub next_pending_send: Option<store::Key>,
-
-    /// Set to true when the stream is pending accept
-    pub is_pending_send: bool,
-
     /// Send data flow control
     pub send_flow: FlowControl,
 
@@ -28,6 +22,9 @@ pub(super) struct Stream<B, P>
     /// TODO: Technically this could be greater than the window size...
     pub buffered_send_data: WindowSize,
 
+    /// Amount of padding expected to be sent along with the buffered data.
+    pub buffered_send_padding: WindowSize,
+
     /// Task tracking additional send capacity (i.e. window updates).
     pub send_task: Option<task::Task>,
 
@@ -44,6 +41,15 @@ pub(super) struct Stream<B, P>
     /// Set to true when the send capacity has been incremented
     pub send_capacity_inc: bool,
 
+    /// The stream's extensible priority, used when scheduling with urgency
+    pub priority: frame::PriorityParams,
+
+    /// True if the stream is waiting for the scheduler to send its frames
+    pub is_scheduled: bool,
+
+    /// True until the PUSH_PROMISE reserving the stream has been sent
+    pub is_pending_push: bool,
+
     // ===== Fields related to receiving =====
 
     /// Next node in the accept linked list
@@ -57,6 +63,10 @@ pub(super) struct Stream<B, P>
 
     pub in_flight_recv_data: WindowSize,
 
+    /// If capacity is released when data is polled. The connection's default
+    /// is used if `None`.
+    pub is_auto_release: Option<bool>,
+
     /// Next node in the linked list of streams waiting to send window updates.
     pub next_window_update: Option<store::Key>,
 
@@ -88,9 +98,6 @@ pub enum ContentLength {
 #[derive(Debug)]
 pub(super) struct NextAccept;
 
-#[derive(Debug)]
-pub(super) struct NextSend;
-
 #[derive(Debug)]
 pub(super) struct NextSendCapacity;
 
@@ -120,16 +127,18 @@ impl<B, P> Stream<B, P>
 
             // ===== Fields related to sending =====
 
-            next_pending_send: None,
-            is_pending_send: false,
             send_flow: send_flow,
             requested_send_capacity: 0,
             buffered_send_data: 0,
+            buffered_send_padding: 0,
             send_task: None,
             pending_send: buffer::Deque::new(),
             is_pending_send_capacity: false,
             next_pending_send_capacity: None,
             send_capacity_inc: false,
+            priority: frame::PriorityParams::default(),
+            is_scheduled: false,
+            is_pending_push: false,
 
             // ===== Fields related to receiving =====
 
@@ -137,6 +146,7 @@ impl<B, P> Stream<B, P>
             is_pending_accept: false,
             recv_flow: recv_flow,
             in_flight_recv_data: 0,
+            is_auto_release: None,
             next_window_update: None,
             is_pending_window_update: false,
             pending_recv: buffer::Deque::new(),
@@ -152,7 +162,7 @@ impl<B, P> Stream<B, P>
         self.send_flow.assign_capacity(capacity);
 
         // Only notify if the capacity exceeds the amount of buffered data
-        if self.send_flow.available() > self.buffered_send_data {
+        if self.send_flow.available() > self.buffered_send_data + self.buffered_send_padding {
             self.notify_send();
         }
     }
@@ -216,28 +226,6 @@ impl store::Next for NextAccept {
     }
 }
 
-impl store::Next for NextSend {
-    fn next<B, P: Peer>(stream: &Stream<B, P>) -> Option<store::Key> {
-        stream.next_pending_send
-    }
-
-    fn set_next<B, P: Peer>(stream: &mut Stream<B, P>, key: Option<store::Key>) {
-        stream.next_pending_send = key;
-    }
-
-    fn take_next<B, P: Peer>(stream: &mut Stream<B, P>) -> Option<store::Key> {
-        stream.next_pending_send.take()
-    }
-
-    fn is_queued<B, P: Peer>(stream: &Stream<B, P>) -> bool {
-        stream.is_pending_send
-    }
-
-    fn set_queued<B, P: Peer>(stream: &mut Stream<B, P>, val: bool) {
-        stream.is_pending_send = val;
-    }
-}
-
 impl store::Next for NextSendCapacity {
     fn next<B, P: Peer>(stream: &Stream<B, P>) -> Option<store::Key> {
         stream.next_pending_send_capacity
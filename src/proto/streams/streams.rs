@@ -1,11 +1,13 @@
-use {client, server, proto};
+use {client, server, proto, Protocol};
 use frame::Reason;
 use codec::{SendError, RecvError, UserError};
+use stats::FlowStats;
 use proto::*;
 use super::*;
+use super::Config;
 use super::store::Resolve;
 
-use http::HeaderMap;
+use http::{HeaderMap, Method};
 
 use std::io;
 use std::sync::{Arc, Mutex};
@@ -94,7 +96,10 @@ impl<B, P> Streams<B, P>
             }
         };
 
-        let stream = me.store.resolve(key);
+        let mut stream = me.store.resolve(key);
+
+        // Track the stream's priority
+        me.actions.send.recv_open(&frame, &mut stream);
 
         me.actions.transition(stream, |actions, stream| {
             let res = if stream.state.is_recv_headers() {
@@ -175,6 +180,55 @@ impl<B, P> Streams<B, P>
         })
     }
 
+    pub fn recv_priority(&mut self, frame: frame::Priority)
+        -> Result<(), RecvError>
+    {
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        if frame.stream_id().is_zero() {
+            return Err(RecvError::Connection(ProtocolError));
+        }
+
+        me.actions.send.recv_priority(&frame);
+
+        Ok(())
+    }
+
+    pub fn recv_priority_update(&mut self, frame: frame::PriorityUpdate)
+        -> Result<(), RecvError>
+    {
+        let id = frame.prioritized_id();
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        // Only clients send PRIORITY_UPDATE frames (RFC 9218 section 7.1).
+        if !P::is_server() {
+            return Err(RecvError::Connection(ProtocolError));
+        }
+
+        // Clients may also reprioritize pushed streams, but only ones that
+        // have been promised.
+        if id.is_server_initiated() && me.actions.send.ensure_not_idle(id).is_err() {
+            return Err(RecvError::Connection(ProtocolError));
+        }
+
+        match me.store.find_mut(&id) {
+            Some(mut stream) => {
+                me.actions.send.recv_priority_update(frame.params(), &mut stream);
+            }
+            None => {
+                // Updates for request streams the client has not opened yet
+                // are applied once they open. Closed streams are ignored.
+                if id.is_client_initiated() && me.actions.recv.ensure_not_idle(id).is_err() {
+                    me.actions.send.recv_idle_priority_update(&frame);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Handle a received error and return the ID of the last processed stream.
     pub fn recv_err(&mut self, err: &proto::Error) -> StreamId {
         let mut me = self.inner.lock().unwrap();
@@ -191,6 +245,54 @@ impl<B, P> Streams<B, P>
         last_processed_id
     }
 
+    /// Handle a received GOAWAY frame.
+    ///
+    /// No new streams may be opened, and locally initiated streams with IDs
+    /// above the frame's last stream ID were not processed by the remote.
+    /// These are failed with a retryable error carrying the frame's error code
+    /// and debug data.
+    pub fn recv_go_away(&mut self, frame: &frame::GoAway) {
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        let actions = &mut me.actions;
+        let last_stream_id = frame.last_stream_id();
+        let err = proto::Error::Refused(frame.debug_data().clone(), frame.reason());
+
+        actions.send.recv_go_away();
+
+        me.store.for_each(|stream| {
+            let is_local = stream.id.is_server_initiated() == P::is_server();
+
+            if is_local && stream.id > last_stream_id {
+                actions.transition(stream, |actions, stream| {
+                    actions.recv.recv_err(&err, stream);
+                });
+            }
+
+            Ok::<_, ()>(())
+        }).ok().expect("unexpected error processing GOAWAY");
+    }
+
+    /// Stop accepting new remote streams, returning the ID of the last
+    /// processed stream.
+    pub fn go_away(&mut self) -> StreamId {
+        let mut me = self.inner.lock().unwrap();
+        me.actions.recv.go_away()
+    }
+
+    /// Returns true if the remote accepts extended CONNECT requests
+    pub fn is_extended_connect_protocol_enabled(&self) -> bool {
+        let me = self.inner.lock().unwrap();
+        me.actions.send.is_extended_connect_protocol_enabled()
+    }
+
+    /// Returns true if there are any open streams
+    pub fn has_open_streams(&self) -> bool {
+        let me = self.inner.lock().unwrap();
+        me.actions.send.num_streams() > 0 || me.actions.recv.num_streams() > 0
+    }
+
     pub fn recv_window_update(&mut self, frame: frame::WindowUpdate)
         -> Result<(), RecvError>
     {
@@ -255,6 +357,38 @@ impl<B, P> Streams<B, P>
         })
     }
 
+    /// Returns the connection's flow control state.
+    pub fn flow_stats(&mut self) -> FlowStats {
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        let mut buffered_send_data = 0;
+        let mut unreleased_recv_data = 0;
+
+        me.store.for_each(|stream| {
+            buffered_send_data += stream.buffered_send_data as usize;
+            unreleased_recv_data += stream.in_flight_recv_data as usize;
+            Ok::<_, ()>(())
+        }).ok().expect("unexpected error collecting flow stats");
+
+        FlowStats::new(
+            me.actions.send.window_size(),
+            me.actions.recv.window_size(),
+            buffered_send_data,
+            unreleased_recv_data)
+    }
+
+    /// Drive adaptive flow control, returning the new initial window size of
+    /// streams if it should grow.
+    pub fn poll_window_tuning<F>(&mut self, ping: F) -> Option<WindowSize>
+        where F: FnOnce() -> UserPing,
+    {
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        me.actions.recv.poll_window_tuning(ping)
+    }
+
     pub fn send_pending_refusal<T>(&mut self, dst: &mut Codec<T, Prioritized<B>>)
         -> Poll<(), io::Error>
         where T: AsyncWrite,
@@ -296,10 +430,21 @@ impl<B, P> Streams<B, P>
             frame, &mut me.store, &mut me.actions.task)
     }
 
-    pub fn send_request(&mut self, request: Request<()>, end_of_stream: bool)
+    pub fn apply_local_settings(&mut self, frame: &frame::Settings)
+        -> Result<(), RecvError>
+    {
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        me.actions.recv.apply_local_settings(frame, &mut me.store)
+    }
+
+    pub fn send_request(&mut self,
+                        request: Request<()>,
+                        stream_dep: Option<frame::StreamDependency>,
+                        end_of_stream: bool)
         -> Result<StreamRef<B, P>, SendError>
     {
-        use http::Method;
         use super::stream::ContentLength;
 
         // TODO: There is a hazard with assigning a stream ID before the
@@ -311,6 +456,26 @@ impl<B, P> Streams<B, P>
             let mut me = self.inner.lock().unwrap();
             let me = &mut *me;
 
+            let is_extended_connect = request.extensions().get::<Protocol>().is_some();
+
+            // The peer would treat these as malformed, so they are rejected
+            // before a stream ID is assigned.
+            if *request.method() == Method::CONNECT {
+                if request.uri().authority().is_none() {
+                    return Err(UserError::MissingConnectAuthority.into());
+                }
+            } else if is_extended_connect {
+                return Err(UserError::ProtocolWithoutConnect.into());
+            }
+
+            // Extended CONNECT requests may only be sent once the remote
+            // enabled them.
+            if is_extended_connect {
+                if !me.actions.send.is_extended_connect_protocol_enabled() {
+                    return Err(UserError::ExtendedConnectDisabled.into());
+                }
+            }
+
             // Initialize a new stream. This fails if the connection is at capacity.
             let stream_id = me.actions.send.open()?;
 
@@ -324,9 +489,13 @@ impl<B, P> Streams<B, P>
             }
 
             // Convert the message
-            let headers = client::Peer::convert_send_message(
+            let mut headers = client::Peer::convert_send_message(
                 stream_id, request, end_of_stream);
 
+            if let Some(stream_dep) = stream_dep {
+                headers.set_stream_dep(stream_dep);
+            }
+
             let mut stream = me.store.insert(stream.id, stream);
 
             me.actions.send.send_headers(
@@ -345,6 +514,44 @@ impl<B, P> Streams<B, P>
         })
     }
 
+    /// Initialize stream 1 for the request of an h2c upgrade.
+    ///
+    /// The request was already sent over HTTP/1.1, so the stream is opened
+    /// half-closed (local) without sending a HEADERS frame.
+    pub fn send_upgrade(&mut self, method: &Method)
+        -> Result<StreamRef<B, P>, SendError>
+    {
+        use super::stream::ContentLength;
+
+        let key = {
+            let mut me = self.inner.lock().unwrap();
+            let me = &mut *me;
+
+            let stream_id = me.actions.send.open()?;
+            debug_assert_eq!(stream_id, StreamId::from(1));
+
+            let mut stream = Stream::new(
+                stream_id,
+                me.actions.send.init_window_sz(),
+                me.actions.recv.init_window_sz());
+
+            if *method == Method::HEAD {
+                stream.content_length = ContentLength::Head;
+            }
+
+            let mut stream = me.store.insert(stream.id, stream);
+
+            me.actions.send.send_upgrade(&mut stream)?;
+
+            stream.key()
+        };
+
+        Ok(StreamRef {
+            inner: self.inner.clone(),
+            key: key,
+        })
+    }
+
     pub fn send_reset(&mut self, id: StreamId, reason: Reason) {
         let mut me = self.inner.lock().unwrap();
         let me = &mut *me;
@@ -390,6 +597,13 @@ impl<B, P> StreamRef<B, P>
     where B: Buf,
           P: Peer,
 {
+    pub fn stream_id(&self) -> StreamId {
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        me.store.resolve(self.key).id
+    }
+
     pub fn send_data(&mut self, data: B, end_stream: bool)
         -> Result<(), UserError>
     {
@@ -466,7 +680,7 @@ impl<B, P> StreamRef<B, P>
 
         let mut stream = me.store.resolve(self.key);
 
-        me.actions.recv.poll_data(&mut stream)
+        me.actions.recv.poll_data(&mut stream, &mut me.actions.task)
     }
 
     pub fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, proto::Error> {
@@ -492,6 +706,30 @@ impl<B, P> StreamRef<B, P>
             capacity, &mut stream, &mut me.actions.task)
     }
 
+    /// Returns the stream's flow control state.
+    pub fn flow_stats(&self) -> FlowStats {
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        let stream = me.store.resolve(self.key);
+
+        FlowStats::new(
+            stream.send_flow.window_size(),
+            stream.recv_flow.window_size(),
+            stream.buffered_send_data as usize,
+            stream.in_flight_recv_data as usize)
+    }
+
+    /// Sets whether recv capacity is released when data is polled.
+    pub fn set_auto_release_capacity(&mut self, enabled: bool) {
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        let mut stream = me.store.resolve(self.key);
+
+        me.actions.recv.set_auto_release(enabled, &mut stream)
+    }
+
     /// Request capacity to send data
     pub fn reserve_capacity(&mut self, capacity: WindowSize) {
         let mut me = self.inner.lock().unwrap();
@@ -540,6 +778,72 @@ impl<B> StreamRef<B, server::Peer>
         let mut stream = me.store.resolve(self.key);
         me.actions.recv.take_request(&mut stream)
     }
+
+    /// Reserve a new stream for a server push associated with this stream,
+    /// queuing a PUSH_PROMISE frame for the promised request.
+    pub fn push_request(&mut self, request: Request<()>)
+        -> Result<StreamRef<B, server::Peer>, UserError>
+    {
+        use http::Method;
+        use super::stream::ContentLength;
+
+        let key = {
+            let mut me = self.inner.lock().unwrap();
+            let me = &mut *me;
+
+            let stream_id = {
+                let stream = me.store.resolve(self.key);
+
+                // PUSH_PROMISE may only be sent on a stream that the local
+                // has not yet ended.
+                if !stream.state.is_local_open() {
+                    return Err(UserError::UnexpectedFrameType);
+                }
+
+                stream.id
+            };
+
+            // Promised requests must be safe and cacheable
+            let is_head = match *request.method() {
+                Method::GET => false,
+                Method::HEAD => true,
+                _ => return Err(UserError::UnsafePushRequest),
+            };
+
+            let promised_id = me.actions.send.reserve_local()?;
+
+            let frame = server::Peer::convert_push_message(
+                stream_id, promised_id, request);
+
+            let mut stream = Stream::new(
+                promised_id,
+                me.actions.send.init_window_sz(),
+                me.actions.recv.init_window_sz());
+
+            if is_head {
+                stream.content_length = ContentLength::Head;
+            }
+
+            let promised = {
+                let mut stream = me.store.insert(stream.id, stream);
+                me.actions.send.reserve_push(&mut stream, stream_id)?;
+                stream.key()
+            };
+
+            // The PUSH_PROMISE is sent on the associated stream
+            let mut stream = me.store.resolve(self.key);
+
+            me.actions.send.send_push_promise(
+                frame, &mut stream, &mut me.actions.task);
+
+            promised
+        };
+
+        Ok(StreamRef {
+            inner: self.inner.clone(),
+            key: key,
+        })
+    }
 }
 
 impl<B> StreamRef<B, client::Peer>
@@ -553,6 +857,46 @@ impl<B> StreamRef<B, client::Peer>
 
         me.actions.recv.poll_response(&mut stream)
     }
+
+    /// Changes the priority of the stream, sending a PRIORITY frame.
+    ///
+    /// A stream cannot depend on itself, so such a change is ignored.
+    pub fn set_priority(&mut self, stream_dep: frame::StreamDependency) {
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        let mut stream = me.store.resolve(self.key);
+
+        if stream_dep.dependency_id() == stream.id {
+            trace!("ignoring self-dependency; id={:?}", stream.id);
+            return;
+        }
+
+        let frame = frame::Priority::new(stream.id, stream_dep);
+
+        me.actions.send.send_priority(frame, &mut stream, &mut me.actions.task)
+    }
+
+    /// Returns the next stream pushed by the server on this stream.
+    pub fn poll_pushed(&mut self)
+        -> Poll<Option<(Request<()>, StreamRef<B, client::Peer>)>, proto::Error>
+    {
+        let mut me = self.inner.lock().unwrap();
+        let me = &mut *me;
+
+        let mut stream = me.store.resolve(self.key);
+
+        let pushed = try_ready!(me.actions.recv.poll_pushed(&mut stream));
+
+        Ok(pushed.map(|(request, key)| {
+            let pushed = StreamRef {
+                inner: self.inner.clone(),
+                key: key,
+            };
+
+            (request, pushed)
+        }).into())
+    }
 }
 
 impl<B, P> Clone for StreamRef<B, P>
@@ -599,6 +943,7 @@ impl<B, P> Actions<B, P>
         where F: FnOnce(&mut Self, &mut store::Ptr<B, P>) -> U,
     {
         let is_counted = stream.state.is_counted();
+        let is_closed = stream.state.is_closed();
 
         let ret = f(self, &mut stream);
 
@@ -606,6 +951,10 @@ impl<B, P> Actions<B, P>
             self.dec_num_streams(stream.id);
         }
 
+        if !is_closed && stream.state.is_closed() {
+            self.send.stream_closed(stream.id);
+        }
+
         ret
     }
 }
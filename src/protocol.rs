@@ -0,0 +1,102 @@
+use bytes::Bytes;
+use string::String;
+
+use std::{error, fmt};
+
+/// The `:protocol` pseudo header of an extended CONNECT request (RFC 8441),
+/// naming the protocol spoken over the tunnel, such as `websocket`.
+///
+/// Clients set it in the extensions of a CONNECT request, which may only be
+/// sent once the server enabled extended CONNECT. Servers find it in the
+/// extensions of the extended CONNECT requests they receive.
+#[derive(Clone, Eq, PartialEq)]
+pub struct Protocol {
+    value: String<Bytes>,
+}
+
+/// Error returned when converting a string that is not a valid token to a
+/// `Protocol`.
+#[derive(Debug)]
+pub struct InvalidProtocol {
+    _priv: (),
+}
+
+// ===== impl Protocol =====
+
+impl Protocol {
+    /// Converts a static string to a `Protocol`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` is not a valid token (RFC 7230 section 3.2.6).
+    pub fn from_static(value: &'static str) -> Self {
+        assert!(is_token(value.as_bytes()), "invalid protocol");
+
+        let value = Bytes::from_static(value.as_bytes());
+        Protocol { value: unsafe { String::from_utf8_unchecked(value) } }
+    }
+
+    /// Converts a string to a `Protocol`.
+    ///
+    /// Fails if `src` is not a valid token (RFC 7230 section 3.2.6), that is
+    /// if it is empty or contains anything but visible ASCII characters other
+    /// than separators.
+    pub fn from_str(src: &str) -> Result<Self, InvalidProtocol> {
+        if !is_token(src.as_bytes()) {
+            return Err(InvalidProtocol { _priv: () });
+        }
+
+        let value = Bytes::from(src);
+        Ok(Protocol { value: unsafe { String::from_utf8_unchecked(value) } })
+    }
+
+    /// Returns the protocol as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.value
+    }
+
+    pub(crate) fn from_string(src: String<Bytes>) -> Result<Self, InvalidProtocol> {
+        if !is_token(src.as_bytes()) {
+            return Err(InvalidProtocol { _priv: () });
+        }
+
+        Ok(Protocol { value: src })
+    }
+
+    pub(crate) fn into_inner(self) -> String<Bytes> {
+        self.value
+    }
+}
+
+impl fmt::Debug for Protocol {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), fmt)
+    }
+}
+
+/// Returns true if `src` is a token (RFC 7230 section 3.2.6).
+fn is_token(src: &[u8]) -> bool {
+    fn is_tchar(b: u8) -> bool {
+        match b {
+            b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'*' | b'+' | b'-' |
+            b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => true,
+            _ => b.is_ascii_alphanumeric(),
+        }
+    }
+
+    !src.is_empty() && src.iter().all(|&b| is_tchar(b))
+}
+
+// ===== impl InvalidProtocol =====
+
+impl fmt::Display for InvalidProtocol {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", error::Error::description(self))
+    }
+}
+
+impl error::Error for InvalidProtocol {
+    fn description(&self) -> &str {
+        "invalid protocol"
+    }
+}
@@ -0,0 +1,76 @@
+use futures::Poll;
+use tokio_io::{AsyncRead, AsyncWrite};
+use bytes::Bytes;
+
+use std::cmp;
+use std::io::{self, Read, Write};
+
+/// I/O handle replaying bytes already read from a connection, before reading
+/// from the underlying I/O.
+///
+/// This is handed back when sniffing the protocol of a connection finds a
+/// client speaking HTTP/1.x, see `server::Builder::sniff`.
+#[derive(Debug)]
+pub struct Rewind<T> {
+    io: T,
+    pre: Bytes,
+}
+
+// ===== impl Rewind =====
+
+impl<T> Rewind<T> {
+    pub(crate) fn new(io: T, pre: Bytes) -> Self {
+        Rewind {
+            io: io,
+            pre: pre,
+        }
+    }
+
+    /// Returns a reference to the underlying I/O.
+    pub fn get_ref(&self) -> &T {
+        &self.io
+    }
+
+    /// Returns a mutable reference to the underlying I/O.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+
+    /// Consumes the `Rewind`, returning the underlying I/O and the bytes that
+    /// were read from it but not replayed yet.
+    pub fn into_parts(self) -> (T, Bytes) {
+        (self.io, self.pre)
+    }
+}
+
+impl<T: Read> Read for Rewind<T> {
+    fn read(&mut self, dst: &mut [u8]) -> io::Result<usize> {
+        if self.pre.is_empty() {
+            return self.io.read(dst);
+        }
+
+        let n = cmp::min(dst.len(), self.pre.len());
+        dst[..n].copy_from_slice(&self.pre.split_to(n));
+
+        Ok(n)
+    }
+}
+
+impl<T: AsyncRead> AsyncRead for Rewind<T> {
+}
+
+impl<T: Write> Write for Rewind<T> {
+    fn write(&mut self, src: &[u8]) -> io::Result<usize> {
+        self.io.write(src)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.io.flush()
+    }
+}
+
+impl<T: AsyncWrite> AsyncWrite for Rewind<T> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.io.shutdown()
+    }
+}
@@ -0,0 +1,23 @@
+use super::{Scheduler, Ready, Selection};
+
+/// Gives each ready stream a turn at writing a frame, in the order streams
+/// became ready.
+///
+/// A stream that writes a frame waits behind the other ready streams before
+/// writing the next one. Priority signals are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct Fifo {
+    _priv: (),
+}
+
+impl Fifo {
+    pub fn new() -> Self {
+        Fifo::default()
+    }
+}
+
+impl Scheduler for Fifo {
+    fn select(&mut self, ready: &[Ready]) -> Selection {
+        Selection::new(ready[0].stream_id())
+    }
+}
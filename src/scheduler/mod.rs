@@ -0,0 +1,193 @@
+//! Deciding which stream writes the next frame.
+//!
+//! Each connection asks its scheduler which of the streams with frames ready
+//! to be sent gets to write next, and how much data it may write. The
+//! scheduler is also told about priority signals from the remote and about
+//! streams opening and closing.
+//!
+//! By default, [`Fifo`] is used, which ignores priority signals. Another
+//! scheduler can be set with `client::Builder::scheduler` or
+//! `server::Builder::scheduler`, such as [`WeightedFair`] to honor the RFC
+//! 7540 priorities sent by the remote, or [`Urgency`] for RFC 9218 extensible
+//! priorities.
+//!
+//! [`Fifo`]: struct.Fifo.html
+//! [`WeightedFair`]: struct.WeightedFair.html
+//! [`Urgency`]: struct.Urgency.html
+
+mod fifo;
+mod round_robin;
+mod urgency;
+mod weighted_fair;
+
+pub use self::fifo::Fifo;
+pub use self::round_robin::RoundRobin;
+pub use self::urgency::Urgency;
+pub use self::weighted_fair::WeightedFair;
+
+use Priority;
+use frame::{self, StreamId};
+
+use std::{fmt, usize};
+use std::sync::Arc;
+
+/// Decides which stream writes the next frame to the connection.
+pub trait Scheduler: fmt::Debug + Send + 'static {
+    /// Called when a stream is opened.
+    fn open(&mut self, stream_id: StreamId) {
+        let _ = stream_id;
+    }
+
+    /// Called when the remote sets the RFC 7540 priority of a stream, and
+    /// with the default priority of streams pushed by the local server.
+    ///
+    /// Priorities sent by the local endpoint are not passed to the scheduler.
+    /// Streams may be prioritized before they are opened.
+    fn reprioritize(&mut self, stream_id: StreamId, priority: &Priority) {
+        let _ = (stream_id, priority);
+    }
+
+    /// Called when a stream is closed. The stream may still have frames left
+    /// to write.
+    fn close(&mut self, stream_id: StreamId) {
+        let _ = stream_id;
+    }
+
+    /// Picks the stream that writes the next frame.
+    ///
+    /// `ready` is never empty, and lists streams in the order they became
+    /// ready. A stream that writes a frame and still has frames to send moves
+    /// behind the other ready streams. Only the first 64 ready streams are
+    /// listed, so that picking a stream does not get slower as more streams
+    /// become ready.
+    ///
+    /// Selecting a stream that is not in `ready` is a bug. The first ready
+    /// stream is used instead, and a warning is logged.
+    fn select(&mut self, ready: &[Ready]) -> Selection;
+
+    /// Called once the selected stream wrote a frame of `len` bytes, including
+    /// the frame header.
+    fn sent(&mut self, stream_id: StreamId, len: usize) {
+        let _ = (stream_id, len);
+    }
+
+    /// Returns the stream's share of the connection's send window, relative
+    /// to the other streams waiting for capacity.
+    ///
+    /// By default, all streams get an equal share.
+    fn share(&self, stream_id: StreamId) -> f64 {
+        let _ = stream_id;
+        1.0
+    }
+}
+
+/// A stream with frames ready to be written.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Ready {
+    stream_id: StreamId,
+    len: usize,
+    urgency: u8,
+    is_incremental: bool,
+}
+
+/// The stream picked to write the next frame.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Selection {
+    stream_id: StreamId,
+    max_len: usize,
+}
+
+/// Creates a scheduler for each new connection.
+#[derive(Clone)]
+pub(crate) struct NewScheduler {
+    inner: Arc<Fn() -> Box<Scheduler> + Send + Sync>,
+}
+
+// ===== impl Ready =====
+
+impl Ready {
+    pub(crate) fn new(stream_id: StreamId, len: usize, priority: frame::PriorityParams) -> Self {
+        Ready {
+            stream_id: stream_id,
+            len: len,
+            urgency: priority.urgency(),
+            is_incremental: priority.is_incremental(),
+        }
+    }
+
+    /// Returns the stream's identifier.
+    pub fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    /// Returns the amount of data the stream can write now, zero if only
+    /// frames other than DATA are ready.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the stream's RFC 9218 urgency, in the range [0, 7]. Lower
+    /// values are more urgent.
+    pub fn urgency(&self) -> u8 {
+        self.urgency
+    }
+
+    /// Returns true if the stream's response can be processed incrementally.
+    pub fn is_incremental(&self) -> bool {
+        self.is_incremental
+    }
+}
+
+// ===== impl Selection =====
+
+impl Selection {
+    /// Selects the stream with the given identifier, without limiting the
+    /// amount of data it writes.
+    pub fn new(stream_id: StreamId) -> Self {
+        Selection {
+            stream_id: stream_id,
+            max_len: usize::MAX,
+        }
+    }
+
+    /// Limits the amount of data written by the stream's next DATA frame.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_len` is zero.
+    pub fn max_len(mut self, max_len: usize) -> Self {
+        assert!(max_len > 0, "max_len must be greater than zero");
+        self.max_len = max_len;
+        self
+    }
+
+    pub(crate) fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    pub(crate) fn max_data_len(&self) -> usize {
+        self.max_len
+    }
+}
+
+// ===== impl NewScheduler =====
+
+impl NewScheduler {
+    pub(crate) fn new<S>(scheduler: S) -> Self
+        where S: Scheduler + Clone + Sync,
+    {
+        NewScheduler {
+            inner: Arc::new(move || Box::new(scheduler.clone()) as Box<Scheduler>),
+        }
+    }
+
+    pub(crate) fn new_scheduler(&self) -> Box<Scheduler> {
+        (self.inner)()
+    }
+}
+
+impl fmt::Debug for NewScheduler {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("NewScheduler").finish()
+    }
+}
@@ -0,0 +1,68 @@
+use super::{Scheduler, Ready, Selection};
+
+use frame::StreamId;
+
+/// Gives each ready stream a turn at writing a frame, in stream ID order.
+///
+/// Priority signals are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct RoundRobin {
+    turns: Turns,
+}
+
+/// Rotates between streams in stream ID order.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Turns {
+    /// The stream that took the last turn
+    last: Option<StreamId>,
+}
+
+// ===== impl RoundRobin =====
+
+impl RoundRobin {
+    pub fn new() -> Self {
+        RoundRobin::default()
+    }
+}
+
+impl Scheduler for RoundRobin {
+    fn select(&mut self, ready: &[Ready]) -> Selection {
+        let stream_id = self.turns.next(ready.iter().map(Ready::stream_id))
+            .expect("no ready streams");
+
+        Selection::new(stream_id)
+    }
+}
+
+// ===== impl Turns =====
+
+impl Turns {
+    /// Picks the stream following the one that took the last turn, wrapping
+    /// around to the lowest stream ID.
+    pub fn next<I>(&mut self, streams: I) -> Option<StreamId>
+        where I: Iterator<Item = StreamId>,
+    {
+        let mut first = None;
+        let mut next = None;
+
+        for stream_id in streams {
+            if first.map_or(true, |id| stream_id < id) {
+                first = Some(stream_id);
+            }
+
+            let is_after_last = self.last.map_or(true, |last| stream_id > last);
+
+            if is_after_last && next.map_or(true, |id| stream_id < id) {
+                next = Some(stream_id);
+            }
+        }
+
+        let next = next.or(first);
+
+        if next.is_some() {
+            self.last = next;
+        }
+
+        next
+    }
+}
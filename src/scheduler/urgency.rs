@@ -0,0 +1,42 @@
+use super::{Scheduler, Ready, Selection};
+use super::round_robin::Turns;
+
+/// Schedules streams using the extensible priority scheme of RFC 9218.
+///
+/// The most urgent ready streams are always served first. Among streams of
+/// the same urgency, non-incremental streams are sent one at a time in stream
+/// ID order, after which incremental streams take turns writing frames. RFC
+/// 7540 priority signals are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct Urgency {
+    turns: Turns,
+}
+
+impl Urgency {
+    pub fn new() -> Self {
+        Urgency::default()
+    }
+}
+
+impl Scheduler for Urgency {
+    fn select(&mut self, ready: &[Ready]) -> Selection {
+        let urgency = ready.iter()
+            .map(Ready::urgency)
+            .min()
+            .expect("no ready streams");
+
+        let bucket = || ready.iter().filter(move |r| r.urgency() == urgency);
+
+        let sequential = bucket()
+            .filter(|r| !r.is_incremental())
+            .map(Ready::stream_id)
+            .min();
+
+        let stream_id = match sequential {
+            Some(stream_id) => stream_id,
+            None => self.turns.next(bucket().map(Ready::stream_id)).unwrap(),
+        };
+
+        Selection::new(stream_id)
+    }
+}
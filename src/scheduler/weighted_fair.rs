@@ -0,0 +1,486 @@
+use super::{Scheduler, Ready, Selection};
+
+use Priority;
+use frame::StreamId;
+
+use std::cmp;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Shares write slots between streams using the stream dependency tree
+/// described in RFC 7540 section 5.3.
+///
+/// Streams with frames ready to be sent are selected by walking down from the
+/// root. A stream that is ready is always selected before its dependencies,
+/// and siblings share write slots in proportion to their weights.
+#[derive(Debug, Clone)]
+pub struct WeightedFair {
+    nodes: HashMap<StreamId, Node>,
+
+    /// Idle and closed streams retained in the tree, oldest first.
+    retained: VecDeque<StreamId>,
+
+    /// Streams marked as ready in the tree
+    ready: HashSet<StreamId>,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    /// The stream this stream depends on, zero for the root
+    parent: StreamId,
+
+    /// The stream's weight, in the range [1, 256]
+    weight: u16,
+
+    /// Streams that depend on this stream
+    children: Vec<StreamId>,
+
+    state: NodeState,
+
+    /// True if the stream has frames ready to be sent
+    is_ready: bool,
+
+    /// Number of ready streams in this subtree, including this stream
+    num_ready: usize,
+
+    /// Virtual time consumed by this subtree, used to share write slots
+    /// between siblings
+    pass: u64,
+
+    /// Virtual time of the most recently selected child
+    vtime: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NodeState {
+    Idle,
+    Open,
+    Closed,
+}
+
+/// The weight given to streams without an explicit priority
+const DEFAULT_WEIGHT: u16 = 16;
+
+/// Number of idle and closed streams retained in the tree so that
+/// dependencies on them can still be honored.
+const MAX_RETAINED: usize = 16;
+
+/// Scales the virtual time charged for each byte written, so that dividing by
+/// the weight does not lose precision.
+const STRIDE: u64 = 256;
+
+// ===== impl WeightedFair =====
+
+impl WeightedFair {
+    pub fn new() -> Self {
+        let mut nodes = HashMap::new();
+
+        let mut root = Node::new(StreamId::zero(), DEFAULT_WEIGHT);
+        root.state = NodeState::Open;
+        nodes.insert(StreamId::zero(), root);
+
+        WeightedFair {
+            nodes: nodes,
+            retained: VecDeque::new(),
+            ready: HashSet::new(),
+        }
+    }
+}
+
+impl Default for WeightedFair {
+    fn default() -> Self {
+        WeightedFair::new()
+    }
+}
+
+impl Scheduler for WeightedFair {
+    fn open(&mut self, id: StreamId) {
+        self.ensure_node(id);
+
+        let node = self.node_mut(id);
+
+        if node.state == NodeState::Idle {
+            node.state = NodeState::Open;
+        }
+    }
+
+    /// Updates the stream's position in the tree.
+    ///
+    /// Streams that are not in the tree are added, which allows idle streams
+    /// to be prioritized before they are opened.
+    fn reprioritize(&mut self, id: StreamId, priority: &Priority) {
+        let parent = priority.dependency_id();
+
+        if parent == id {
+            // A stream cannot depend on itself, this is checked when the frame
+            // is decoded.
+            return;
+        }
+
+        // A dependency on a stream that is not in the tree results in that
+        // stream being given the default priority.
+        self.ensure_node(parent);
+        self.ensure_node(id);
+
+        trace!("reprioritize; id={:?}; priority={:?}", id, priority);
+
+        // If the stream is made dependent on one of its own dependencies, that
+        // dependency is first moved to depend on the stream's previous parent.
+        if self.is_descendant(parent, id) {
+            let prev = self.node(id).parent;
+            self.detach(parent);
+            self.attach(parent, prev, false);
+        }
+
+        self.detach(id);
+        self.node_mut(id).weight = priority.weight();
+        self.attach(id, parent, priority.is_exclusive());
+    }
+
+    /// The stream remains in the tree for a while so that other streams may
+    /// still depend on it.
+    fn close(&mut self, id: StreamId) {
+        match self.nodes.get_mut(&id) {
+            Some(node) => node.state = NodeState::Closed,
+            None => return,
+        }
+
+        self.retain(id);
+    }
+
+    fn select(&mut self, ready: &[Ready]) -> Selection {
+        self.sync_ready(ready);
+
+        let id = self.next_ready().expect("no ready streams");
+        Selection::new(id)
+    }
+
+    /// Charges the stream, and the streams it depends on, for writing `len`
+    /// bytes.
+    fn sent(&mut self, id: StreamId, len: usize) {
+        let mut id = id;
+
+        while !id.is_zero() {
+            let node = match self.nodes.get_mut(&id) {
+                Some(node) => node,
+                None => return,
+            };
+
+            node.pass += len as u64 * STRIDE / node.weight as u64;
+            id = node.parent;
+        }
+    }
+
+    fn share(&self, id: StreamId) -> f64 {
+        if !self.nodes.contains_key(&id) {
+            // The stream would be given the default priority
+            let total = self.weight_of_children(StreamId::zero()) + DEFAULT_WEIGHT as u32;
+            return DEFAULT_WEIGHT as f64 / total as f64;
+        }
+
+        let mut share = 1.0;
+        let mut id = id;
+
+        while !id.is_zero() {
+            let node = self.node(id);
+            let total = self.weight_of_children(node.parent);
+
+            share *= node.weight as f64 / total as f64;
+            id = node.parent;
+        }
+
+        share
+    }
+}
+
+impl WeightedFair {
+
+    /// Marks the streams in `ready` as ready in the tree, and all others as
+    /// not ready.
+    fn sync_ready(&mut self, ready: &[Ready]) {
+        let ids: HashSet<StreamId> = ready.iter().map(Ready::stream_id).collect();
+
+        let stale: Vec<StreamId> = self.ready.difference(&ids).cloned().collect();
+
+        for id in stale {
+            self.ready.remove(&id);
+            self.node_mut(id).is_ready = false;
+            self.dec_ready(id);
+        }
+
+        for id in ids {
+            if self.ready.contains(&id) {
+                continue;
+            }
+
+            self.ensure_node(id);
+
+            {
+                let node = self.node_mut(id);
+
+                if node.state == NodeState::Idle {
+                    node.state = NodeState::Open;
+                }
+
+                node.is_ready = true;
+            }
+
+            self.ready.insert(id);
+            self.inc_ready(id);
+        }
+    }
+
+    /// Walks down from the root to find the next stream to send a frame.
+    fn next_ready(&mut self) -> Option<StreamId> {
+        let mut id = StreamId::zero();
+
+        if self.node(id).num_ready == 0 {
+            return None;
+        }
+
+        loop {
+            let next = {
+                let node = self.node(id);
+
+                if node.is_ready && !id.is_zero() {
+                    return Some(id);
+                }
+
+                // Select the sibling that has consumed the least, breaking ties
+                // by order of insertion.
+                let mut next: Option<(StreamId, u64)> = None;
+
+                for &child in &node.children {
+                    let child_node = self.node(child);
+
+                    if child_node.num_ready == 0 {
+                        continue;
+                    }
+
+                    match next {
+                        Some((_, pass)) if pass <= child_node.pass => {}
+                        _ => next = Some((child, child_node.pass)),
+                    }
+                }
+
+                next
+            };
+
+            let (child, pass) = next.expect("ready count out of sync");
+
+            self.node_mut(id).vtime = pass;
+            id = child;
+        }
+    }
+
+    fn ensure_node(&mut self, id: StreamId) {
+        if self.nodes.contains_key(&id) {
+            return;
+        }
+
+        self.nodes.insert(id, Node::new(StreamId::zero(), DEFAULT_WEIGHT));
+        self.node_mut(StreamId::zero()).children.push(id);
+
+        self.retain(id);
+    }
+
+    fn retain(&mut self, id: StreamId) {
+        self.retained.push_back(id);
+
+        while self.retained.len() > MAX_RETAINED {
+            let id = self.retained.pop_front().unwrap();
+
+            let (state, is_ready) = match self.nodes.get(&id) {
+                Some(node) => (node.state, node.is_ready),
+                None => continue,
+            };
+
+            if state == NodeState::Open {
+                // The stream was opened after being retained as idle. It will
+                // be retained again once closed.
+                continue;
+            }
+
+            if is_ready {
+                // Frames are still pending, try again later.
+                self.retained.push_front(id);
+                return;
+            }
+
+            self.remove(id);
+        }
+    }
+
+    /// Removes the stream from the tree, moving its dependencies to its
+    /// parent (RFC 7540 section 5.3.4).
+    fn remove(&mut self, id: StreamId) {
+        trace!("remove from priority tree; id={:?}", id);
+
+        self.detach(id);
+
+        let total = self.weight_of_children(id);
+        let node = self.nodes.remove(&id).unwrap();
+
+        for child in node.children {
+            {
+                // The weight of the removed stream is distributed between its
+                // dependencies, in proportion to their weights.
+                let child_node = self.node_mut(child);
+                let weight = node.weight as u32 * child_node.weight as u32 / total;
+
+                child_node.weight = cmp::max(weight, 1) as u16;
+            }
+
+            self.attach(child, node.parent, false);
+        }
+    }
+
+    /// Returns true if `id` depends, directly or not, on `ancestor`.
+    fn is_descendant(&self, id: StreamId, ancestor: StreamId) -> bool {
+        let mut id = id;
+
+        while !id.is_zero() {
+            id = self.node(id).parent;
+
+            if id == ancestor {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Removes the stream from its parent's dependencies.
+    fn detach(&mut self, id: StreamId) {
+        let (parent, num_ready) = {
+            let node = self.node(id);
+            (node.parent, node.num_ready)
+        };
+
+        self.node_mut(parent).children.retain(|&child| child != id);
+        self.sub_ready(parent, num_ready);
+    }
+
+    /// Makes the detached stream depend on `parent`.
+    fn attach(&mut self, id: StreamId, parent: StreamId, exclusive: bool) {
+        if exclusive {
+            // The stream becomes the sole dependency of its parent, all other
+            // dependencies now depend on the stream.
+            let children = ::std::mem::replace(
+                &mut self.node_mut(parent).children, vec![]);
+
+            let mut moved = 0;
+
+            for &child in &children {
+                let child_node = self.node_mut(child);
+                child_node.parent = id;
+                moved += child_node.num_ready;
+            }
+
+            self.sub_ready(parent, moved);
+
+            let node = self.node_mut(id);
+            node.children.extend(children);
+            node.num_ready += moved;
+        }
+
+        let vtime = self.node(parent).vtime;
+        let num_ready = {
+            let node = self.node_mut(id);
+            node.parent = parent;
+
+            // Don't let a stream that was inactive claim all write slots until
+            // it catches up with its siblings.
+            node.pass = cmp::max(node.pass, vtime);
+            node.num_ready
+        };
+
+        self.node_mut(parent).children.push(id);
+        self.add_ready(parent, num_ready);
+    }
+
+    fn inc_ready(&mut self, id: StreamId) {
+        let mut id = id;
+
+        loop {
+            let (parent, activated) = {
+                let node = self.node_mut(id);
+                node.num_ready += 1;
+                (node.parent, node.num_ready == 1)
+            };
+
+            if id.is_zero() {
+                return;
+            }
+
+            if activated {
+                let vtime = self.node(parent).vtime;
+                let node = self.node_mut(id);
+                node.pass = cmp::max(node.pass, vtime);
+            }
+
+            id = parent;
+        }
+    }
+
+    fn dec_ready(&mut self, id: StreamId) {
+        self.sub_ready(id, 1);
+    }
+
+    /// Adds `n` ready streams to `id` and its ancestors.
+    fn add_ready(&mut self, id: StreamId, n: usize) {
+        self.walk_up(id, |node| node.num_ready += n);
+    }
+
+    /// Removes `n` ready streams from `id` and its ancestors.
+    fn sub_ready(&mut self, id: StreamId, n: usize) {
+        self.walk_up(id, |node| node.num_ready -= n);
+    }
+
+    fn walk_up<F>(&mut self, id: StreamId, mut f: F)
+        where F: FnMut(&mut Node),
+    {
+        let mut id = id;
+
+        loop {
+            let node = self.node_mut(id);
+            f(node);
+
+            if id.is_zero() {
+                return;
+            }
+
+            id = node.parent;
+        }
+    }
+
+    fn weight_of_children(&self, id: StreamId) -> u32 {
+        self.node(id).children.iter()
+            .map(|child| self.node(*child).weight as u32)
+            .sum()
+    }
+
+    fn node(&self, id: StreamId) -> &Node {
+        &self.nodes[&id]
+    }
+
+    fn node_mut(&mut self, id: StreamId) -> &mut Node {
+        self.nodes.get_mut(&id).expect("stream not in priority tree")
+    }
+}
+
+// ===== impl Node =====
+
+impl Node {
+    fn new(parent: StreamId, weight: u16) -> Node {
+        Node {
+            parent: parent,
+            weight: weight,
+            children: vec![],
+            state: NodeState::Idle,
+            is_ready: false,
+            num_ready: 0,
+            pass: 0,
+            vtime: 0,
+        }
+    }
+}
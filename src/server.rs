@@ -2,13 +2,26 @@ use frame::{self, StreamId, Reason};
 use frame::Reason::*;
 use codec::{Codec, RecvError};
 use proto::{self, Connection, WindowSize};
+use padding::Padding;
+use scheduler::{Scheduler, NewScheduler};
+use stats::FlowStats;
+use tunnel::Pipe;
+use Protocol;
+
+pub use rewind::Rewind;
 
 use http::{Request, Response, HeaderMap};
+use http::header::HeaderValue;
 use futures::{self, Future, Poll, Async};
+use futures::future::{AndThen, MapErr};
 use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_io::io::{self as io_util, WriteAll, write_all};
 use bytes::{Bytes, Buf, IntoBuf};
+use tokio_timer::Timer;
 
-use std::fmt;
+use std::{fmt, io, mem};
+use std::io::{Read, Write};
+use std::time::Duration;
 
 /// In progress H2 connection binding
 pub struct Handshake<T, B: IntoBuf = Bytes> {
@@ -16,6 +29,40 @@ pub struct Handshake<T, B: IntoBuf = Bytes> {
     inner: Box<Future<Item = Server<T, B>, Error = ::Error>>,
 }
 
+/// Future resolving to the protocol spoken by a client, see `Builder::sniff`.
+pub struct Sniff<T, B: IntoBuf = Bytes> {
+    state: SniffState<T, B>,
+    builder: Builder,
+}
+
+enum SniffState<T, B: IntoBuf> {
+    /// Reading the client connection preface
+    ReadPreface(Option<T>, Vec<u8>),
+
+    /// Performing the HTTP/2 handshake
+    Handshake(Handshake<T, B>),
+}
+
+/// The protocol spoken by a client, detected by `Builder::sniff`.
+pub enum Sniffed<T, B: IntoBuf = Bytes> {
+    /// The client sent the HTTP/2 connection preface
+    Http2(Server<T, B>),
+
+    /// The client is speaking HTTP/1.x
+    Http1(Rewind<T>),
+}
+
+/// Future writing a canned response to an HTTP/1.x client.
+pub struct Reject<T> {
+    inner: MapErr<WriteResponse<T>, fn(io::Error) -> ::Error>,
+}
+
+/// Writes a response, then flushes the I/O
+type WriteResponse<T> = AndThen<
+    WriteAll<T, &'static [u8]>,
+    io_util::Flush<T>,
+    fn((T, &'static [u8])) -> io_util::Flush<T>>;
+
 /// Marker type indicating a client peer
 pub struct Server<T, B: IntoBuf> {
     connection: Connection<T, Peer, B>,
@@ -31,6 +78,27 @@ pub struct Body<B: IntoBuf> {
     inner: proto::StreamRef<B::Buf, Peer>,
 }
 
+/// Bidirectional byte pipe over the stream of a CONNECT request.
+///
+/// Writes are sent as DATA frames as flow control allows, reads yield the
+/// data sent by the client and shutting down ends the stream.
+#[derive(Debug)]
+pub struct Tunnel {
+    inner: Pipe<Peer>,
+}
+
+/// A future resolving to the round-trip time of a PING frame.
+#[derive(Debug)]
+pub struct Ping {
+    inner: proto::UserPing,
+}
+
+/// Build a Server.
+#[derive(Clone, Debug, Default)]
+pub struct Builder {
+    config: proto::Config,
+}
+
 #[derive(Debug)]
 pub struct Send<T> {
     src: T,
@@ -57,14 +125,50 @@ pub(crate) struct Peer;
 
 const PREFACE: [u8; 24] = *b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
 
+/// Response to an HTTP/1.1 request upgrading the connection to h2c
+const SWITCHING_PROTOCOLS: &'static [u8] =
+    b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n";
+
+/// Response rejecting an HTTP/1.x request
+const VERSION_NOT_SUPPORTED: &'static [u8] =
+    b"HTTP/1.1 505 HTTP Version Not Supported\r\nConnection: close\r\nContent-Length: 0\r\n\r\n";
+
+/// Response asking an HTTP/1.x client to upgrade to h2c
+const UPGRADE_REQUIRED: &'static [u8] =
+    b"HTTP/1.1 426 Upgrade Required\r\nConnection: Upgrade, close\r\nUpgrade: h2c\r\nContent-Length: 0\r\n\r\n";
+
 // ===== impl Server =====
 
+impl Server<(), Bytes> {
+    /// Returns a server builder instance, used to configure the local
+    /// settings of the connection.
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+}
+
 impl<T> Server<T, Bytes>
     where T: AsyncRead + AsyncWrite + 'static,
 {
     pub fn handshake(io: T) -> Handshake<T, Bytes> {
         Server::handshake2(io)
     }
+
+    /// Bind an H2 server connection upgraded from HTTP/1.1 (h2c).
+    ///
+    /// See `Builder::upgrade`.
+    pub fn upgrade(io: T, request: Request<()>, settings: &HeaderValue)
+        -> Handshake<T, Bytes>
+    {
+        Builder::default().upgrade(io, request, settings)
+    }
+
+    /// Detect whether the client speaks HTTP/2 or HTTP/1.x.
+    ///
+    /// See `Builder::sniff`.
+    pub fn sniff(io: T) -> Sniff<T, Bytes> {
+        Builder::default().sniff(io)
+    }
 }
 
 impl<T, B> Server<T, B>
@@ -76,26 +180,7 @@ impl<T, B> Server<T, B>
     /// Returns a future which resolves to the connection value once the H2
     /// handshake has been completed.
     pub fn handshake2(io: T) -> Handshake<T, B> {
-        // Create the codec
-        let mut codec = Codec::new(io);
-
-        // Create the initial SETTINGS frame
-        let settings = frame::Settings::default();
-
-       // Send initial settings frame
-       codec.buffer(settings.into())
-           .ok().expect("invalid SETTINGS frame");
-
-        // Flush pending settings frame and then wait for the client preface
-        let handshake = Flush::new(codec)
-            .and_then(ReadPreface::new)
-            .map(move |codec| {
-                let connection = Connection::new(codec);
-                Server { connection }
-            })
-            ;
-
-        Handshake { inner: Box::new(handshake) }
+        Builder::default().handshake(io)
     }
 
     /// Returns `Ready` when the underlying connection has closed.
@@ -103,6 +188,70 @@ impl<T, B> Server<T, B>
         self.connection.poll()
             .map_err(Into::into)
     }
+
+    /// Sets the initial window size of streams opened by the remote.
+    ///
+    /// A new SETTINGS frame is sent the next time the connection is polled.
+    /// The receive window of every open stream is adjusted by the difference
+    /// once the remote acknowledges the change.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is larger than 2^31-1.
+    pub fn set_initial_window_size(&mut self, size: u32) {
+        self.connection.set_initial_window_size(size);
+    }
+
+    /// Sets the max number of concurrent streams that the remote may open.
+    ///
+    /// A new SETTINGS frame is sent the next time the connection is polled.
+    /// The limit is enforced once the remote acknowledges the change. Streams
+    /// that are already open are not affected.
+    pub fn set_max_concurrent_streams(&mut self, max: u32) {
+        self.connection.set_max_concurrent_streams(max);
+    }
+
+    /// Sets opaque debug data to include in GOAWAY frames sent to the client.
+    ///
+    /// This applies to GOAWAY frames sent when closing the connection, either
+    /// due to an error or during a graceful shutdown. The data is meant for
+    /// diagnostics only, such as "shutting down for deploy", and is truncated
+    /// to fit in a single frame.
+    pub fn set_go_away_debug_data<D: Into<Bytes>>(&mut self, debug_data: D) {
+        self.connection.set_go_away_debug_data(debug_data.into());
+    }
+
+    /// Gracefully shut down the connection.
+    ///
+    /// A GOAWAY frame is sent to let the client know that no new streams will
+    /// be accepted. After one round-trip, measured with a PING, a second
+    /// GOAWAY carrying the ID of the last processed stream is sent. Streams
+    /// that are already open run to completion, after which the connection is
+    /// closed.
+    ///
+    /// If the PING is not acknowledged within the graceful shutdown timeout,
+    /// the second GOAWAY is sent anyway.
+    ///
+    /// The shutdown starts the next time the connection is polled.
+    pub fn graceful_shutdown(&mut self) {
+        self.connection.graceful_shutdown();
+    }
+
+    /// Returns the connection's flow control state.
+    ///
+    /// The buffered and unreleased amounts are totals over all streams.
+    pub fn flow_stats(&mut self) -> FlowStats {
+        self.connection.flow_stats()
+    }
+
+    /// Send a PING frame to the client.
+    ///
+    /// Returns a future which resolves to the round-trip time once the client
+    /// acknowledges the PING. The frame is sent the next time the connection
+    /// is polled.
+    pub fn ping(&mut self) -> Ping {
+        Ping { inner: self.connection.ping() }
+    }
 }
 
 impl<T, B> futures::Stream for Server<T, B>
@@ -151,6 +300,294 @@ impl<T, B> fmt::Debug for Server<T, B>
     }
 }
 
+// ===== impl Builder =====
+
+impl Builder {
+    /// Set the initial window size of streams opened by the remote.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is larger than 2^31-1.
+    pub fn initial_window_size(&mut self, size: u32) -> &mut Self {
+        self.config.settings.set_initial_window_size(Some(size));
+        self
+    }
+
+    /// Set the max number of concurrent streams that the remote may open.
+    pub fn max_concurrent_streams(&mut self, max: u32) -> &mut Self {
+        self.config.settings.set_max_concurrent_streams(Some(max));
+        self
+    }
+
+    /// Set the max size of frame payloads that the remote may send.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is not in the range [2^14, 2^24-1].
+    pub fn max_frame_size(&mut self, max: u32) -> &mut Self {
+        self.config.settings.set_max_frame_size(Some(max));
+        self
+    }
+
+    /// Set the max size of the HPACK table used to decode headers.
+    pub fn header_table_size(&mut self, size: u32) -> &mut Self {
+        self.config.settings.set_header_table_size(Some(size));
+        self
+    }
+
+    /// Set the max size of header lists that the remote may send.
+    pub fn max_header_list_size(&mut self, max: u32) -> &mut Self {
+        self.config.settings.set_max_header_list_size(Some(max));
+        self
+    }
+
+    /// Enable or disable releasing capacity automatically.
+    ///
+    /// When enabled, the capacity of data received on a stream is released
+    /// back to the peer as soon as the data is yielded by `Body::poll`, and
+    /// WINDOW_UPDATE frames are batched. By default, capacity must be released
+    /// with `Body::release_capacity`, which allows applying backpressure.
+    ///
+    /// This can be overridden on each `Body`.
+    pub fn auto_release_capacity(&mut self, enabled: bool) -> &mut Self {
+        self.config.auto_release_capacity = enabled;
+        self
+    }
+
+    /// Enable or disable adaptive flow control.
+    ///
+    /// When enabled, the bandwidth-delay product of the connection is measured
+    /// using PING round trips and the rate at which data is received. The
+    /// connection and stream receive windows grow through WINDOW_UPDATE and
+    /// SETTINGS frames when they are too small to keep the link busy, up to
+    /// `max_adaptive_window_size`. Disabled by default.
+    pub fn adaptive_window(&mut self, enabled: bool) -> &mut Self {
+        self.config.adaptive_window = enabled;
+        self
+    }
+
+    /// Set the max size that adaptive flow control grows the receive windows
+    /// to.
+    ///
+    /// Defaults to 16 MiB. Values larger than the max window size allowed by
+    /// the protocol are capped.
+    pub fn max_adaptive_window_size(&mut self, max: u32) -> &mut Self {
+        self.config.max_adaptive_window_size = max;
+        self
+    }
+
+    /// Disable the RFC 7540 priority scheme in favor of RFC 9218 extensible
+    /// priorities.
+    ///
+    /// This is advertised to the client with SETTINGS_NO_RFC7540_PRIORITIES.
+    /// To schedule responses by the urgency and incremental parameters of
+    /// each request's `priority` header, as updated by PRIORITY_UPDATE
+    /// frames, set the `Urgency` scheduler. A `priority` header on the
+    /// response overrides the client's signal.
+    pub fn no_rfc7540_priorities(&mut self, enabled: bool) -> &mut Self {
+        self.config.settings.set_no_rfc7540_priorities(enabled);
+        self
+    }
+
+    /// Enable or disable extended CONNECT requests (RFC 8441).
+    ///
+    /// This is advertised to the client with SETTINGS_ENABLE_CONNECT_PROTOCOL,
+    /// allowing it to bootstrap protocols such as WebSocket on a stream. The
+    /// protocol is found in the `Protocol` extension of the request.
+    /// Disabled by default.
+    pub fn enable_connect_protocol(&mut self, enabled: bool) -> &mut Self {
+        self.config.settings.set_enable_connect_protocol(enabled);
+        self
+    }
+
+    /// Set how long to wait for the remote to acknowledge the local settings.
+    ///
+    /// If the SETTINGS frame is not acknowledged in time, the connection is
+    /// closed with a GOAWAY frame carrying `SETTINGS_TIMEOUT`. By default, no
+    /// timeout is enforced.
+    pub fn settings_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.settings_timeout = Some(timeout);
+        self
+    }
+
+    /// Enable keep-alive PINGs, sent once the connection has not received
+    /// any frames for `interval`.
+    ///
+    /// If a keep-alive PING is not acknowledged within the keep-alive timeout,
+    /// the connection is considered dead. All streams are reset and the
+    /// connection fails with an I/O error of kind `TimedOut`.
+    pub fn keep_alive_interval(&mut self, interval: Duration) -> &mut Self {
+        self.config.keep_alive_interval = Some(interval);
+        self
+    }
+
+    /// Set how long to wait for a keep-alive PING to be acknowledged.
+    ///
+    /// Defaults to 20 seconds.
+    pub fn keep_alive_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.keep_alive_timeout = timeout;
+        self
+    }
+
+    /// Set how long a graceful shutdown waits for its PING to be acknowledged
+    /// before sending the final GOAWAY frame.
+    ///
+    /// Defaults to 1 second.
+    pub fn graceful_shutdown_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.graceful_shutdown_timeout = timeout;
+        self
+    }
+
+    /// Set whether keep-alive PINGs are sent when there are no open streams.
+    ///
+    /// By default, keep-alive PINGs are only sent while streams are open.
+    pub fn keep_alive_while_idle(&mut self, enabled: bool) -> &mut Self {
+        self.config.keep_alive_while_idle = enabled;
+        self
+    }
+
+    /// Set the scheduler deciding which stream sends the next frame.
+    ///
+    /// Each connection uses its own clone of `scheduler`. By default, `Fifo`
+    /// is used, which ignores priorities: ready streams take turns writing a
+    /// frame in the order they became ready. Set `WeightedFair` or `Urgency`
+    /// to honor the priorities signaled by the client.
+    pub fn scheduler<S>(&mut self, scheduler: S) -> &mut Self
+        where S: Scheduler + Clone + Sync,
+    {
+        self.config.scheduler = Some(NewScheduler::new(scheduler));
+        self
+    }
+
+    /// Set the padding added to outbound DATA and HEADERS frames.
+    ///
+    /// Padding hides the exact size of headers and body chunks to mitigate
+    /// traffic analysis. The padding of DATA frames counts against flow
+    /// control. By default, frames are not padded.
+    pub fn padding(&mut self, padding: Padding) -> &mut Self {
+        self.config.padding = Some(padding);
+        self
+    }
+
+    /// Set the timer used to track timeouts.
+    ///
+    /// If no timer is provided, a new one is created for each connection that
+    /// needs one, which spawns a thread. Sharing a timer across connections
+    /// avoids this.
+    pub fn timer(&mut self, timer: Timer) -> &mut Self {
+        self.config.timer = Some(timer);
+        self
+    }
+
+    /// Bind an H2 server connection.
+    ///
+    /// Returns a future which resolves to the connection value once the H2
+    /// handshake has been completed.
+    pub fn handshake<T, B>(&self, io: T) -> Handshake<T, B>
+        where T: AsyncRead + AsyncWrite + 'static,
+              B: IntoBuf + 'static,
+    {
+        self.bind(io, 0)
+    }
+
+    /// Detect whether the client speaks HTTP/2 with prior knowledge or
+    /// HTTP/1.x, so that one listener can serve both protocols.
+    ///
+    /// The first bytes read from `io` are compared to the HTTP/2 connection
+    /// preface. If they match, the handshake continues as with `handshake`
+    /// and the future resolves to `Sniffed::Http2`. Otherwise, it resolves to
+    /// `Sniffed::Http1` with a `Rewind` that replays the bytes already read,
+    /// which can be handed to an HTTP/1.x server or rejected with a canned
+    /// response.
+    pub fn sniff<T, B>(&self, io: T) -> Sniff<T, B>
+        where T: AsyncRead + AsyncWrite + 'static,
+              B: IntoBuf + 'static,
+    {
+        Sniff {
+            state: SniffState::ReadPreface(Some(io), Vec::with_capacity(PREFACE.len())),
+            builder: self.clone(),
+        }
+    }
+
+    /// Bind an H2 server connection, once the first `preface_len` bytes of
+    /// the client connection preface have been read from `io`.
+    fn bind<T, B>(&self, io: T, preface_len: usize) -> Handshake<T, B>
+        where T: AsyncRead + AsyncWrite + 'static,
+              B: IntoBuf + 'static,
+    {
+        let config = self.config.clone();
+
+        let max_frame_size = config.settings.max_frame_size()
+            .unwrap_or(frame::DEFAULT_MAX_FRAME_SIZE);
+
+        // Create the codec
+        let mut codec = Codec::with_max_recv_frame_size(io, max_frame_size as usize);
+
+        // Send initial settings frame
+        codec.buffer(config.settings.clone().into())
+            .ok().expect("invalid SETTINGS frame");
+
+        // Flush pending settings frame and then wait for the client preface
+        let handshake = Flush::new(codec)
+            .and_then(move |codec| ReadPreface::new(codec, preface_len))
+            .map(move |codec| {
+                let connection = Connection::new(codec, &config);
+                Server { connection }
+            })
+            ;
+
+        Handshake { inner: Box::new(handshake) }
+    }
+
+    /// Bind an H2 server connection upgraded from HTTP/1.1 (h2c).
+    ///
+    /// `request` is the HTTP/1.1 request carrying `Upgrade: h2c`, with its
+    /// body already read, and `settings` is the value of its `HTTP2-Settings`
+    /// header. The `101 Switching Protocols` response is written to `io`
+    /// before the HTTP/2 handshake.
+    ///
+    /// The settings are applied as the client's initial SETTINGS. The request
+    /// is the first item yielded by the returned `Server`, on stream 1, and
+    /// the response to the upgrade request is sent on its `Stream`.
+    ///
+    /// The handshake fails with `PROTOCOL_ERROR`, without writing anything,
+    /// if `settings` or `request` are invalid.
+    pub fn upgrade<T, B>(&self, io: T, request: Request<()>, settings: &HeaderValue)
+        -> Handshake<T, B>
+        where T: AsyncRead + AsyncWrite + 'static,
+              B: IntoBuf + 'static,
+    {
+        let remote_settings = match frame::Settings::load_http2_settings(settings.as_bytes()) {
+            Ok(settings) => settings,
+            Err(e) => {
+                debug!("invalid HTTP2-Settings header; err={:?}", e);
+                return Handshake::error(ProtocolError.into());
+            }
+        };
+
+        let request = match Peer::convert_upgrade_message(request) {
+            Some(request) => request,
+            None => {
+                debug!("invalid upgrade request");
+                return Handshake::error(ProtocolError.into());
+            }
+        };
+
+        let builder = self.clone();
+
+        let handshake = write_all(io, SWITCHING_PROTOCOLS)
+            .map_err(::Error::from)
+            .and_then(move |(io, _)| builder.handshake(io))
+            .and_then(move |mut server: Server<T, B>| {
+                server.connection.recv_upgrade(&remote_settings, request)?;
+                Ok(server)
+            })
+            ;
+
+        Handshake { inner: Box::new(handshake) }
+    }
+}
+
 // ===== impl Stream =====
 
 impl<B: IntoBuf> Stream<B> {
@@ -195,9 +632,40 @@ impl<B: IntoBuf> Stream<B> {
             .map_err(Into::into)
     }
 
+    /// Returns the stream's identifier.
+    pub fn stream_id(&self) -> StreamId {
+        self.inner.stream_id()
+    }
+
+    /// Returns the stream's flow control state.
+    pub fn flow_stats(&self) -> FlowStats {
+        self.inner.flow_stats()
+    }
+
     pub fn send_reset(mut self, reason: Reason) {
         self.inner.send_reset(reason)
     }
+
+    /// Push a response to the client for the given request.
+    ///
+    /// A PUSH_PROMISE frame carrying the request is sent on this stream,
+    /// reserving a new stream on which the pushed response is sent. The
+    /// request must be safe and cacheable, i.e. `GET` or `HEAD`, and should
+    /// include the scheme and authority of the resource.
+    ///
+    /// Fails if the client disabled push with SETTINGS_ENABLE_PUSH, or if
+    /// this stream's response has already been fully sent.
+    ///
+    /// Pushed streams count against the client's
+    /// SETTINGS_MAX_CONCURRENT_STREAMS once their response is sent. Sending a
+    /// response that does not end the stream fails if the limit is reached.
+    pub fn push_request(&mut self, request: Request<()>)
+        -> Result<Stream<B>, ::Error>
+    {
+        self.inner.push_request(request)
+            .map(|inner| Stream { inner })
+            .map_err(Into::into)
+    }
 }
 
 impl Stream<Bytes> {
@@ -212,6 +680,15 @@ impl Stream<Bytes> {
             eos: end_of_stream,
         }
     }
+
+    /// Converts the stream of a CONNECT request into a tunnel.
+    ///
+    /// This must be called once a successful (2xx) response has been sent
+    /// with `send_response`, without ending the stream. The capacity of the
+    /// data read from the tunnel is released automatically.
+    pub fn into_tunnel(self) -> Tunnel {
+        Tunnel { inner: Pipe::new(self.inner) }
+    }
 }
 
 // ===== impl Body =====
@@ -227,6 +704,15 @@ impl<B: IntoBuf> Body<B> {
             .map_err(Into::into)
     }
 
+    /// Sets whether capacity is released when data is yielded by `poll`,
+    /// overriding the connection's default.
+    ///
+    /// Capacity of data already yielded must still be released with
+    /// `release_capacity`.
+    pub fn set_auto_release_capacity(&mut self, enabled: bool) {
+        self.inner.set_auto_release_capacity(enabled)
+    }
+
     /// Poll trailers
     ///
     /// This function **must** not be called until `Body::poll` returns `None`.
@@ -246,6 +732,45 @@ impl<B: IntoBuf> futures::Stream for Body<B> {
     }
 }
 
+// ===== impl Tunnel =====
+
+impl Read for Tunnel {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl AsyncRead for Tunnel {}
+
+impl Write for Tunnel {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncWrite for Tunnel {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.inner.shutdown()?;
+        Ok(Async::Ready(()))
+    }
+}
+
+// ===== impl Ping =====
+
+impl Future for Ping {
+    type Item = Duration;
+    type Error = ::Error;
+
+    fn poll(&mut self) -> Poll<Duration, ::Error> {
+        self.inner.poll_rtt()
+            .map_err(Into::into)
+    }
+}
+
 // ===== impl Send =====
 
 impl<T> Future for Send<T>
@@ -324,10 +849,10 @@ impl<T, B> Future for Flush<T, B>
 }
 
 impl<T, B: Buf> ReadPreface<T, B> {
-    fn new(codec: Codec<T, B>) -> Self {
+    fn new(codec: Codec<T, B>, pos: usize) -> Self {
         ReadPreface {
             codec: Some(codec),
-            pos: 0,
+            pos: pos,
         }
     }
 
@@ -363,8 +888,150 @@ impl<T, B> Future for ReadPreface<T, B>
     }
 }
 
+// ===== impl Sniff =====
+
+impl<T, B> Future for Sniff<T, B>
+    where T: AsyncRead + AsyncWrite + 'static,
+          B: IntoBuf + 'static,
+{
+    type Item = Sniffed<T, B>;
+    type Error = ::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let next = match self.state {
+                SniffState::ReadPreface(ref mut io, ref mut buf) => {
+                    let mut chunk = [0; 24];
+
+                    while buf.len() < PREFACE.len() {
+                        let rem = PREFACE.len() - buf.len();
+                        let n = try_nb!(io.as_mut().unwrap().read(&mut chunk[..rem]));
+
+                        if n == 0 {
+                            let err = io::Error::new(
+                                io::ErrorKind::UnexpectedEof,
+                                "connection closed before sending a request");
+                            return Err(err.into());
+                        }
+
+                        buf.extend_from_slice(&chunk[..n]);
+
+                        if !PREFACE.starts_with(buf) {
+                            debug!("client is not speaking HTTP/2");
+
+                            let pre = Bytes::from(mem::replace(buf, vec![]));
+                            let io = Rewind::new(io.take().unwrap(), pre);
+
+                            return Ok(Async::Ready(Sniffed::Http1(io)));
+                        }
+                    }
+
+                    SniffState::Handshake(self.builder.bind(io.take().unwrap(), PREFACE.len()))
+                }
+                SniffState::Handshake(ref mut handshake) => {
+                    let server = try_ready!(handshake.poll());
+                    return Ok(Async::Ready(Sniffed::Http2(server)));
+                }
+            };
+
+            self.state = next;
+        }
+    }
+}
+
+impl<T, B> fmt::Debug for Sniff<T, B>
+    where T: fmt::Debug,
+          B: fmt::Debug + IntoBuf,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "server::Sniff")
+    }
+}
+
+// ===== impl Sniffed =====
+
+impl<T, B> fmt::Debug for Sniffed<T, B>
+    where T: fmt::Debug,
+          B: fmt::Debug + IntoBuf,
+          B::Buf: fmt::Debug,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Sniffed::Http2(ref server) => {
+                fmt.debug_tuple("Http2").field(server).finish()
+            }
+            Sniffed::Http1(ref io) => {
+                fmt.debug_tuple("Http1").field(io).finish()
+            }
+        }
+    }
+}
+
+// ===== impl Rewind =====
+
+impl<T: AsyncWrite> Rewind<T> {
+    /// Respond with `505 HTTP Version Not Supported`.
+    ///
+    /// The returned future resolves to the underlying I/O once the response
+    /// is flushed, after which the connection should be closed.
+    pub fn reject_version(self) -> Reject<T> {
+        Reject::new(self.into_parts().0, VERSION_NOT_SUPPORTED)
+    }
+
+    /// Respond with `426 Upgrade Required`, asking the client to upgrade the
+    /// connection to h2c.
+    ///
+    /// The returned future resolves to the underlying I/O once the response
+    /// is flushed, after which the connection should be closed.
+    pub fn require_upgrade(self) -> Reject<T> {
+        Reject::new(self.into_parts().0, UPGRADE_REQUIRED)
+    }
+}
+
+// ===== impl Reject =====
+
+impl<T: AsyncWrite> Reject<T> {
+    fn new(io: T, response: &'static [u8]) -> Self {
+        fn flush<T: AsyncWrite>((io, _): (T, &'static [u8])) -> io_util::Flush<T> {
+            io_util::flush(io)
+        }
+
+        let inner = write_all(io, response)
+            .and_then(flush as fn((T, &'static [u8])) -> io_util::Flush<T>)
+            .map_err(::Error::from as fn(io::Error) -> ::Error);
+
+        Reject { inner: inner }
+    }
+}
+
+impl<T: AsyncWrite> Future for Reject<T> {
+    type Item = T;
+    type Error = ::Error;
+
+    fn poll(&mut self) -> Poll<T, ::Error> {
+        self.inner.poll()
+    }
+}
+
+impl<T> fmt::Debug for Reject<T>
+    where T: fmt::Debug,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "server::Reject")
+    }
+}
+
 // ===== impl Handshake =====
 
+impl<T, B: IntoBuf> Handshake<T, B> {
+    fn error(err: ::Error) -> Self
+        where T: 'static,
+              B: 'static,
+    {
+        Handshake { inner: Box::new(futures::future::err(err)) }
+    }
+}
+
 impl<T, B: IntoBuf> Future for Handshake<T, B> {
     type Item = Server<T, B>;
     type Error = ::Error;
@@ -383,6 +1050,102 @@ impl<T, B> fmt::Debug for Handshake<T, B>
     }
 }
 
+impl Peer {
+    pub fn convert_push_message(
+        stream_id: StreamId,
+        promised_id: StreamId,
+        request: Request<()>) -> frame::PushPromise
+    {
+        use http::request::Parts;
+
+        let (Parts { method, uri, headers, .. }, _) = request.into_parts();
+
+        let pseudo = frame::Pseudo::request(method, uri);
+
+        frame::PushPromise::new(stream_id, promised_id, pseudo, headers)
+    }
+
+    /// Converts the HTTP/1.1 request of an h2c upgrade into the HEADERS frame
+    /// opening stream 1.
+    ///
+    /// Returns `None` if the request's `Host` header is not a valid
+    /// authority.
+    pub fn convert_upgrade_message(request: Request<()>) -> Option<frame::Headers> {
+        use http::request::Parts;
+        use http::header::{self, HeaderName};
+        use http::uri::{self, Uri};
+
+        let (Parts { method, uri, mut headers, .. }, _) = request.into_parts();
+
+        // Connection specific header fields are not valid in HTTP/2
+        let mut connection_headers = vec![];
+
+        for value in headers.get_all(header::CONNECTION) {
+            let value = match value.to_str() {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+
+            for name in value.split(',') {
+                if let Ok(name) = HeaderName::from_bytes(name.trim().as_bytes()) {
+                    connection_headers.push(name);
+                }
+            }
+        }
+
+        for name in connection_headers {
+            headers.remove(name);
+        }
+
+        for name in &["connection", "upgrade", "http2-settings", "keep-alive",
+                      "proxy-connection", "transfer-encoding"]
+        {
+            headers.remove(*name);
+        }
+
+        if headers.get(header::TE).map(|te| te != "trailers").unwrap_or(false) {
+            headers.remove(header::TE);
+        }
+
+        // The `Host` header becomes the `:authority` pseudo header, and h2c
+        // is always cleartext
+        let host = headers.remove(header::HOST);
+        let mut parts = uri::Parts::from(uri);
+
+        if parts.scheme.is_none() {
+            parts.scheme = Some(uri::Scheme::HTTP);
+        }
+
+        if parts.authority.is_none() {
+            if let Some(host) = host {
+                let authority = uri::Authority::from_shared(Bytes::from(host.as_bytes()));
+
+                match authority {
+                    Ok(authority) => parts.authority = Some(authority),
+                    Err(_) => return None,
+                }
+            }
+        }
+
+        if parts.path_and_query.is_none() {
+            parts.path_and_query = Some(uri::PathAndQuery::from_static("/"));
+        }
+
+        let uri = match Uri::from_parts(parts) {
+            Ok(uri) => uri,
+            Err(_) => return None,
+        };
+
+        let pseudo = frame::Pseudo::request(method, uri);
+
+        // The request was fully received over HTTP/1.1
+        let mut frame = frame::Headers::new(1.into(), pseudo, headers);
+        frame.set_end_stream();
+
+        Some(frame)
+    }
+}
+
 impl proto::Peer for Peer {
     type Send = Response<()>;
     type Poll = Request<()>;
@@ -418,7 +1181,7 @@ impl proto::Peer for Peer {
     fn convert_poll_message(headers: frame::Headers)
         -> Result<Self::Poll, RecvError>
     {
-        use http::{Version, uri};
+        use http::{Method, Version, uri};
 
         let mut b = Request::builder();
 
@@ -436,6 +1199,34 @@ impl proto::Peer for Peer {
 
         b.version(Version::HTTP_2);
 
+        // CONNECT requests only carry :authority (RFC 7540 section 8.3)
+        let is_connect = pseudo.method == Some(Method::CONNECT) && pseudo.protocol.is_none();
+
+        if is_connect {
+            if pseudo.scheme.is_some() || pseudo.path.is_some() || pseudo.authority.is_none() {
+                malformed!();
+            }
+        }
+
+        // Extended CONNECT requests (RFC 8441) name the tunneled protocol with
+        // :protocol, and carry :scheme, :path and :authority like other
+        // requests.
+        let protocol = match pseudo.protocol {
+            Some(protocol) => {
+                if pseudo.method != Some(Method::CONNECT) ||
+                    pseudo.path.is_none() || pseudo.authority.is_none()
+                {
+                    malformed!();
+                }
+
+                match Protocol::from_string(protocol) {
+                    Ok(protocol) => Some(protocol),
+                    Err(_) => malformed!(),
+                }
+            }
+            None => None,
+        };
+
         if let Some(method) = pseudo.method {
             b.method(method);
         } else {
@@ -453,7 +1244,7 @@ impl proto::Peer for Peer {
         if let Some(scheme) = pseudo.scheme {
             // TODO: Don't unwrap
             parts.scheme = Some(uri::Scheme::from_shared(scheme.into_inner()).unwrap());
-        } else {
+        } else if !is_connect {
             malformed!();
         }
 
@@ -488,6 +1279,10 @@ impl proto::Peer for Peer {
 
         *request.headers_mut() = fields;
 
+        if let Some(protocol) = protocol {
+            request.extensions_mut().insert(protocol);
+        }
+
         Ok(request)
     }
 }
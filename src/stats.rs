@@ -0,0 +1,53 @@
+/// A snapshot of the flow control state of a connection or a stream.
+///
+/// Returned by `flow_stats` on clients, servers and their streams, this helps
+/// finding out why data is not flowing: a send window of zero means the peer
+/// has not released capacity, while a recv window of zero with unreleased
+/// data means the local application has not released capacity.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct FlowStats {
+    send_window: u32,
+    recv_window: u32,
+    buffered_send_data: usize,
+    unreleased_recv_data: usize,
+}
+
+// ===== impl FlowStats =====
+
+impl FlowStats {
+    pub(crate) fn new(send_window: u32,
+                      recv_window: u32,
+                      buffered_send_data: usize,
+                      unreleased_recv_data: usize)
+        -> Self
+    {
+        FlowStats {
+            send_window: send_window,
+            recv_window: recv_window,
+            buffered_send_data: buffered_send_data,
+            unreleased_recv_data: unreleased_recv_data,
+        }
+    }
+
+    /// Returns the amount of data the peer allows to be sent.
+    pub fn send_window(&self) -> u32 {
+        self.send_window
+    }
+
+    /// Returns the amount of data the peer is allowed to send.
+    pub fn recv_window(&self) -> u32 {
+        self.recv_window
+    }
+
+    /// Returns the amount of data queued to be sent that has not been written
+    /// to the socket yet.
+    pub fn buffered_send_data(&self) -> usize {
+        self.buffered_send_data
+    }
+
+    /// Returns the amount of data received whose capacity has not been
+    /// released yet.
+    pub fn unreleased_recv_data(&self) -> usize {
+        self.unreleased_recv_data
+    }
+}
@@ -0,0 +1,99 @@
+use proto::{self, Peer, WindowSize, MAX_WINDOW_SIZE};
+
+use futures::Async;
+use bytes::Bytes;
+
+use std::cmp;
+use std::io::{self, Cursor};
+
+/// Byte pipe over the DATA frames of a stream, shared by the client and server
+/// tunnels.
+///
+/// Writes are limited by the stream's send capacity, reads release the
+/// capacity of the data they consume and shutting down ends the stream.
+#[derive(Debug)]
+pub(crate) struct Pipe<P: Peer> {
+    inner: proto::StreamRef<Cursor<Bytes>, P>,
+
+    /// Received data not read yet
+    buf: Bytes,
+
+    /// True once END_STREAM has been sent
+    is_shutdown: bool,
+}
+
+// ===== impl Pipe =====
+
+impl<P: Peer> Pipe<P> {
+    pub fn new(mut inner: proto::StreamRef<Cursor<Bytes>, P>) -> Self {
+        inner.set_auto_release_capacity(true);
+
+        Pipe {
+            inner: inner,
+            buf: Bytes::new(),
+            is_shutdown: false,
+        }
+    }
+
+    pub fn read(&mut self, dst: &mut [u8]) -> io::Result<usize> {
+        // Empty DATA frames do not end the stream, so keep polling.
+        while self.buf.is_empty() {
+            match self.inner.poll_data() {
+                Ok(Async::Ready(Some(data))) => self.buf = data,
+                Ok(Async::Ready(None)) => return Ok(0),
+                Ok(Async::NotReady) => return Err(io::ErrorKind::WouldBlock.into()),
+                Err(e) => return Err(::Error::from(e).into()),
+            }
+        }
+
+        let n = cmp::min(dst.len(), self.buf.len());
+
+        dst[..n].copy_from_slice(&self.buf[..n]);
+        self.buf.advance(n);
+
+        Ok(n)
+    }
+
+    pub fn write(&mut self, src: &[u8]) -> io::Result<usize> {
+        if src.is_empty() {
+            return Ok(0);
+        }
+
+        let len = cmp::min(src.len(), MAX_WINDOW_SIZE as usize) as WindowSize;
+        self.inner.reserve_capacity(len);
+
+        let capacity = loop {
+            let capacity = self.inner.capacity();
+
+            if capacity > 0 {
+                break capacity as usize;
+            }
+
+            match self.inner.poll_capacity() {
+                Ok(Async::Ready(Some(_))) => {}
+                Ok(Async::Ready(None)) => return Err(io::ErrorKind::BrokenPipe.into()),
+                Ok(Async::NotReady) => return Err(io::ErrorKind::WouldBlock.into()),
+                Err(e) => return Err(::Error::from(e).into()),
+            }
+        };
+
+        let n = cmp::min(capacity, src.len());
+        let data = Bytes::from(&src[..n]);
+
+        self.inner.send_data(Cursor::new(data), false)
+            .map_err(::Error::from)?;
+
+        Ok(n)
+    }
+
+    pub fn shutdown(&mut self) -> io::Result<()> {
+        if !self.is_shutdown {
+            self.inner.send_data(Cursor::new(Bytes::new()), true)
+                .map_err(::Error::from)?;
+
+            self.is_shutdown = true;
+        }
+
+        Ok(())
+    }
+}
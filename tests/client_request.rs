@@ -1,6 +1,7 @@
 #[macro_use]
 extern crate log;
 
+#[macro_use]
 extern crate h2_test_support;
 use h2_test_support::prelude::*;
 
@@ -22,6 +23,30 @@ fn handshake() {
     h2.wait().unwrap();
 }
 
+#[test]
+fn handshake_sends_configured_settings() {
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let h2 = Client::builder()
+        .initial_window_size(1_000_000)
+        .max_concurrent_streams(10)
+        .enable_push(false)
+        .handshake::<_, Bytes>(io)
+        .unwrap()
+        .and_then(|conn| conn.unwrap());
+
+    let srv = srv.assert_client_handshake().unwrap()
+        .map(|(settings, _)| {
+            assert_eq!(settings.initial_window_size(), Some(1_000_000));
+            assert_eq!(settings.max_concurrent_streams(), Some(10));
+            assert!(!settings.is_push_enabled());
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
 #[test]
 fn recv_invalid_server_stream_id() {
     let _ = ::env_logger::init();
@@ -58,6 +83,1210 @@ fn recv_invalid_server_stream_id() {
     assert!(stream.wait().is_err());
 }
 
+#[test]
+fn recv_go_away_refuses_unprocessed_streams() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    fn request() -> Request<()> {
+        Request::builder()
+            .uri("https://http2.akamai.com/")
+            .body(()).unwrap()
+    }
+
+    let h2 = Client::handshake(io).unwrap()
+        .and_then(|mut client| {
+            let stream1 = client.request(request(), true).unwrap();
+            let stream3 = client.request(request(), true).unwrap();
+
+            let client = Rc::new(RefCell::new(client));
+            let conn = client.clone();
+
+            let streams = stream3
+                .then(move |res| {
+                    // Stream 3 was not processed by the server
+                    let err = res.unwrap_err();
+                    assert_eq!(err.reason(), Some(Reason::RefusedStream));
+                    assert_eq!(err.go_away_reason(), Some(Reason::NoError));
+                    assert_eq!(err.go_away_debug_data().unwrap(), "shutting down for deploy");
+
+                    // New requests are refused
+                    assert!(client.borrow_mut().request(request(), true).is_err());
+
+                    // Stream 1 runs to completion
+                    stream1
+                })
+                .map(|response| {
+                    assert_eq!(response.status(), StatusCode::OK);
+                })
+                .unwrap();
+
+            let conn = poll_fn(move || conn.borrow_mut().poll()).unwrap();
+
+            conn.join(streams)
+        });
+
+    let srv = srv.assert_client_handshake().unwrap()
+        .and_then(|(_, srv)| srv.into_future().unwrap())
+        .and_then(|(frame, srv)| {
+            assert_headers!(frame.unwrap());
+            srv.into_future().unwrap()
+        })
+        .map(|(frame, mut srv)| {
+            assert_headers!(frame.unwrap());
+
+            // Only stream 1 will be processed
+            let mut go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            go_away.set_debug_data("shutting down for deploy".into());
+            srv.send(go_away.into()).unwrap();
+
+            let mut response = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::response(StatusCode::OK),
+                HeaderMap::new());
+            response.set_end_stream();
+            srv.send(response.into()).unwrap();
+
+            srv
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn recv_go_away_with_debug_data() {
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let h2 = Client::handshake(io).unwrap()
+        .and_then(|conn| {
+            conn.then(|res| {
+                let err = res.unwrap_err();
+                assert_eq!(err.reason(), Some(Reason::EnhanceYourCalm));
+                assert_eq!(err.go_away_debug_data().unwrap(), "too many requests");
+                Ok::<_, ()>(())
+            })
+        });
+
+    let srv = srv.assert_client_handshake().unwrap()
+        .map(|(_, mut srv)| {
+            let mut go_away = frame::GoAway::new(0.into(), Reason::EnhanceYourCalm);
+            go_away.set_debug_data("too many requests".into());
+            srv.send(go_away.into()).unwrap();
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn recv_go_away_with_debug_data_fails_streams() {
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let h2 = Client::handshake(io).unwrap()
+        .and_then(|mut client| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+
+            let stream = client.request(request, true).unwrap()
+                .then(|res| {
+                    // Stream 1 was processed, but the connection failed
+                    let err = res.unwrap_err();
+                    assert_eq!(err.reason(), Some(Reason::EnhanceYourCalm));
+                    assert_eq!(err.go_away_debug_data().unwrap(), "too many requests");
+                    Ok::<_, ()>(())
+                });
+
+            let conn = client.then(|res| {
+                let err = res.unwrap_err();
+                assert_eq!(err.go_away_debug_data().unwrap(), "too many requests");
+                Ok::<_, ()>(())
+            });
+
+            conn.join(stream)
+        });
+
+    let srv = srv.assert_client_handshake().unwrap()
+        .and_then(|(_, srv)| srv.into_future().unwrap())
+        .map(|(frame, mut srv)| {
+            assert_headers!(frame.unwrap());
+
+            let mut go_away = frame::GoAway::new(1.into(), Reason::EnhanceYourCalm);
+            go_away.set_debug_data("too many requests".into());
+            srv.send(go_away.into()).unwrap();
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn send_go_away_with_debug_data() {
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let h2 = Client::handshake(io).unwrap()
+        .and_then(|mut conn| {
+            conn.set_go_away_debug_data("unexpected ack");
+
+            conn.then(|res| {
+                assert!(res.is_err());
+                Ok::<_, ()>(())
+            })
+        });
+
+    let srv = srv.assert_client_handshake().unwrap()
+        .and_then(|(_, mut srv)| {
+            // The handshake is complete, so this ACK is unexpected
+            srv.send(frame::Settings::ack().into()).unwrap();
+            srv.into_future().unwrap()
+        })
+        .map(|(frame, _)| {
+            let go_away = assert_go_away!(frame.unwrap());
+            assert_eq!(go_away.reason(), Reason::ProtocolError);
+            assert_eq!(go_away.debug_data(), "unexpected ack");
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn recv_push_promise() {
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let h2 = Client::handshake(io).unwrap()
+        .and_then(|mut client| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+            let mut stream = client.request(request, true).unwrap();
+
+            // Collect all pushes promised on the stream
+            let mut pushes = vec![];
+            let pushed = poll_fn(move || -> futures::Poll<_, h2::Error> {
+                loop {
+                    match stream.poll_pushed()? {
+                        futures::Async::Ready(Some(push)) => pushes.push(push),
+                        futures::Async::Ready(None) => return Ok(pushes.split_off(0).into()),
+                        futures::Async::NotReady => return Ok(futures::Async::NotReady),
+                    }
+                }
+            });
+
+            let pushed = pushed.unwrap()
+                .and_then(|pushes: Vec<client::PushPromise<Bytes>>| {
+                    assert_eq!(pushes.len(), 1);
+
+                    let push = pushes.into_iter().next().unwrap();
+                    let (request, response) = push.into_parts();
+                    assert_eq!(request.method(), Method::GET);
+                    assert_eq!(request.uri(), "https://http2.akamai.com/style.css");
+
+                    response.unwrap()
+                })
+                .map(|response| {
+                    assert_eq!(response.status(), StatusCode::OK);
+                });
+
+            client.unwrap().join(pushed)
+        });
+
+    let srv = srv.assert_client_handshake().unwrap()
+        .and_then(|(_, srv)| srv.into_future().unwrap())
+        .map(|(frame, mut srv)| {
+            assert_headers!(frame.unwrap());
+
+            let uri = "https://http2.akamai.com/style.css".parse().unwrap();
+            let push = frame::PushPromise::new(
+                1.into(),
+                2.into(),
+                frame::Pseudo::request(Method::GET, uri),
+                HeaderMap::new());
+            srv.send(push.into()).unwrap();
+
+            for id in &[1, 2] {
+                let mut response = frame::Headers::new(
+                    (*id).into(),
+                    frame::Pseudo::response(StatusCode::OK),
+                    HeaderMap::new());
+                response.set_end_stream();
+                srv.send(response.into()).unwrap();
+            }
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            srv.send(go_away.into()).unwrap();
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn recv_padded_push_promise() {
+    let _ = ::env_logger::init();
+
+    let mock = mock_io::Builder::new()
+        .handshake()
+        // Write GET /
+        .write(&[
+            0, 0, 0x10, 1, 5, 0, 0, 0, 1, 0x82, 0x87, 0x41, 0x8B, 0x9D, 0x29,
+                0xAC, 0x4B, 0x8F, 0xA8, 0xE9, 0x19, 0x97, 0x21, 0xE9, 0x84,
+        ])
+        .write(SETTINGS_ACK)
+        // Read PUSH_PROMISE for GET / with 3 bytes of padding
+        .read(&[
+            0, 0, 0x18, 5, 0xC, 0, 0, 0, 1, 3, 0, 0, 0, 2, 0x82, 0x87, 0x41,
+                0x8B, 0x9D, 0x29, 0xAC, 0x4B, 0x8F, 0xA8, 0xE9, 0x19, 0x97,
+                0x21, 0xE9, 0x84, 0, 0, 0,
+        ])
+        // Read responses
+        .read(&[0, 0, 1, 1, 5, 0, 0, 0, 1, 0x88])
+        .read(&[0, 0, 1, 1, 5, 0, 0, 0, 2, 0x88])
+        .build();
+
+    let mut h2 = Client::handshake(mock)
+        .wait().unwrap();
+
+    let request = Request::builder()
+        .uri("https://http2.akamai.com/")
+        .body(()).unwrap();
+
+    let mut stream = h2.request(request, true).unwrap();
+
+    let push = h2.run(poll_fn(|| stream.poll_pushed())).unwrap().unwrap();
+
+    let (request, pushed) = push.into_parts();
+    assert_eq!(request.method(), Method::GET);
+    assert_eq!(request.uri(), "https://http2.akamai.com/");
+
+    let resp = h2.run(poll_fn(|| stream.poll_response())).unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = h2.run(pushed).unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    h2.wait().unwrap();
+}
+
+#[test]
+fn recv_push_promise_with_continuation() {
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    // Large enough that the promised request spans multiple frames
+    let token = ::std::iter::repeat('x').take(64 * 1_024).collect::<String>();
+    let request_token = token.clone();
+
+    let h2 = Client::handshake(io).unwrap()
+        .and_then(move |mut client| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+            let mut stream = client.request(request, true).unwrap();
+
+            let pushed = poll_fn(move || stream.poll_pushed()).unwrap()
+                .and_then(move |push| {
+                    let (request, response) = push.unwrap().into_parts();
+                    assert_eq!(request.uri(), "https://http2.akamai.com/style.css");
+                    assert_eq!(request.headers()["x-token"], &request_token[..]);
+
+                    response.unwrap()
+                })
+                .map(|response| {
+                    assert_eq!(response.status(), StatusCode::OK);
+                });
+
+            client.unwrap().join(pushed)
+        });
+
+    let srv = srv.assert_client_handshake().unwrap()
+        .and_then(|(_, srv)| srv.into_future().unwrap())
+        .map(move |(frame, mut srv)| {
+            assert_headers!(frame.unwrap());
+
+            let mut fields = HeaderMap::new();
+            fields.insert("x-token", token.parse().unwrap());
+
+            let uri = "https://http2.akamai.com/style.css".parse().unwrap();
+            let push = frame::PushPromise::new(
+                1.into(),
+                2.into(),
+                frame::Pseudo::request(Method::GET, uri),
+                fields);
+            srv.send(push.into()).unwrap();
+
+            for id in &[1, 2] {
+                let mut response = frame::Headers::new(
+                    (*id).into(),
+                    frame::Pseudo::response(StatusCode::OK),
+                    HeaderMap::new());
+                response.set_end_stream();
+                srv.send(response.into()).unwrap();
+            }
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            srv.send(go_away.into()).unwrap();
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn refuse_push_promise() {
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let h2 = Client::handshake(io).unwrap()
+        .and_then(|mut client| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+            let mut stream = client.request(request, true).unwrap();
+
+            let refused = poll_fn(move || stream.poll_pushed()).unwrap()
+                .map(|push| push.unwrap().refuse());
+
+            client.unwrap().join(refused)
+        });
+
+    let srv = srv.assert_client_handshake().unwrap()
+        .and_then(|(_, srv)| srv.into_future().unwrap())
+        .and_then(|(frame, mut srv)| {
+            assert_headers!(frame.unwrap());
+
+            let uri = "https://http2.akamai.com/style.css".parse().unwrap();
+            let push = frame::PushPromise::new(
+                1.into(),
+                2.into(),
+                frame::Pseudo::request(Method::GET, uri),
+                HeaderMap::new());
+            srv.send(push.into()).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .map(|(frame, mut srv)| {
+            // The client cancels the push
+            let reset = assert_reset!(frame.unwrap());
+            assert_eq!(u32::from(reset.stream_id()), 2);
+            assert_eq!(reset.reason(), Reason::Cancel);
+
+            let mut response = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::response(StatusCode::OK),
+                HeaderMap::new());
+            response.set_end_stream();
+            srv.send(response.into()).unwrap();
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            srv.send(go_away.into()).unwrap();
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn send_and_recv_headers_larger_than_max_frame_size() {
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    // Large enough that the HPACK encoded block spans multiple frames
+    let cookie = ::std::iter::repeat('x').take(64 * 1_024).collect::<String>();
+    let token = ::std::iter::repeat('y').take(64 * 1_024).collect::<String>();
+
+    let request_cookie = cookie.clone();
+    let response_token = token.clone();
+
+    let h2 = Client::handshake(io).unwrap()
+        .and_then(move |mut client| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .header("cookie", &request_cookie[..])
+                .body(()).unwrap();
+
+            let response = client.request(request, true).unwrap()
+                .unwrap()
+                .map(move |response| {
+                    assert_eq!(response.status(), StatusCode::OK);
+                    assert_eq!(response.headers()["x-token"], &response_token[..]);
+                });
+
+            client.unwrap().join(response)
+        });
+
+    let srv = srv.assert_client_handshake().unwrap()
+        .and_then(|(_, srv)| srv.into_future().unwrap())
+        .map(move |(frame, mut srv)| {
+            // The CONTINUATION frames are assembled into a single HEADERS
+            // frame by the codec.
+            let headers = assert_headers!(frame.unwrap());
+            assert!(headers.is_end_stream());
+            assert_eq!(headers.fields()["cookie"], &cookie[..]);
+
+            let mut fields = HeaderMap::new();
+            fields.insert("x-token", token.parse().unwrap());
+
+            let mut response = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::response(StatusCode::OK),
+                fields);
+            response.set_end_stream();
+            srv.send(response.into()).unwrap();
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            srv.send(go_away.into()).unwrap();
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn request_with_priority() {
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let h2 = Client::handshake(io).unwrap()
+        .and_then(|mut client| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+
+            let stream1 = client.request(request, true).unwrap();
+
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/style.css")
+                .body(()).unwrap();
+
+            let priority = Priority::new(32)
+                .depends_on(&stream1)
+                .exclusive();
+
+            let stream3 = client.request_with_priority(request, priority, true).unwrap();
+
+            let responses = stream1.join(stream3)
+                .map(|(response1, response3)| {
+                    assert_eq!(response1.status(), StatusCode::OK);
+                    assert_eq!(response3.status(), StatusCode::OK);
+                })
+                .map_err(|e| panic!("unexpected error; {:?}", e));
+
+            client.unwrap().join(responses)
+        });
+
+    let srv = srv.assert_client_handshake().unwrap()
+        .and_then(|(_, srv)| srv.recv_frames(2))
+        .map(|(frames, mut srv)| {
+            let mut frames = frames.into_iter();
+
+            let headers = assert_headers!(frames.next().unwrap());
+            assert_eq!(headers.stream_id(), 1);
+            assert!(headers.stream_dep().is_none());
+
+            let headers = assert_headers!(frames.next().unwrap());
+            assert_eq!(headers.stream_id(), 3);
+
+            let stream_dep = headers.stream_dep().unwrap();
+            assert_eq!(stream_dep.dependency_id(), 1);
+            assert_eq!(stream_dep.weight(), 31);
+            assert!(stream_dep.is_exclusive());
+
+            for id in &[1, 3] {
+                let mut response = frame::Headers::new(
+                    (*id).into(),
+                    frame::Pseudo::response(StatusCode::OK),
+                    HeaderMap::new());
+                response.set_end_stream();
+                srv.send(response.into()).unwrap();
+            }
+
+            let go_away = frame::GoAway::new(3.into(), Reason::NoError);
+            srv.send(go_away.into()).unwrap();
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn update_stream_priority() {
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let h2 = Client::handshake(io).unwrap()
+        .and_then(|mut client| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+
+            let mut stream = client.request(request, true).unwrap();
+            stream.set_priority(Priority::new(64));
+
+            let response = stream
+                .map(|response| {
+                    assert_eq!(response.status(), StatusCode::OK);
+                })
+                .map_err(|e| panic!("unexpected error; {:?}", e));
+
+            client.unwrap().join(response)
+        });
+
+    let srv = srv.assert_client_handshake().unwrap()
+        .and_then(|(_, srv)| srv.recv_frames(2))
+        .map(|(frames, mut srv)| {
+            let mut frames = frames.into_iter();
+
+            let headers = assert_headers!(frames.next().unwrap());
+            assert_eq!(headers.stream_id(), 1);
+
+            // The PRIORITY frame follows the stream's HEADERS
+            let priority = assert_priority!(frames.next().unwrap());
+            assert_eq!(priority.stream_id(), 1);
+            assert_eq!(priority.dependency().dependency_id(), 0);
+            assert_eq!(priority.dependency().weight(), 63);
+            assert!(!priority.dependency().is_exclusive());
+
+            let mut response = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::response(StatusCode::OK),
+                HeaderMap::new());
+            response.set_end_stream();
+            srv.send(response.into()).unwrap();
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            srv.send(go_away.into()).unwrap();
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn update_stream_priority_with_blocked_data() {
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let h2 = Client::handshake(io).unwrap()
+        .and_then(|mut client| {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+
+            // More data than the windows allow
+            let mut stream = client.request(request, false).unwrap();
+            stream.send_data(Bytes::from(vec![0; 70_000]), true).unwrap();
+            stream.set_priority(Priority::new(64));
+
+            let response = stream
+                .map(|response| {
+                    assert_eq!(response.status(), StatusCode::OK);
+                })
+                .map_err(|e| panic!("unexpected error; {:?}", e));
+
+            client.unwrap().join(response)
+        });
+
+    let srv = srv.assert_client_handshake().unwrap()
+        .and_then(|(_, srv)| srv.recv_frames(6))
+        .and_then(|(frames, mut srv)| {
+            let mut frames = frames.into_iter();
+
+            let headers = assert_headers!(frames.next().unwrap());
+            assert_eq!(headers.stream_id(), 1);
+
+            // The PRIORITY frame does not wait for the stream's data
+            let priority = assert_priority!(frames.next().unwrap());
+            assert_eq!(priority.stream_id(), 1);
+            assert_eq!(priority.dependency().weight(), 63);
+
+            let len: usize = frames
+                .map(|frame| assert_data!(frame).payload().len())
+                .sum();
+            assert_eq!(len, 65_535);
+
+            let update = frame::WindowUpdate::new(0.into(), 4_465);
+            srv.send(update.into()).unwrap();
+            let update = frame::WindowUpdate::new(1.into(), 4_465);
+            srv.send(update.into()).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .map(|(frame, mut srv)| {
+            let data = assert_data!(frame.unwrap());
+            assert_eq!(data.payload().len(), 4_465);
+            assert!(data.is_end_stream());
+
+            let mut response = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::response(StatusCode::OK),
+                HeaderMap::new());
+            response.set_end_stream();
+            srv.send(response.into()).unwrap();
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            srv.send(go_away.into()).unwrap();
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn self_dependent_priority_is_ignored() {
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let h2 = Client::handshake(io).unwrap()
+        .and_then(|mut client| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+
+            let mut stream = client.request(request, true).unwrap();
+
+            // A stream cannot depend on itself, so no PRIORITY frame is sent.
+            let priority = Priority::new(16).depends_on(&stream);
+            stream.set_priority(priority);
+
+            stream.set_priority(Priority::new(64));
+
+            let response = stream
+                .map(|response| {
+                    assert_eq!(response.status(), StatusCode::OK);
+                })
+                .map_err(|e| panic!("unexpected error; {:?}", e));
+
+            client.unwrap().join(response)
+        });
+
+    let srv = srv.assert_client_handshake().unwrap()
+        .and_then(|(_, srv)| srv.recv_frames(2))
+        .map(|(frames, mut srv)| {
+            let mut frames = frames.into_iter();
+
+            let headers = assert_headers!(frames.next().unwrap());
+            assert_eq!(headers.stream_id(), 1);
+
+            // Only the valid priority change is sent
+            let priority = assert_priority!(frames.next().unwrap());
+            assert_eq!(priority.stream_id(), 1);
+            assert_eq!(priority.dependency().dependency_id(), 0);
+            assert_eq!(priority.dependency().weight(), 63);
+            assert!(!priority.dependency().is_exclusive());
+
+            let mut response = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::response(StatusCode::OK),
+                HeaderMap::new());
+            response.set_end_stream();
+            srv.send(response.into()).unwrap();
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            srv.send(go_away.into()).unwrap();
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn recv_priority_update_is_connection_error() {
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let h2 = Client::handshake(io).unwrap()
+        .and_then(|client| {
+            client.then(|res| {
+                assert!(res.is_err());
+                Ok::<_, ()>(())
+            })
+        });
+
+    let srv = srv.assert_client_handshake().unwrap()
+        .and_then(|(_, mut srv)| {
+            // Only clients may send PRIORITY_UPDATE frames
+            let update = frame::PriorityUpdate::new(
+                1.into(), frame::PriorityParams::new(0, false));
+            srv.send(update.into()).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .map(|(frame, _)| {
+            let go_away = assert_go_away!(frame.unwrap());
+            assert_eq!(go_away.reason(), Reason::ProtocolError);
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn upgrade_h2c() {
+    use h2_test_support::prelude::tokio_io::io::read_exact;
+
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let request = Request::builder()
+        .uri("http://example.com/index.html")
+        .header("accept", "text/html")
+        .body(()).unwrap();
+
+    let h2 = Client::builder()
+        .initial_window_size(1_000)
+        .upgrade::<_, Bytes>(io, request)
+        .unwrap()
+        .and_then(|upgraded| {
+            let (mut client, stream) = match upgraded {
+                client::Upgraded::Http2(client, stream) => (client, stream),
+                upgraded => panic!("unexpected protocol; {:?}", upgraded),
+            };
+
+            assert_eq!(stream.stream_id(), 1);
+
+            // The next request is sent on stream 3
+            let request = Request::builder()
+                .uri("http://example.com/style.css")
+                .body(()).unwrap();
+
+            let stream3 = client.request(request, true).unwrap();
+            assert_eq!(stream3.stream_id(), 3);
+
+            let response = stream
+                .map(|response| assert_eq!(response.status(), StatusCode::OK))
+                .map_err(|e| panic!("unexpected error; {:?}", e));
+
+            client.unwrap().join(response)
+        });
+
+    let expected = &b"GET /index.html HTTP/1.1\r\n\
+                      Host: example.com\r\n\
+                      accept: text/html\r\n\
+                      Connection: Upgrade, HTTP2-Settings\r\n\
+                      Upgrade: h2c\r\n\
+                      HTTP2-Settings: AAQAAAPo\r\n\r\n"[..];
+
+    let srv = read_exact(srv, vec![0; expected.len()])
+        .map_err(|e| panic!("err={:?}", e))
+        .and_then(move |(mut srv, buf)| {
+            assert_eq!(&buf[..], expected);
+
+            srv.send_bytes(b"HTTP/1.1 101 Switching Protocols\r\n\
+                             Connection: Upgrade\r\n\
+                             Upgrade: h2c\r\n\r\n");
+
+            srv.assert_client_handshake().unwrap()
+        })
+        .and_then(|(settings, srv)| {
+            assert_eq!(settings.initial_window_size(), Some(1_000));
+            srv.into_future().unwrap()
+        })
+        .map(|(frame, mut srv)| {
+            // No HEADERS frame is sent for the upgrade request
+            let headers = assert_headers!(frame.unwrap());
+            assert_eq!(headers.stream_id(), 3);
+
+            let mut response = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::response(StatusCode::OK),
+                HeaderMap::new());
+            response.set_end_stream();
+            srv.send(response.into()).unwrap();
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            srv.send(go_away.into()).unwrap();
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn upgrade_h2c_refused() {
+    use h2_test_support::prelude::tokio_io::io::{read_exact, read_to_end};
+
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let request = Request::builder()
+        .uri("http://example.com/index.html")
+        .body(()).unwrap();
+
+    let response = &b"HTTP/1.1 200 OK\r\n\
+                      Content-Length: 5\r\n\r\n\
+                      hello"[..];
+
+    let h2 = Client::builder()
+        .initial_window_size(1_000)
+        .upgrade::<_, Bytes>(io, request)
+        .unwrap()
+        .and_then(|upgraded| {
+            let io = match upgraded {
+                client::Upgraded::Http1(io) => io,
+                upgraded => panic!("unexpected protocol; {:?}", upgraded),
+            };
+
+            // The bytes read before falling back are replayed
+            read_to_end(io, vec![]).unwrap()
+        })
+        .map(move |(_, buf)| assert_eq!(&buf[..], response));
+
+    let expected = &b"GET /index.html HTTP/1.1\r\n\
+                      Host: example.com\r\n\
+                      Connection: Upgrade, HTTP2-Settings\r\n\
+                      Upgrade: h2c\r\n\
+                      HTTP2-Settings: AAQAAAPo\r\n\r\n"[..];
+
+    let srv = read_exact(srv, vec![0; expected.len()])
+        .map_err(|e| panic!("err={:?}", e))
+        .map(move |(mut srv, buf)| {
+            assert_eq!(&buf[..], expected);
+
+            // The server ignores the upgrade and closes the connection
+            srv.send_bytes(response);
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn upgrade_h2c_switched_to_other_protocol() {
+    use h2_test_support::prelude::tokio_io::io::read_exact;
+
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let request = Request::builder()
+        .uri("http://example.com/index.html")
+        .body(()).unwrap();
+
+    let h2 = Client::builder()
+        .initial_window_size(1_000)
+        .upgrade::<_, Bytes>(io, request)
+        .then(|res| {
+            let err = res.unwrap_err();
+            assert_eq!(err.reason(), Some(Reason::ProtocolError));
+            Ok::<(), ()>(())
+        });
+
+    let expected = &b"GET /index.html HTTP/1.1\r\n\
+                      Host: example.com\r\n\
+                      Connection: Upgrade, HTTP2-Settings\r\n\
+                      Upgrade: h2c\r\n\
+                      HTTP2-Settings: AAQAAAPo\r\n\r\n"[..];
+
+    let srv = read_exact(srv, vec![0; expected.len()])
+        .map_err(|e| panic!("err={:?}", e))
+        .map(move |(mut srv, buf)| {
+            assert_eq!(&buf[..], expected);
+
+            srv.send_bytes(b"HTTP/1.1 101 Switching Protocols\r\n\
+                             Connection: Upgrade\r\n\
+                             Upgrade: websocket\r\n\r\n");
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn protocol_from_str() {
+    let protocol = Protocol::from_str("websocket").unwrap();
+    assert_eq!(protocol.as_str(), "websocket");
+
+    // Protocols are tokens
+    assert!(Protocol::from_str("").is_err());
+    assert!(Protocol::from_str("web socket").is_err());
+    assert!(Protocol::from_str("chat/1").is_err());
+    assert!(Protocol::from_str("caf\u{e9}").is_err());
+}
+
+#[test]
+fn send_extended_connect_request() {
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    fn request() -> Request<()> {
+        let mut request = Request::builder()
+            .method(Method::CONNECT)
+            .uri("https://example.com/chat")
+            .body(()).unwrap();
+
+        request.extensions_mut().insert(Protocol::from_static("websocket"));
+        request
+    }
+
+    let h2 = Client::handshake(io).unwrap()
+        .and_then(|mut client| {
+            // The server's SETTINGS frame has not been received yet
+            assert!(!client.is_extended_connect_protocol_enabled());
+            assert!(client.request(request(), false).is_err());
+
+            let mut client = Some(client);
+
+            poll_fn(move || -> futures::Poll<_, h2::Error> {
+                client.as_mut().unwrap().poll()?;
+
+                if client.as_ref().unwrap().is_extended_connect_protocol_enabled() {
+                    Ok(client.take().unwrap().into())
+                } else {
+                    Ok(futures::Async::NotReady)
+                }
+            }).unwrap()
+        })
+        .and_then(|mut client| {
+            let response = client.request(request(), false).unwrap()
+                .map(|response| assert_eq!(response.status(), StatusCode::OK))
+                .map_err(|e| panic!("unexpected error; {:?}", e));
+
+            client.unwrap().join(response)
+        });
+
+    let mut settings = frame::Settings::default();
+    settings.set_enable_connect_protocol(true);
+
+    let srv = srv.assert_client_handshake_with_settings(settings).unwrap()
+        .and_then(|(_, srv)| srv.into_future().unwrap())
+        .map(|(frame, mut srv)| {
+            let headers = assert_headers!(frame.unwrap());
+            assert_eq!(headers.stream_id(), 1);
+            assert!(!headers.is_end_stream());
+
+            let pseudo = headers.pseudo();
+            assert_eq!(pseudo.method, Some(Method::CONNECT));
+            assert_eq!(pseudo.protocol.as_ref().map(|v| &**v), Some("websocket"));
+            assert_eq!(pseudo.scheme.as_ref().map(|v| &**v), Some("https"));
+            assert_eq!(pseudo.path.as_ref().map(|v| &**v), Some("/chat"));
+
+            let mut response = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::response(StatusCode::OK),
+                HeaderMap::new());
+            response.set_end_stream();
+            srv.send(response.into()).unwrap();
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            srv.send(go_away.into()).unwrap();
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn send_connect_request_without_authority() {
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let h2 = Client::handshake(io).unwrap()
+        .and_then(|mut client| {
+            let request = Request::builder()
+                .method(Method::CONNECT)
+                .uri("/chat")
+                .body(()).unwrap();
+
+            let err = client.request(request, false).unwrap_err();
+            assert_eq!(err.to_string(), "CONNECT request missing authority");
+
+            // No stream ID was used by the rejected request
+            let request = Request::builder()
+                .method(Method::CONNECT)
+                .uri("example.com:443")
+                .body(()).unwrap();
+
+            let response = client.request(request, true).unwrap()
+                .map(|response| assert_eq!(response.status(), StatusCode::OK))
+                .map_err(|e| panic!("unexpected error; {:?}", e));
+
+            client.unwrap().join(response)
+        });
+
+    let srv = srv.assert_client_handshake().unwrap()
+        .and_then(|(_, srv)| srv.into_future().unwrap())
+        .map(|(frame, mut srv)| {
+            let headers = assert_headers!(frame.unwrap());
+            assert_eq!(headers.stream_id(), 1);
+
+            let mut response = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::response(StatusCode::OK),
+                HeaderMap::new());
+            response.set_end_stream();
+            srv.send(response.into()).unwrap();
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            srv.send(go_away.into()).unwrap();
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn send_protocol_without_connect() {
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let mut settings = frame::Settings::default();
+    settings.set_enable_connect_protocol(true);
+
+    let h2 = Client::handshake(io).unwrap()
+        .and_then(|mut client| {
+            let mut request = Request::builder()
+                .method(Method::GET)
+                .uri("https://example.com/chat")
+                .body(()).unwrap();
+            request.extensions_mut().insert(Protocol::from_static("websocket"));
+
+            let err = client.request(request, true).unwrap_err();
+            assert_eq!(err.to_string(), "protocol requires a CONNECT request");
+
+            // No stream ID was used by the rejected request
+            let request = Request::builder()
+                .method(Method::GET)
+                .uri("https://example.com/chat")
+                .body(()).unwrap();
+
+            let response = client.request(request, true).unwrap()
+                .map(|response| assert_eq!(response.status(), StatusCode::OK))
+                .map_err(|e| panic!("unexpected error; {:?}", e));
+
+            client.unwrap().join(response)
+        });
+
+    let srv = srv.assert_client_handshake_with_settings(settings).unwrap()
+        .and_then(|(_, srv)| srv.into_future().unwrap())
+        .map(|(frame, mut srv)| {
+            let headers = assert_headers!(frame.unwrap());
+            assert_eq!(headers.stream_id(), 1);
+            assert!(headers.pseudo().protocol.is_none());
+
+            let mut response = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::response(StatusCode::OK),
+                HeaderMap::new());
+            response.set_end_stream();
+            srv.send(response.into()).unwrap();
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            srv.send(go_away.into()).unwrap();
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn connect_tunnel() {
+    use h2_test_support::prelude::tokio_io::io::{read_exact, read_to_end, shutdown, write_all};
+    use std::io;
+
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let h2 = Client::handshake(io).unwrap()
+        .and_then(|mut client| {
+            let request = Request::builder()
+                .method(Method::CONNECT)
+                .uri("example.com:443")
+                .body(()).unwrap();
+
+            let mut stream = Some(client.request(request, false).unwrap());
+
+            let tunnel = poll_fn(move || -> futures::Poll<_, io::Error> {
+                let response = match stream.as_mut().unwrap().poll_response()? {
+                    futures::Async::Ready(response) => response,
+                    futures::Async::NotReady => return Ok(futures::Async::NotReady),
+                };
+                assert_eq!(response.status(), StatusCode::OK);
+
+                Ok(stream.take().unwrap().into_tunnel().into())
+            })
+                .and_then(|tunnel| write_all(tunnel, "hello"))
+                .and_then(|(tunnel, _)| read_exact(tunnel, [0; 5]))
+                .and_then(|(tunnel, buf)| {
+                    assert_eq!(&buf, b"world");
+                    shutdown(tunnel)
+                })
+                .and_then(|tunnel| read_to_end(tunnel, vec![]))
+                .map(|(_, buf)| assert!(buf.is_empty()))
+                .map_err(|e| panic!("unexpected error; {:?}", e));
+
+            client.unwrap().join(tunnel)
+        });
+
+    let srv = srv.assert_client_handshake().unwrap()
+        .and_then(|(_, srv)| srv.into_future().unwrap())
+        .and_then(|(frame, mut srv)| {
+            let headers = assert_headers!(frame.unwrap());
+            assert_eq!(headers.stream_id(), 1);
+            assert!(!headers.is_end_stream());
+
+            // CONNECT requests only carry :method and :authority
+            let pseudo = headers.pseudo();
+            assert_eq!(pseudo.method, Some(Method::CONNECT));
+            assert_eq!(pseudo.authority.as_ref().map(|v| &**v), Some("example.com:443"));
+            assert!(pseudo.scheme.is_none());
+            assert!(pseudo.path.is_none());
+
+            let response = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::response(StatusCode::OK),
+                HeaderMap::new());
+            srv.send(response.into()).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .and_then(|(frame, mut srv)| {
+            let data = assert_data!(frame.unwrap());
+            assert_eq!(data.payload(), &b"hello"[..]);
+            assert!(!data.is_end_stream());
+
+            let data = frame::Data::new(1.into(), Bytes::from_static(b"world").into_buf());
+            srv.send(data.into()).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .and_then(|(frame, srv)| {
+            // Reading from the tunnel released the capacity of the data
+            match frame.unwrap() {
+                frame::Frame::WindowUpdate(v) => {
+                    assert_eq!(u32::from(v.stream_id()), 0);
+                    assert_eq!(v.size_increment(), 5);
+                }
+                frame => panic!("unexpected frame; {:?}", frame),
+            }
+
+            srv.into_future().unwrap()
+        })
+        .map(|(frame, mut srv)| {
+            let data = assert_data!(frame.unwrap());
+            assert!(data.payload().is_empty());
+            assert!(data.is_end_stream());
+
+            let mut data = frame::Data::new(1.into(), Bytes::new().into_buf());
+            data.set_end_stream(true);
+            srv.send(data.into()).unwrap();
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            srv.send(go_away.into()).unwrap();
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
 #[test]
 #[ignore]
 fn request_without_scheme() {
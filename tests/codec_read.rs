@@ -98,6 +98,43 @@ fn read_data_stream_id_zero() {
     poll_err!(codec);
 }
 
+// ===== PRIORITY_UPDATE =====
+
+#[test]
+fn read_priority_update() {
+    let mut codec = raw_codec! {
+        read => [
+            0, 0, 15, 0x10, 0, 0, 0, 0, 0,
+            0, 0, 0, 1,
+            "u=1;x, i, z",
+        ];
+    };
+
+    let frame = match codec.poll() {
+        Ok(futures::Async::Ready(Some(frame::Frame::PriorityUpdate(frame)))) => frame,
+        frame => panic!("expected priority update frame; actual={:?}", frame),
+    };
+
+    assert_eq!(frame.prioritized_id(), 1);
+    assert_eq!(frame.params().urgency(), 1);
+    assert!(frame.params().is_incremental());
+
+    assert_closed!(codec);
+}
+
+#[test]
+fn read_priority_update_stream_id_not_zero() {
+    let mut codec = raw_codec! {
+        read => [
+            0, 0, 7, 0x10, 0, 0, 0, 0, 1,
+            0, 0, 0, 1,
+            "u=1",
+        ];
+    };
+
+    poll_err!(codec);
+}
+
 // ===== HEADERS =====
 
 #[test]
@@ -1,3 +1,4 @@
+#[macro_use]
 extern crate h2_test_support;
 use h2_test_support::prelude::*;
 
@@ -69,3 +70,818 @@ fn recv_data_overflows_window() {
 fn recv_window_update_causes_overflow() {
     // A received window update causes the window to overflow.
 }
+
+#[test]
+fn adaptive_window_grows_recv_windows() {
+    let _ = ::env_logger::init();
+    let (io, client) = mock::new();
+
+    let srv = Server::builder()
+        .adaptive_window(true)
+        .max_adaptive_window_size(100_000)
+        .handshake::<_, Bytes>(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            // Hold on to the stream without releasing capacity
+            let (_, stream) = req.unwrap();
+
+            // The connection closes once the client sends GOAWAY
+            srv.into_future().unwrap()
+                .map(move |(req, _)| {
+                    assert!(req.is_none());
+                    drop(stream);
+                })
+        });
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, mut client)| {
+            let uri = "https://example.com/".parse().unwrap();
+            let request = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::request(Method::POST, uri),
+                HeaderMap::new());
+            client.send(request.into()).unwrap();
+
+            // Fill most of the 65,535 byte window
+            for _ in 0..4 {
+                let data = frame::Data::new(1.into(), Bytes::from(vec![0; 15_000]).into_buf());
+                client.send(data.into()).unwrap();
+            }
+
+            // The server measures the round-trip time
+            client.into_future().unwrap()
+        })
+        .and_then(|(frame, mut client)| {
+            let ping = assert_ping!(frame.unwrap());
+            assert!(!ping.is_ack());
+
+            let pong = frame::Ping::pong(ping.into_payload());
+            client.send(pong.into()).unwrap();
+
+            client.recv_frames(2)
+        })
+        .map(|(mut frames, mut client)| {
+            // The windows grow to twice the data received, up to the max
+            let settings = assert_settings!(frames.remove(0));
+            assert_eq!(settings.initial_window_size(), Some(100_000));
+
+            match frames.remove(0) {
+                frame::Frame::WindowUpdate(v) => {
+                    assert!(v.stream_id().is_zero());
+                    assert_eq!(v.size_increment(), 100_000 - 65_535);
+                }
+                f => panic!("expected WINDOW_UPDATE; actual={:?}", f),
+            }
+
+            client.send(frame::Settings::ack().into()).unwrap();
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn adaptive_window_follows_local_window_size() {
+    let _ = ::env_logger::init();
+    let (io, client) = mock::new();
+
+    let srv = Server::builder()
+        .adaptive_window(true)
+        .max_adaptive_window_size(100_000)
+        .handshake::<_, Bytes>(io).unwrap()
+        .and_then(|mut srv| {
+            // Shrink the stream windows
+            srv.set_initial_window_size(20_000);
+            srv.into_future().unwrap()
+        })
+        .and_then(|(req, srv)| {
+            let (_, stream) = req.unwrap();
+
+            srv.into_future().unwrap()
+                .map(move |(req, _)| {
+                    assert!(req.is_none());
+                    drop(stream);
+                })
+        });
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, client)| client.into_future().unwrap())
+        .and_then(|(frame, mut client)| {
+            let settings = assert_settings!(frame.unwrap());
+            assert_eq!(settings.initial_window_size(), Some(20_000));
+            client.send(frame::Settings::ack().into()).unwrap();
+
+            let uri = "https://example.com/".parse().unwrap();
+            let request = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::request(Method::POST, uri),
+                HeaderMap::new());
+            client.send(request.into()).unwrap();
+
+            // Fill most of the shrunk window
+            let data = frame::Data::new(1.into(), Bytes::from(vec![0; 15_000]).into_buf());
+            client.send(data.into()).unwrap();
+
+            client.into_future().unwrap()
+        })
+        .and_then(|(frame, mut client)| {
+            let ping = assert_ping!(frame.unwrap());
+            let pong = frame::Ping::pong(ping.into_payload());
+            client.send(pong.into()).unwrap();
+
+            client.into_future().unwrap()
+        })
+        .map(|(frame, mut client)| {
+            // The stream windows grow from the shrunk size, while the
+            // connection window is still large enough.
+            let settings = assert_settings!(frame.unwrap());
+            assert_eq!(settings.initial_window_size(), Some(30_000));
+
+            client.send(frame::Settings::ack().into()).unwrap();
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn recv_more_than_initial_window() {
+    let _ = ::env_logger::init();
+    let (io, client) = mock::new();
+
+    let srv = Server::handshake(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (request, stream) = req.unwrap();
+
+            // Read the whole initial window
+            let mut body = Some(request.into_body());
+            let mut received = 0;
+
+            poll_fn(move || -> futures::Poll<_, h2::Error> {
+                while received < 65_535 {
+                    match body.as_mut().unwrap().poll()? {
+                        futures::Async::Ready(Some(chunk)) => received += chunk.len(),
+                        futures::Async::Ready(None) => panic!("unexpected end of body"),
+                        futures::Async::NotReady => return Ok(futures::Async::NotReady),
+                    }
+                }
+
+                Ok(body.take().unwrap().into())
+            })
+                .map(move |body| (body, stream, srv))
+        })
+        .and_then(|(mut body, mut stream, srv)| {
+            body.release_capacity(65_535).unwrap();
+
+            // The data past the initial window is accepted
+            let rest = body.into_future().unwrap()
+                .map(move |(chunk, _)| {
+                    assert_eq!(chunk.unwrap().len(), 10);
+
+                    let response = Response::builder()
+                        .status(StatusCode::OK)
+                        .body(()).unwrap();
+                    stream.send_response(response, true).unwrap();
+                });
+
+            rest.join(srv.into_future().unwrap())
+        })
+        .map(|(_, (req, _))| assert!(req.is_none()));
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, mut client)| {
+            let uri = "https://example.com/".parse().unwrap();
+            let request = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::request(Method::POST, uri),
+                HeaderMap::new());
+            client.send(request.into()).unwrap();
+
+            for &len in &[16_384, 16_384, 16_384, 16_383] {
+                let data = frame::Data::new(1.into(), Bytes::from(vec![0; len]).into_buf());
+                client.send(data.into()).unwrap();
+            }
+
+            client.recv_frames(2)
+        })
+        .and_then(|(frames, mut client)| {
+            let updates: Vec<(u32, u32)> = frames.into_iter()
+                .map(|frame| match frame {
+                    frame::Frame::WindowUpdate(v) => {
+                        (u32::from(v.stream_id()), v.size_increment())
+                    }
+                    f => panic!("expected WINDOW_UPDATE; actual={:?}", f),
+                })
+                .collect();
+
+            assert_eq!(updates, vec![(0, 65_535), (1, 65_535)]);
+
+            // Both windows are open again
+            let mut data = frame::Data::new(1.into(), Bytes::from(vec![0; 10]).into_buf());
+            data.set_end_stream(true);
+            client.send(data.into()).unwrap();
+
+            client.into_future().unwrap()
+        })
+        .map(|(frame, mut client)| {
+            let headers = assert_headers!(frame.unwrap());
+            assert_eq!(u32::from(headers.stream_id()), 1);
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn auto_release_capacity_batches_window_updates() {
+    let _ = ::env_logger::init();
+    let (io, client) = mock::new();
+
+    let srv = Server::builder()
+        .auto_release_capacity(true)
+        .handshake::<_, Bytes>(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (request, stream) = req.unwrap();
+
+            // Capacity is released without calling `release_capacity`
+            request.into_body().take(3).collect().unwrap()
+                .map(move |chunks| (chunks, stream, srv))
+        })
+        .and_then(|(chunks, stream, srv)| {
+            assert_eq!(chunks.len(), 3);
+
+            // The connection closes once the client sends GOAWAY
+            srv.into_future().unwrap()
+                .map(move |(req, _)| {
+                    assert!(req.is_none());
+                    drop(stream);
+                })
+        });
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, mut client)| {
+            let uri = "https://example.com/".parse().unwrap();
+            let request = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::request(Method::POST, uri),
+                HeaderMap::new());
+            client.send(request.into()).unwrap();
+
+            // No WINDOW_UPDATE is sent until half of the window is released
+            for &len in &[16_000, 16_000, 1_000] {
+                let data = frame::Data::new(1.into(), Bytes::from(vec![0; len]).into_buf());
+                client.send(data.into()).unwrap();
+            }
+
+            client.recv_frames(2)
+        })
+        .map(|(frames, mut client)| {
+            let updates: Vec<(u32, u32)> = frames.into_iter()
+                .map(|frame| match frame {
+                    frame::Frame::WindowUpdate(v) => {
+                        (u32::from(v.stream_id()), v.size_increment())
+                    }
+                    f => panic!("expected WINDOW_UPDATE; actual={:?}", f),
+                })
+                .collect();
+
+            assert_eq!(updates, vec![(0, 33_000), (1, 33_000)]);
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn auto_release_capacity_on_body() {
+    let _ = ::env_logger::init();
+    let (io, client) = mock::new();
+
+    let srv = Server::handshake(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (request, stream) = req.unwrap();
+
+            let mut body = request.into_body();
+            body.set_auto_release_capacity(true);
+
+            body.into_future().unwrap()
+                .map(move |(chunk, _)| (chunk, stream, srv))
+        })
+        .and_then(|(chunk, stream, srv)| {
+            assert_eq!(chunk.unwrap().len(), 16_000);
+
+            // The connection closes once the client sends GOAWAY
+            srv.into_future().unwrap()
+                .map(move |(req, _)| {
+                    assert!(req.is_none());
+                    drop(stream);
+                })
+        });
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, mut client)| {
+            let uri = "https://example.com/".parse().unwrap();
+            let request = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::request(Method::POST, uri),
+                HeaderMap::new());
+            client.send(request.into()).unwrap();
+
+            let data = frame::Data::new(1.into(), Bytes::from(vec![0; 16_000]).into_buf());
+            client.send(data.into()).unwrap();
+
+            client.into_future().unwrap()
+        })
+        .map(|(frame, mut client)| {
+            // Only the stream's updates are batched
+            match frame.unwrap() {
+                frame::Frame::WindowUpdate(v) => {
+                    assert!(v.stream_id().is_zero());
+                    assert_eq!(v.size_increment(), 16_000);
+                }
+                f => panic!("expected WINDOW_UPDATE; actual={:?}", f),
+            }
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn flow_stats() {
+    let _ = ::env_logger::init();
+    let (io, client) = mock::new();
+
+    let srv = Server::handshake(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (request, stream) = req.unwrap();
+
+            request.into_body().into_future().unwrap()
+                .map(move |(chunk, body)| {
+                    assert_eq!(chunk.unwrap().len(), 1_000);
+                    (body, stream, srv)
+                })
+        })
+        .and_then(|(mut body, mut stream, mut srv)| {
+            let stats = srv.flow_stats();
+            assert_eq!(stats.send_window(), 65_535);
+            assert_eq!(stats.recv_window(), 65_535 - 1_000);
+            assert_eq!(stats.buffered_send_data(), 0);
+            assert_eq!(stats.unreleased_recv_data(), 1_000);
+
+            let response = Response::builder()
+                .status(StatusCode::OK)
+                .body(()).unwrap();
+            stream.send_response(response, false).unwrap();
+            stream.send_data("hello".into(), false).unwrap();
+
+            let stats = stream.flow_stats();
+            assert_eq!(stats.send_window(), 65_535);
+            assert_eq!(stats.recv_window(), 65_535 - 1_000);
+            assert_eq!(stats.buffered_send_data(), 5);
+            assert_eq!(stats.unreleased_recv_data(), 1_000);
+
+            body.release_capacity(1_000).unwrap();
+
+            assert_eq!(stream.flow_stats().unreleased_recv_data(), 0);
+            assert_eq!(srv.flow_stats().unreleased_recv_data(), 0);
+
+            // The connection closes once the client sends GOAWAY
+            srv.into_future().unwrap()
+                .map(move |(req, _)| {
+                    assert!(req.is_none());
+
+                    // The data was written
+                    let stats = stream.flow_stats();
+                    assert_eq!(stats.send_window(), 65_535 - 5);
+                    assert_eq!(stats.buffered_send_data(), 0);
+                })
+        });
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, mut client)| {
+            let uri = "https://example.com/".parse().unwrap();
+            let request = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::request(Method::POST, uri),
+                HeaderMap::new());
+            client.send(request.into()).unwrap();
+
+            let data = frame::Data::new(1.into(), Bytes::from(vec![0; 1_000]).into_buf());
+            client.send(data.into()).unwrap();
+
+            // HEADERS, DATA and WINDOW_UPDATE frames for the connection and
+            // the stream
+            client.recv_frames(4)
+        })
+        .map(|(_, mut client)| {
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn capacity_after_partial_send() {
+    let _ = ::env_logger::init();
+    let (io, client) = mock::new();
+
+    let srv = Server::handshake(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, mut stream) = req.unwrap();
+
+            let response = Response::builder()
+                .status(StatusCode::OK)
+                .body(()).unwrap();
+            stream.send_response(response, false).unwrap();
+
+            stream.reserve_capacity(10);
+            assert_eq!(stream.capacity(), 10);
+
+            stream.send_data("abcd".into(), false).unwrap();
+            assert_eq!(stream.capacity(), 6);
+
+            let mut srv = Some(srv);
+
+            // Write the data
+            poll_fn(move || {
+                srv.as_mut().unwrap().poll_close().unwrap();
+
+                if stream.flow_stats().buffered_send_data() > 0 {
+                    return Ok(futures::Async::NotReady);
+                }
+
+                Ok::<_, h2::Error>((srv.take().unwrap(), stream).into())
+            })
+        })
+        .and_then(|(srv, mut stream)| {
+            // The data sent is no longer counted as buffered, so the rest of
+            // the reservation remains
+            assert_eq!(stream.capacity(), 6);
+
+            // Reserving again only requests the capacity that was sent
+            stream.reserve_capacity(10);
+            assert_eq!(stream.capacity(), 10);
+
+            stream.send_data(Bytes::new(), true).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, mut client)| {
+            let uri = "https://example.com/".parse().unwrap();
+            let mut request = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::request(Method::GET, uri),
+                HeaderMap::new());
+            request.set_end_stream();
+            client.send(request.into()).unwrap();
+
+            client.recv_frames(3)
+        })
+        .map(|(frames, mut client)| {
+            let data = assert_data!(frames.into_iter().nth(1).unwrap());
+            assert_eq!(data.payload(), &b"abcd"[..]);
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn padding_counts_against_flow_control() {
+    let _ = ::env_logger::init();
+    let (io, client) = mock::new();
+
+    let srv = Server::builder()
+        .padding(Padding::fixed(10))
+        .handshake::<_, Bytes>(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, mut stream) = req.unwrap();
+
+            let response = Response::builder()
+                .status(StatusCode::OK)
+                .body(()).unwrap();
+            stream.send_response(response, false).unwrap();
+            stream.send_data(Bytes::from(vec![0; 300]), false).unwrap();
+            stream.send_data("hello".into(), true).unwrap();
+
+            // The connection closes once the client sends GOAWAY
+            srv.into_future().unwrap()
+                .map(move |(req, _)| {
+                    assert!(req.is_none());
+
+                    // Each DATA frame consumed its padding and pad length
+                    let stats = stream.flow_stats();
+                    assert_eq!(stats.send_window(), 65_535 - 311 - 16);
+                    assert_eq!(stats.buffered_send_data(), 0);
+                })
+        });
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, mut client)| {
+            let uri = "https://example.com/".parse().unwrap();
+            let mut request = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::request(Method::GET, uri),
+                HeaderMap::new());
+            request.set_end_stream();
+            client.send(request.into()).unwrap();
+
+            client.recv_frames(3)
+        })
+        .map(|(mut frames, mut client)| {
+            let data2 = assert_data!(frames.pop().unwrap());
+            let data1 = assert_data!(frames.pop().unwrap());
+            let headers = assert_headers!(frames.pop().unwrap());
+
+            assert!(headers.is_padded());
+
+            assert_eq!(data1.payload().len(), 300);
+            assert_eq!(data1.pad_len(), Some(10));
+            assert!(!data1.is_end_stream());
+
+            assert_eq!(data2.payload(), &b"hello"[..]);
+            assert_eq!(data2.pad_len(), Some(10));
+            assert!(data2.is_end_stream());
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn padding_shrinks_data_to_fit_window() {
+    let _ = ::env_logger::init();
+    let (io, client) = mock::new();
+
+    let padding = Padding::policy(|frame, _| {
+        match frame {
+            PaddedFrame::Data => 5,
+            PaddedFrame::Headers => 0,
+        }
+    });
+
+    let srv = Server::builder()
+        .padding(padding)
+        .handshake::<_, Bytes>(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, mut stream) = req.unwrap();
+
+            let response = Response::builder()
+                .status(StatusCode::OK)
+                .body(()).unwrap();
+            stream.send_response(response, false).unwrap();
+            stream.send_data(Bytes::from(vec![0; 20]), true).unwrap();
+
+            // The connection closes once the client sends GOAWAY
+            srv.into_future().unwrap()
+                .map(move |(req, _)| {
+                    assert!(req.is_none());
+                    drop(stream);
+                })
+        });
+
+    let mut settings = frame::Settings::default();
+    settings.set_initial_window_size(Some(20));
+
+    let client = client.assert_server_handshake_with_settings(settings).unwrap()
+        .and_then(|(_, mut client)| {
+            let uri = "https://example.com/".parse().unwrap();
+            let mut request = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::request(Method::GET, uri),
+                HeaderMap::new());
+            request.set_end_stream();
+            client.send(request.into()).unwrap();
+
+            client.recv_frames(2)
+        })
+        .and_then(|(mut frames, mut client)| {
+            let data = assert_data!(frames.pop().unwrap());
+            let headers = assert_headers!(frames.pop().unwrap());
+
+            assert!(!headers.is_padded());
+
+            // Less data is sent to make room for the padding
+            assert_eq!(data.payload().len(), 14);
+            assert_eq!(data.pad_len(), Some(5));
+            assert!(!data.is_end_stream());
+
+            let update = frame::WindowUpdate::new(1.into(), 6);
+            client.send(update.into()).unwrap();
+
+            client.into_future().unwrap()
+        })
+        .map(|(frame, mut client)| {
+            // The window is too small to fit any padding
+            let data = assert_data!(frame.unwrap());
+            assert_eq!(data.payload().len(), 6);
+            assert_eq!(data.pad_len(), None);
+            assert!(data.is_end_stream());
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn shrinking_reserved_capacity_returns_it_to_the_connection() {
+    let _ = ::env_logger::init();
+    let (io, client) = mock::new();
+
+    let srv = Server::handshake(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, stream1) = req.unwrap();
+
+            srv.into_future().unwrap()
+                .map(move |(req, srv)| (stream1, req, srv))
+        })
+        .and_then(|(mut stream1, req, srv)| {
+            let (_, mut stream2) = req.unwrap();
+
+            let response = || {
+                Response::builder()
+                    .status(StatusCode::OK)
+                    .body(()).unwrap()
+            };
+            stream1.send_response(response(), false).unwrap();
+            stream2.send_response(response(), false).unwrap();
+
+            // The first stream takes the whole connection window
+            stream1.reserve_capacity(65_535);
+            assert_eq!(stream1.capacity(), 65_535);
+
+            stream2.reserve_capacity(10);
+            assert_eq!(stream2.capacity(), 0);
+
+            // Shrinking the reservation hands the excess to the other stream
+            stream1.reserve_capacity(100);
+            assert_eq!(stream1.capacity(), 100);
+            assert_eq!(stream2.capacity(), 10);
+
+            stream1.send_data(Bytes::new(), true).unwrap();
+            stream2.send_data(Bytes::new(), true).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, mut client)| {
+            for id in &[1, 3] {
+                let uri = "https://example.com/".parse().unwrap();
+                let mut request = frame::Headers::new(
+                    (*id).into(),
+                    frame::Pseudo::request(Method::GET, uri),
+                    HeaderMap::new());
+                request.set_end_stream();
+                client.send(request.into()).unwrap();
+            }
+
+            // HEADERS and DATA frames on both streams
+            client.recv_frames(4)
+        })
+        .map(|(_, mut client)| {
+            let go_away = frame::GoAway::new(3.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn poll_capacity_woken_by_window_update() {
+    use futures::executor::{self, Notify};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    struct Flag(AtomicBool);
+
+    impl Notify for Flag {
+        fn notify(&self, _: usize) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let _ = ::env_logger::init();
+    let (io, client) = mock::new();
+
+    let srv = Server::handshake(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, mut stream) = req.unwrap();
+
+            let response = Response::builder()
+                .status(StatusCode::OK)
+                .body(()).unwrap();
+            stream.send_response(response, false).unwrap();
+            stream.reserve_capacity(5);
+
+            // Wait for capacity from a task other than the connection's
+            let mut stream = Some(stream);
+            let flag = Arc::new(Flag(AtomicBool::new(false)));
+            let mut capacity = executor::spawn(poll_fn(move || -> futures::Poll<_, h2::Error> {
+                match stream.as_mut().unwrap().poll_capacity()? {
+                    futures::Async::Ready(n) => Ok((n, stream.take().unwrap()).into()),
+                    futures::Async::NotReady => Ok(futures::Async::NotReady),
+                }
+            }));
+            assert!(capacity.poll_future_notify(&flag, 0).unwrap().is_not_ready());
+
+            let mut srv = Some(srv);
+
+            poll_fn(move || {
+                srv.as_mut().unwrap().poll_close().unwrap();
+
+                if !flag.0.load(Ordering::SeqCst) {
+                    return Ok(futures::Async::NotReady);
+                }
+
+                match capacity.poll_future_notify(&flag, 0).unwrap() {
+                    futures::Async::Ready((n, stream)) => {
+                        assert_eq!(n, Some(5));
+                        Ok::<_, h2::Error>((srv.take().unwrap(), stream).into())
+                    }
+                    futures::Async::NotReady => panic!("woken without capacity"),
+                }
+            })
+        })
+        .and_then(|(srv, mut stream)| {
+            stream.send_data("hello".into(), true).unwrap();
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    let mut settings = frame::Settings::default();
+    settings.set_initial_window_size(Some(0));
+
+    let client = client.assert_server_handshake_with_settings(settings).unwrap()
+        .and_then(|(_, mut client)| {
+            let uri = "https://example.com/".parse().unwrap();
+            let mut request = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::request(Method::GET, uri),
+                HeaderMap::new());
+            request.set_end_stream();
+            client.send(request.into()).unwrap();
+
+            client.into_future().unwrap()
+        })
+        .and_then(|(frame, mut client)| {
+            assert_headers!(frame.unwrap());
+
+            let update = frame::WindowUpdate::new(1.into(), 5);
+            client.send(update.into()).unwrap();
+
+            client.into_future().unwrap()
+        })
+        .map(|(frame, mut client)| {
+            let data = assert_data!(frame.unwrap());
+            assert_eq!(data.payload(), &b"hello"[..]);
+            assert!(data.is_end_stream());
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
@@ -33,3 +33,84 @@ fn recv_single_ping() {
     let _ = h2.join(mock)
         .wait().unwrap();
 }
+
+#[test]
+fn send_ping_and_measure_rtt() {
+    let _ = ::env_logger::init();
+    let (m, mut mock) = mock::new();
+
+    let h2 = Client::handshake(m).unwrap()
+        .and_then(|mut conn| {
+            let ping = conn.ping().unwrap();
+            conn.unwrap().join(ping)
+        });
+
+    // Send the server's SETTINGS
+    mock.send(frame::Settings::default().into()).unwrap();
+
+    let mock = mock.read_preface().unwrap()
+        .and_then(|mock| mock.into_future().unwrap())
+        .and_then(|(frame, mut mock)| {
+            let settings = assert_settings!(frame.unwrap());
+            assert!(!settings.is_ack());
+
+            mock.send(frame::Settings::ack().into()).unwrap();
+
+            // The PING is queued before the connection is first polled, so it
+            // is written before the client acknowledges the server SETTINGS.
+            mock.into_future().unwrap()
+        })
+        .and_then(|(frame, mut mock)| {
+            let ping = assert_ping!(frame.unwrap());
+
+            // Not an ACK, and the payload is unique
+            assert!(!ping.is_ack());
+            assert!(*ping.payload() != <[u8; 8]>::default());
+
+            let pong = frame::Ping::pong(*ping.payload());
+            mock.send(pong.into()).unwrap();
+
+            mock.into_future().unwrap()
+        })
+        .map(|(frame, _)| {
+            let settings = assert_settings!(frame.unwrap());
+            assert!(settings.is_ack());
+        });
+
+    let _ = h2.join(mock)
+        .wait().unwrap();
+}
+
+#[test]
+fn keep_alive_ping_timeout_fails_connection() {
+    let _ = ::env_logger::init();
+    let (m, mock) = mock::new();
+
+    let h2 = Client::builder()
+        .keep_alive_interval(Duration::from_millis(300))
+        .keep_alive_timeout(Duration::from_millis(500))
+        .keep_alive_while_idle(true)
+        .handshake::<_, Bytes>(m)
+        .unwrap()
+        .and_then(|conn| {
+            conn.then(|res| {
+                let err = res.unwrap_err();
+                assert!(err.is_keep_alive_timeout());
+                assert_eq!(err.to_string(), "keep-alive timed out");
+                Ok::<_, ()>(())
+            })
+        });
+
+    let mock = mock.assert_client_handshake().unwrap()
+        .and_then(|(_, mock)| mock.into_future().unwrap())
+        .map(|(frame, mock)| {
+            let ping = assert_ping!(frame.unwrap());
+            assert!(!ping.is_ack());
+
+            // Never acknowledge the PING, but keep the connection open
+            mock
+        });
+
+    let _ = h2.join(mock)
+        .wait().unwrap();
+}
@@ -1,3 +1,4 @@
+#[macro_use]
 extern crate h2_test_support;
 use h2_test_support::prelude::*;
 
@@ -6,7 +7,7 @@ const SETTINGS_ACK: &'static [u8] = &[0, 0, 0, 4, 1, 0, 0, 0, 0];
 
 #[test]
 fn read_preface_in_multiple_frames() {
-    let _ = ::env_logger::init().unwrap();
+    let _ = ::env_logger::init();
 
     let mock = mock_io::Builder::new()
         .read(b"PRI * HTTP/2.0")
@@ -23,7 +24,1399 @@ fn read_preface_in_multiple_frames() {
     assert!(Stream::wait(h2).next().is_none());
 }
 
+#[test]
+fn sniff_http2() {
+    let _ = ::env_logger::init();
+
+    let mock = mock_io::Builder::new()
+        .read(b"PRI * HTTP/2.0")
+        .read(b"\r\n\r\nSM\r\n\r\n")
+        .write(SETTINGS)
+        .read(SETTINGS)
+        .write(SETTINGS_ACK)
+        .read(SETTINGS_ACK)
+        .build();
+
+    let h2 = match Server::sniff(mock).wait().unwrap() {
+        server::Sniffed::Http2(h2) => h2,
+        sniffed => panic!("unexpected protocol; {:?}", sniffed),
+    };
+
+    assert!(Stream::wait(h2).next().is_none());
+}
+
+#[test]
+fn sniff_http1() {
+    use h2_test_support::prelude::tokio_io::io::read_exact;
+
+    let _ = ::env_logger::init();
+
+    let request = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n";
+
+    let mock = mock_io::Builder::new()
+        .read(&request[..5])
+        .read(&request[5..])
+        .write(b"HTTP/1.1 426 Upgrade Required\r\n\
+                 Connection: Upgrade, close\r\n\
+                 Upgrade: h2c\r\n\
+                 Content-Length: 0\r\n\r\n")
+        .build();
+
+    let io = match Server::sniff(mock).wait().unwrap() {
+        server::Sniffed::Http1(io) => io,
+        sniffed => panic!("unexpected protocol; {:?}", sniffed),
+    };
+
+    // The bytes read while sniffing are replayed
+    let (io, buf) = read_exact(io, vec![0; request.len()]).wait().unwrap();
+    assert_eq!(&buf[..], &request[..]);
+
+    io.require_upgrade().wait().unwrap();
+}
+
 #[test]
 #[ignore]
 fn accept_with_pending_connections_after_socket_close() {
 }
+
+#[test]
+fn graceful_shutdown() {
+    let (io, client) = mock::new();
+
+    let srv = Server::handshake(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, mut srv)| {
+            let (_, mut stream) = req.unwrap();
+
+            srv.graceful_shutdown();
+
+            // Streams that are already open run to completion
+            let response = Response::builder()
+                .status(StatusCode::OK)
+                .body(()).unwrap();
+            stream.send_response(response, true).unwrap();
+
+            // The connection closes once the open stream completes
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, mut client)| {
+            let uri = "https://example.com/".parse().unwrap();
+            let mut request = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::request(Method::GET, uri),
+                HeaderMap::new());
+            request.set_end_stream();
+            client.send(request.into()).unwrap();
+
+            client.into_future().unwrap()
+        })
+        .and_then(|(frame, client)| {
+            // The first GOAWAY advertises the max stream ID
+            let go_away = assert_go_away!(frame.unwrap());
+            assert_eq!(go_away.last_stream_id(), frame::StreamId::max());
+            assert_eq!(go_away.reason(), Reason::NoError);
+
+            client.into_future().unwrap()
+        })
+        .and_then(|(frame, mut client)| {
+            let ping = assert_ping!(frame.unwrap());
+            assert!(!ping.is_ack());
+
+            let pong = frame::Ping::pong(*ping.payload());
+            client.send(pong.into()).unwrap();
+
+            client.into_future().unwrap()
+        })
+        .and_then(|(frame, client)| {
+            let headers = assert_headers!(frame.unwrap());
+            assert_eq!(u32::from(headers.stream_id()), 1);
+
+            client.into_future().unwrap()
+        })
+        .map(|(frame, _)| {
+            // The final GOAWAY carries the last processed stream ID
+            let go_away = assert_go_away!(frame.unwrap());
+            assert_eq!(u32::from(go_away.last_stream_id()), 1);
+            assert_eq!(go_away.reason(), Reason::NoError);
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn graceful_shutdown_ping_timeout() {
+    let _ = ::env_logger::init();
+    let (io, client) = mock::new();
+
+    let srv = Server::builder()
+        .graceful_shutdown_timeout(Duration::from_millis(300))
+        .handshake::<_, Bytes>(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, mut srv)| {
+            let (_, mut stream) = req.unwrap();
+
+            srv.graceful_shutdown();
+
+            let response = Response::builder()
+                .status(StatusCode::OK)
+                .body(()).unwrap();
+            stream.send_response(response, true).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, mut client)| {
+            let uri = "https://example.com/".parse().unwrap();
+            let mut request = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::request(Method::GET, uri),
+                HeaderMap::new());
+            request.set_end_stream();
+            client.send(request.into()).unwrap();
+
+            client.into_future().unwrap()
+        })
+        .and_then(|(frame, client)| {
+            let go_away = assert_go_away!(frame.unwrap());
+            assert_eq!(go_away.last_stream_id(), frame::StreamId::max());
+
+            client.into_future().unwrap()
+        })
+        .and_then(|(frame, client)| {
+            // Never acknowledge the PING
+            let ping = assert_ping!(frame.unwrap());
+            assert!(!ping.is_ack());
+
+            client.into_future().unwrap()
+        })
+        .and_then(|(frame, client)| {
+            let headers = assert_headers!(frame.unwrap());
+            assert_eq!(u32::from(headers.stream_id()), 1);
+
+            client.into_future().unwrap()
+        })
+        .map(|(frame, _)| {
+            // The final GOAWAY is sent once the PING times out
+            let go_away = assert_go_away!(frame.unwrap());
+            assert_eq!(u32::from(go_away.last_stream_id()), 1);
+            assert_eq!(go_away.reason(), Reason::NoError);
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn push_request() {
+    let (io, client) = mock::new();
+
+    let srv = Server::handshake(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, mut stream) = req.unwrap();
+
+            let request = Request::builder()
+                .uri("https://example.com/style.css")
+                .body(()).unwrap();
+            let mut pushed = stream.push_request(request).unwrap();
+
+            let response = Response::builder()
+                .status(StatusCode::OK)
+                .body(()).unwrap();
+            stream.send_response(response, true).unwrap();
+
+            let response = Response::builder()
+                .status(StatusCode::OK)
+                .body(()).unwrap();
+            pushed.send_response(response, true).unwrap();
+
+            // The connection closes once the client sends GOAWAY
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, mut client)| {
+            let uri = "https://example.com/".parse().unwrap();
+            let mut request = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::request(Method::GET, uri),
+                HeaderMap::new());
+            request.set_end_stream();
+            client.send(request.into()).unwrap();
+
+            client.into_future().unwrap()
+        })
+        .and_then(|(frame, client)| {
+            // The push is promised on the client's stream
+            let push_promise = assert_push_promise!(frame.unwrap());
+            assert_eq!(u32::from(push_promise.stream_id()), 1);
+            assert_eq!(u32::from(push_promise.promised_id()), 2);
+
+            client.into_future().unwrap()
+        })
+        .and_then(|(frame, client)| {
+            let headers = assert_headers!(frame.unwrap());
+            assert_eq!(u32::from(headers.stream_id()), 1);
+            assert!(headers.is_end_stream());
+
+            client.into_future().unwrap()
+        })
+        .map(|(frame, mut client)| {
+            // The pushed response is sent on the promised stream
+            let headers = assert_headers!(frame.unwrap());
+            assert_eq!(u32::from(headers.stream_id()), 2);
+            assert!(headers.is_end_stream());
+
+            let go_away = frame::GoAway::new(0.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn push_request_after_data() {
+    let (io, client) = mock::new();
+
+    let srv = Server::handshake(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, mut stream) = req.unwrap();
+
+            let response = || {
+                Response::builder()
+                    .status(StatusCode::OK)
+                    .body(()).unwrap()
+            };
+            stream.send_response(response(), false).unwrap();
+            stream.send_data("hello".into(), false).unwrap();
+
+            let request = Request::builder()
+                .uri("https://example.com/style.css")
+                .body(()).unwrap();
+            let mut pushed = stream.push_request(request).unwrap();
+
+            pushed.send_response(response(), true).unwrap();
+            stream.send_data("world".into(), true).unwrap();
+
+            // The connection closes once the client sends GOAWAY
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, mut client)| {
+            let uri = "https://example.com/".parse().unwrap();
+            let mut request = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::request(Method::GET, uri),
+                HeaderMap::new());
+            request.set_end_stream();
+            client.send(request.into()).unwrap();
+
+            client.recv_frames(5)
+        })
+        .map(|(frames, mut client)| {
+            let mut frames: Vec<_> = frames.into_iter()
+                .map(|frame| match frame {
+                    frame::Frame::PushPromise(v) => ("push_promise", u32::from(v.promised_id()), false),
+                    frame::Frame::Headers(v) => ("headers", u32::from(v.stream_id()), v.is_end_stream()),
+                    frame::Frame::Data(v) => ("data", u32::from(v.stream_id()), v.is_end_stream()),
+                    frame => panic!("unexpected frame; {:?}", frame),
+                })
+                .collect();
+
+            // The promise follows the frames sent on the associated stream
+            // before it
+            assert_eq!(&frames[..3], &[
+                ("headers", 1, false),
+                ("data", 1, false),
+                ("push_promise", 2, false),
+            ]);
+
+            // The pushed response and the rest of the associated stream
+            // follow the promise in any order
+            frames[3..].sort();
+            assert_eq!(&frames[3..], &[
+                ("data", 1, true),
+                ("headers", 2, true),
+            ]);
+
+            let go_away = frame::GoAway::new(0.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn push_request_max_concurrent_streams() {
+    let (io, client) = mock::new();
+
+    let srv = Server::handshake(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, mut stream) = req.unwrap();
+
+            let mut pushed = vec![];
+
+            for path in &["/style.css", "/script.js"] {
+                let request = Request::builder()
+                    .uri(format!("https://example.com{}", path).as_str())
+                    .body(()).unwrap();
+                pushed.push(stream.push_request(request).unwrap());
+            }
+
+            // The client allows one concurrent stream
+            let response = || {
+                Response::builder()
+                    .status(StatusCode::OK)
+                    .body(()).unwrap()
+            };
+            pushed[0].send_response(response(), false).unwrap();
+            assert!(pushed[1].send_response(response(), false).is_err());
+
+            // Ending the pushed response right away does not open it
+            pushed[1].send_response(response(), true).unwrap();
+
+            pushed[0].send_data("hello".into(), true).unwrap();
+            stream.send_response(response(), true).unwrap();
+
+            // The connection closes once the client sends GOAWAY
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    let mut settings = frame::Settings::default();
+    settings.set_max_concurrent_streams(Some(1));
+
+    let client = client.assert_server_handshake_with_settings(settings).unwrap()
+        .and_then(|(_, mut client)| {
+            let uri = "https://example.com/".parse().unwrap();
+            let mut request = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::request(Method::GET, uri),
+                HeaderMap::new());
+            request.set_end_stream();
+            client.send(request.into()).unwrap();
+
+            client.take(6).collect()
+                .map(|frames| {
+                    let mut frames: Vec<_> = frames.into_iter()
+                        .map(|frame| match frame {
+                            frame::Frame::PushPromise(v) => ("push_promise", u32::from(v.promised_id()), false),
+                            frame::Frame::Headers(v) => ("headers", u32::from(v.stream_id()), v.is_end_stream()),
+                            frame::Frame::Data(v) => ("data", u32::from(v.stream_id()), v.is_end_stream()),
+                            frame => panic!("unexpected frame; {:?}", frame),
+                        })
+                        .collect();
+                    frames.sort();
+                    frames
+                })
+                .unwrap()
+        })
+        .map(|frames| {
+            assert_eq!(frames, vec![
+                ("data", 2, true),
+                ("headers", 1, true),
+                ("headers", 2, false),
+                ("headers", 4, true),
+                ("push_promise", 2, false),
+                ("push_promise", 4, false),
+            ]);
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn push_request_disabled_by_client() {
+    let (io, client) = mock::new();
+
+    let srv = Server::handshake(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, mut stream) = req.unwrap();
+
+            let request = Request::builder()
+                .uri("https://example.com/style.css")
+                .body(()).unwrap();
+            assert!(stream.push_request(request).is_err());
+
+            let response = Response::builder()
+                .status(StatusCode::OK)
+                .body(()).unwrap();
+            stream.send_response(response, true).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    let mut settings = frame::Settings::default();
+    settings.set_enable_push(false);
+
+    let client = client.assert_server_handshake_with_settings(settings).unwrap()
+        .and_then(|(_, mut client)| {
+            let uri = "https://example.com/".parse().unwrap();
+            let mut request = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::request(Method::GET, uri),
+                HeaderMap::new());
+            request.set_end_stream();
+            client.send(request.into()).unwrap();
+
+            client.into_future().unwrap()
+        })
+        .map(|(frame, mut client)| {
+            // No PUSH_PROMISE is sent
+            let headers = assert_headers!(frame.unwrap());
+            assert_eq!(u32::from(headers.stream_id()), 1);
+
+            let go_away = frame::GoAway::new(0.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn recv_header_list_too_large() {
+    let _ = ::env_logger::init();
+    let (io, client) = mock::new();
+
+    let srv = Server::builder()
+        .max_header_list_size(300)
+        .handshake::<_, Bytes>(io)
+        .unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (request, mut stream) = req.unwrap();
+
+            // The request on stream 1 was reset
+            assert_eq!(stream.stream_id(), 3);
+            assert_eq!(request.headers()["x-large"].len(), 10);
+
+            let response = Response::builder()
+                .status(StatusCode::OK)
+                .body(()).unwrap();
+            stream.send_response(response, true).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(settings, mut client)| {
+            assert_eq!(settings.max_header_list_size(), Some(300));
+
+            let request = |id: u32, len: usize| {
+                let uri = "https://example.com/".parse().unwrap();
+                let mut fields = HeaderMap::new();
+                fields.insert("x-large", "a".repeat(len).parse().unwrap());
+
+                let mut request = frame::Headers::new(
+                    id.into(),
+                    frame::Pseudo::request(Method::GET, uri),
+                    fields);
+                request.set_end_stream();
+                request
+            };
+
+            client.send(request(1, 300).into()).unwrap();
+            client.into_future().unwrap()
+                .map(move |(frame, client)| (frame, client, request))
+        })
+        .and_then(|(frame, mut client, request)| {
+            let reset = assert_reset!(frame.unwrap());
+            assert_eq!(u32::from(reset.stream_id()), 1);
+            assert_eq!(reset.reason(), Reason::ProtocolError);
+
+            client.send(request(3, 10).into()).unwrap();
+            client.into_future().unwrap()
+        })
+        .map(|(frame, mut client)| {
+            let headers = assert_headers!(frame.unwrap());
+            assert_eq!(u32::from(headers.stream_id()), 3);
+
+            let go_away = frame::GoAway::new(3.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn recv_continuation_flood() {
+    let _ = ::env_logger::init();
+    let (io, client) = mock::new();
+
+    let srv = Server::builder()
+        .max_header_list_size(100)
+        .handshake::<_, Bytes>(io)
+        .unwrap()
+        .and_then(|srv| {
+            srv.into_future().then(|res| {
+                let (err, _) = res.unwrap_err();
+                assert_eq!(err.reason(), Some(Reason::EnhanceYourCalm));
+                Ok::<_, ()>(())
+            })
+        });
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, mut client)| {
+            // HEADERS on stream 1 without END_HEADERS
+            client.send_bytes(&[0, 0, 50, 1, 0, 0, 0, 0, 1]);
+            client.send_bytes(&[0; 50]);
+
+            // CONTINUATION frames that never end the header block
+            for _ in 0..3 {
+                client.send_bytes(&[0, 0, 100, 9, 0, 0, 0, 0, 1]);
+                client.send_bytes(&[0; 100]);
+            }
+
+            client.into_future().unwrap()
+        })
+        .map(|(frame, _)| {
+            let go_away = assert_go_away!(frame.unwrap());
+            assert_eq!(go_away.reason(), Reason::EnhanceYourCalm);
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn recv_priority_dependency() {
+    let (io, client) = mock::new();
+
+    let srv = Server::builder()
+        .scheduler(scheduler::WeightedFair::new())
+        .handshake::<_, Bytes>(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, stream1) = req.unwrap();
+
+            srv.into_future().unwrap()
+                .map(move |(req, srv)| (stream1, req.unwrap().1, srv))
+        })
+        .and_then(|(mut stream1, mut stream3, srv)| {
+            // Stream 1 queues its response first
+            for stream in vec![&mut stream1, &mut stream3] {
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .body(()).unwrap();
+                stream.send_response(response, false).unwrap();
+                stream.send_data("hello".into(), true).unwrap();
+            }
+
+            // The connection closes once the client sends GOAWAY
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, mut client)| {
+            // PRIORITY: stream 1 depends exclusively on stream 3
+            client.send_bytes(&[0, 0, 5, 2, 0, 0, 0, 0, 1, 0x80, 0, 0, 3, 15]);
+
+            for id in &[1, 3] {
+                let uri = "https://example.com/".parse().unwrap();
+                let mut request = frame::Headers::new(
+                    (*id).into(),
+                    frame::Pseudo::request(Method::GET, uri),
+                    HeaderMap::new());
+                request.set_end_stream();
+                client.send(request.into()).unwrap();
+            }
+
+            client.recv_frames(4)
+        })
+        .map(|(frames, mut client)| {
+            let ids: Vec<u32> = frames.iter()
+                .map(|frame| match *frame {
+                    frame::Frame::Headers(ref v) => u32::from(v.stream_id()),
+                    frame::Frame::Data(ref v) => u32::from(v.stream_id()),
+                    ref f => panic!("unexpected frame; frame={:?}", f),
+                })
+                .collect();
+
+            // Stream 3 is sent first, as stream 1 depends on it
+            assert_eq!(ids, vec![3, 3, 1, 1]);
+
+            let go_away = frame::GoAway::new(3.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn recv_priority_weights() {
+    let (io, client) = mock::new();
+
+    let srv = Server::builder()
+        .scheduler(scheduler::WeightedFair::new())
+        .handshake::<_, Bytes>(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, stream1) = req.unwrap();
+
+            srv.into_future().unwrap()
+                .map(move |(req, srv)| (stream1, req.unwrap().1, srv))
+        })
+        .and_then(|(mut stream1, mut stream3, srv)| {
+            // Stream 1 queues its response first
+            for stream in vec![&mut stream1, &mut stream3] {
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .body(()).unwrap();
+                stream.send_response(response, false).unwrap();
+
+                for i in 0..4 {
+                    stream.send_data(vec![0; 1_000].into(), i == 3).unwrap();
+                }
+            }
+
+            // The connection closes once the client sends GOAWAY
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, mut client)| {
+            // PRIORITY: stream 1 has a weight of 1, stream 3 of 256
+            client.send_bytes(&[0, 0, 5, 2, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0]);
+            client.send_bytes(&[0, 0, 5, 2, 0, 0, 0, 0, 3, 0, 0, 0, 0, 255]);
+
+            for id in &[1, 3] {
+                let uri = "https://example.com/".parse().unwrap();
+                let mut request = frame::Headers::new(
+                    (*id).into(),
+                    frame::Pseudo::request(Method::GET, uri),
+                    HeaderMap::new());
+                request.set_end_stream();
+                client.send(request.into()).unwrap();
+            }
+
+            client.recv_frames(10)
+        })
+        .map(|(frames, mut client)| {
+            let end_of_stream: Vec<u32> = frames.iter()
+                .filter_map(|frame| match *frame {
+                    frame::Frame::Data(ref v) if v.is_end_stream() => {
+                        Some(u32::from(v.stream_id()))
+                    }
+                    _ => None,
+                })
+                .collect();
+
+            // The heavier stream 3 completes first
+            assert_eq!(end_of_stream, vec![3, 1]);
+
+            let go_away = frame::GoAway::new(3.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn recv_priority_urgency() {
+    let (io, client) = mock::new();
+
+    let srv = Server::builder()
+        .no_rfc7540_priorities(true)
+        .scheduler(scheduler::Urgency::new())
+        .handshake::<_, Bytes>(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, stream1) = req.unwrap();
+
+            srv.into_future().unwrap()
+                .map(move |(req, srv)| (stream1, req.unwrap().1, srv))
+        })
+        .and_then(|(stream1, stream3, srv)| {
+            srv.into_future().unwrap()
+                .map(move |(req, srv)| (stream1, stream3, req.unwrap().1, srv))
+        })
+        .and_then(|(mut stream1, mut stream3, mut stream5, srv)| {
+            // Stream 1 queues its response first
+            for stream in vec![&mut stream1, &mut stream3, &mut stream5] {
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .body(()).unwrap();
+                stream.send_response(response, false).unwrap();
+                stream.send_data("hello".into(), true).unwrap();
+            }
+
+            // The connection closes once the client sends GOAWAY
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(settings, mut client)| {
+            assert_eq!(settings.no_rfc7540_priorities(), Some(1));
+
+            // Stream 5 has the default urgency of 3
+            for &(id, priority) in &[(1, Some("u=5")), (3, Some("u=1, i")), (5, None)] {
+                let mut fields = HeaderMap::new();
+
+                if let Some(priority) = priority {
+                    fields.insert("priority", priority.parse().unwrap());
+                }
+
+                let uri = "https://example.com/".parse().unwrap();
+                let mut request = frame::Headers::new(
+                    id.into(),
+                    frame::Pseudo::request(Method::GET, uri),
+                    fields);
+                request.set_end_stream();
+                client.send(request.into()).unwrap();
+            }
+
+            client.recv_frames(6)
+        })
+        .map(|(frames, mut client)| {
+            let end_of_stream: Vec<u32> = frames.iter()
+                .filter_map(|frame| match *frame {
+                    frame::Frame::Data(ref v) if v.is_end_stream() => {
+                        Some(u32::from(v.stream_id()))
+                    }
+                    _ => None,
+                })
+                .collect();
+
+            // The most urgent stream completes first
+            assert_eq!(end_of_stream, vec![3, 5, 1]);
+
+            let go_away = frame::GoAway::new(5.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn recv_priority_update() {
+    let (io, client) = mock::new();
+
+    let srv = Server::builder()
+        .scheduler(scheduler::Urgency::new())
+        .handshake::<_, Bytes>(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, stream1) = req.unwrap();
+
+            srv.into_future().unwrap()
+                .map(move |(req, srv)| (stream1, req.unwrap().1, srv))
+        })
+        .and_then(|(mut stream1, mut stream3, srv)| {
+            // Stream 1 queues its response first
+            for stream in vec![&mut stream1, &mut stream3] {
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .body(()).unwrap();
+                stream.send_response(response, false).unwrap();
+                stream.send_data("hello".into(), true).unwrap();
+            }
+
+            // The connection closes once the client sends GOAWAY
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    // The client disables RFC 7540 priorities
+    let mut settings = frame::Settings::default();
+    settings.set_no_rfc7540_priorities(true);
+
+    let client = client.assert_server_handshake_with_settings(settings).unwrap()
+        .and_then(|(_, mut client)| {
+            // Stream 3 is reprioritized before it is opened
+            let update = frame::PriorityUpdate::new(
+                3.into(), frame::PriorityParams::new(0, false));
+            client.send(update.into()).unwrap();
+
+            for id in &[1, 3] {
+                let uri = "https://example.com/".parse().unwrap();
+                let mut request = frame::Headers::new(
+                    (*id).into(),
+                    frame::Pseudo::request(Method::GET, uri),
+                    HeaderMap::new());
+                request.set_end_stream();
+                client.send(request.into()).unwrap();
+            }
+
+            // Stream 1 is reprioritized once opened
+            let update = frame::PriorityUpdate::new(
+                1.into(), frame::PriorityParams::new(7, false));
+            client.send(update.into()).unwrap();
+
+            client.recv_frames(4)
+        })
+        .map(|(frames, mut client)| {
+            let ids: Vec<u32> = frames.iter()
+                .map(|frame| match *frame {
+                    frame::Frame::Headers(ref v) => u32::from(v.stream_id()),
+                    frame::Frame::Data(ref v) => u32::from(v.stream_id()),
+                    ref f => panic!("unexpected frame; frame={:?}", f),
+                })
+                .collect();
+
+            assert_eq!(ids, vec![3, 3, 1, 1]);
+
+            let go_away = frame::GoAway::new(3.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn recv_priority_update_for_pushed_stream() {
+    let (io, client) = mock::new();
+
+    let srv = Server::handshake(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, mut stream) = req.unwrap();
+
+            let request = Request::builder()
+                .uri("https://example.com/style.css")
+                .body(()).unwrap();
+            let pushed = stream.push_request(request).unwrap();
+
+            srv.into_future().unwrap()
+                .map(move |(req, srv)| (stream, pushed, req.unwrap().1, srv))
+        })
+        .and_then(|(stream, pushed, mut stream3, srv)| {
+            let response = Response::builder()
+                .status(StatusCode::OK)
+                .body(()).unwrap();
+            stream3.send_response(response, true).unwrap();
+
+            // The update for a stream that was never promised is a
+            // connection error.
+            srv.into_future()
+                .map(move |_| drop((stream, pushed)))
+                .then(|res| {
+                    assert!(res.is_err());
+                    Ok::<_, ()>(())
+                })
+        });
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, mut client)| {
+            let uri = "https://example.com/".parse().unwrap();
+            let mut request = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::request(Method::GET, uri),
+                HeaderMap::new());
+            request.set_end_stream();
+            client.send(request.into()).unwrap();
+
+            client.into_future().unwrap()
+        })
+        .and_then(|(frame, mut client)| {
+            let push_promise = assert_push_promise!(frame.unwrap());
+            assert_eq!(u32::from(push_promise.promised_id()), 2);
+
+            // The promised stream may be reprioritized
+            let update = frame::PriorityUpdate::new(
+                2.into(), frame::PriorityParams::new(0, false));
+            client.send(update.into()).unwrap();
+
+            let uri = "https://example.com/".parse().unwrap();
+            let mut request = frame::Headers::new(
+                3.into(),
+                frame::Pseudo::request(Method::GET, uri),
+                HeaderMap::new());
+            request.set_end_stream();
+            client.send(request.into()).unwrap();
+
+            client.into_future().unwrap()
+        })
+        .and_then(|(frame, mut client)| {
+            let headers = assert_headers!(frame.unwrap());
+            assert_eq!(u32::from(headers.stream_id()), 3);
+
+            let update = frame::PriorityUpdate::new(
+                4.into(), frame::PriorityParams::new(0, false));
+            client.send(update.into()).unwrap();
+
+            client.into_future().unwrap()
+        })
+        .map(|(frame, _)| {
+            let go_away = assert_go_away!(frame.unwrap());
+            assert_eq!(go_away.reason(), Reason::ProtocolError);
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn send_scheduler_default() {
+    let frames = send_two_responses(&Server::builder(), 6);
+
+    // The streams take turns sending a frame, regardless of priorities
+    assert_eq!(frames, vec![
+        (1, 0), (3, 0), (1, 5),
+        (3, 5), (1, 5), (3, 5),
+    ]);
+}
+
+#[test]
+fn send_scheduler_fifo() {
+    let mut builder = Server::builder();
+    builder.scheduler(scheduler::Fifo::new());
+
+    let frames = send_two_responses(&builder, 6);
+
+    // Stream 1 became ready first, then the streams take turns sending a
+    // frame
+    assert_eq!(frames, vec![
+        (1, 0), (3, 0), (1, 5),
+        (3, 5), (1, 5), (3, 5),
+    ]);
+}
+
+#[test]
+fn send_scheduler_round_robin() {
+    let mut builder = Server::builder();
+    builder.scheduler(scheduler::RoundRobin::new());
+
+    let frames = send_two_responses(&builder, 6);
+
+    // The streams take turns sending a frame
+    assert_eq!(frames, vec![
+        (1, 0), (3, 0), (1, 5),
+        (3, 5), (1, 5), (3, 5),
+    ]);
+}
+
+#[test]
+fn send_scheduler_custom() {
+    /// Sends the stream with the highest ID first, two bytes at a time.
+    #[derive(Debug, Clone)]
+    struct HighestFirst;
+
+    impl scheduler::Scheduler for HighestFirst {
+        fn select(&mut self, ready: &[scheduler::Ready]) -> scheduler::Selection {
+            let stream_id = ready.iter()
+                .map(|ready| ready.stream_id())
+                .max()
+                .unwrap();
+
+            scheduler::Selection::new(stream_id).max_len(2)
+        }
+    }
+
+    let mut builder = Server::builder();
+    builder.scheduler(HighestFirst);
+
+    let frames = send_two_responses(&builder, 14);
+
+    assert_eq!(frames, vec![
+        (3, 0), (3, 2), (3, 2), (3, 1), (3, 2), (3, 2), (3, 1),
+        (1, 0), (1, 2), (1, 2), (1, 1), (1, 2), (1, 2), (1, 1),
+    ]);
+}
+
+#[test]
+fn send_scheduler_selects_stream_not_ready() {
+    /// Always selects a stream that does not exist.
+    #[derive(Debug, Clone)]
+    struct Bogus;
+
+    impl scheduler::Scheduler for Bogus {
+        fn select(&mut self, _: &[scheduler::Ready]) -> scheduler::Selection {
+            scheduler::Selection::new(StreamId::from(99))
+        }
+    }
+
+    let mut builder = Server::builder();
+    builder.scheduler(Bogus);
+
+    let frames = send_two_responses(&builder, 6);
+
+    // The first ready stream sends instead
+    assert_eq!(frames, vec![
+        (1, 0), (3, 0), (1, 5),
+        (3, 5), (1, 5), (3, 5),
+    ]);
+}
+
+#[test]
+fn upgrade_h2c() {
+    use h2_test_support::prelude::tokio_io::io::read_exact;
+
+    let _ = ::env_logger::init();
+    let (io, client) = mock::new();
+
+    let request = Request::builder()
+        .uri("/index.html")
+        .header("host", "example.com")
+        .header("connection", "Upgrade, HTTP2-Settings")
+        .header("upgrade", "h2c")
+        .header("accept", "text/html")
+        .body(()).unwrap();
+
+    // SETTINGS_INITIAL_WINDOW_SIZE = 1000
+    let settings = "AAQAAAPo".parse().unwrap();
+
+    let srv = Server::upgrade(io, request, &settings).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (request, mut stream) = req.unwrap();
+
+            assert_eq!(stream.stream_id(), 1);
+            assert_eq!(request.method(), Method::GET);
+            assert_eq!(request.uri(), "http://example.com/index.html");
+            assert_eq!(request.headers()["accept"], "text/html");
+            assert!(!request.headers().contains_key("connection"));
+            assert!(!request.headers().contains_key("upgrade"));
+            assert!(!request.headers().contains_key("host"));
+            assert!(request.body().is_empty());
+
+            // The HTTP2-Settings are applied without waiting for SETTINGS
+            assert_eq!(stream.flow_stats().send_window(), 1_000);
+
+            let response = Response::builder()
+                .status(StatusCode::OK)
+                .body(()).unwrap();
+            stream.send_response(response, true).unwrap();
+
+            // The connection closes once the client sends GOAWAY
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    let client = read_exact(client, vec![0; 71])
+        .map_err(|e| panic!("err={:?}", e))
+        .and_then(|(client, buf)| {
+            assert_eq!(&buf[..], &b"HTTP/1.1 101 Switching Protocols\r\n\
+                                     Connection: Upgrade\r\n\
+                                     Upgrade: h2c\r\n\r\n"[..]);
+
+            client.assert_server_handshake().unwrap()
+        })
+        .and_then(|(_, client)| client.into_future().unwrap())
+        .map(|(frame, mut client)| {
+            // The response to the upgrade request
+            let headers = assert_headers!(frame.unwrap());
+            assert_eq!(u32::from(headers.stream_id()), 1);
+            assert!(headers.is_end_stream());
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+fn extended_connect_request(id: u32, method: Method) -> frame::Headers {
+    let uri = "https://example.com/chat".parse().unwrap();
+    let protocol = Protocol::from_static("websocket");
+    let pseudo = frame::Pseudo::request_with_protocol(method, uri, Some(protocol));
+
+    frame::Headers::new(id.into(), pseudo, HeaderMap::new())
+}
+
+#[test]
+fn recv_extended_connect() {
+    let _ = ::env_logger::init();
+    let (io, client) = mock::new();
+
+    let srv = Server::builder()
+        .enable_connect_protocol(true)
+        .handshake::<_, Bytes>(io)
+        .unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (request, mut stream) = req.unwrap();
+
+            // The malformed request on stream 1 was reset
+            assert_eq!(stream.stream_id(), 3);
+            assert_eq!(request.method(), Method::CONNECT);
+            assert_eq!(request.uri(), "https://example.com/chat");
+
+            let protocol = request.extensions().get::<Protocol>().unwrap();
+            assert_eq!(protocol.as_str(), "websocket");
+
+            let response = Response::builder()
+                .status(StatusCode::OK)
+                .body(()).unwrap();
+            stream.send_response(response, true).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(settings, mut client)| {
+            assert!(settings.is_extended_connect_protocol_enabled());
+
+            // Only CONNECT requests may carry :protocol
+            client.send(extended_connect_request(1, Method::GET).into()).unwrap();
+            client.send(extended_connect_request(3, Method::CONNECT).into()).unwrap();
+
+            client.into_future().unwrap()
+        })
+        .and_then(|(frame, client)| {
+            let reset = assert_reset!(frame.unwrap());
+            assert_eq!(u32::from(reset.stream_id()), 1);
+            assert_eq!(reset.reason(), Reason::ProtocolError);
+
+            client.into_future().unwrap()
+        })
+        .map(|(frame, mut client)| {
+            let headers = assert_headers!(frame.unwrap());
+            assert_eq!(u32::from(headers.stream_id()), 3);
+
+            let go_away = frame::GoAway::new(3.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn recv_extended_connect_disabled() {
+    let _ = ::env_logger::init();
+    let (io, client) = mock::new();
+
+    let srv = Server::handshake(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .map(|(req, _)| assert!(req.is_none()));
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(settings, mut client)| {
+            assert!(!settings.is_extended_connect_protocol_enabled());
+
+            client.send(extended_connect_request(1, Method::CONNECT).into()).unwrap();
+            client.into_future().unwrap()
+        })
+        .map(|(frame, mut client)| {
+            let reset = assert_reset!(frame.unwrap());
+            assert_eq!(u32::from(reset.stream_id()), 1);
+            assert_eq!(reset.reason(), Reason::ProtocolError);
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn recv_extended_connect_without_authority() {
+    let _ = ::env_logger::init();
+    let (io, client) = mock::new();
+
+    let srv = Server::builder()
+        .enable_connect_protocol(true)
+        .handshake::<_, Bytes>(io)
+        .unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .map(|(req, _)| assert!(req.is_none()));
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, mut client)| {
+            let uri = "https://example.com/chat".parse().unwrap();
+            let protocol = Protocol::from_static("websocket");
+            let mut pseudo = frame::Pseudo::request_with_protocol(
+                Method::CONNECT, uri, Some(protocol));
+            pseudo.authority = None;
+
+            let request = frame::Headers::new(1.into(), pseudo, HeaderMap::new());
+            client.send(request.into()).unwrap();
+            client.into_future().unwrap()
+        })
+        .map(|(frame, mut client)| {
+            let reset = assert_reset!(frame.unwrap());
+            assert_eq!(u32::from(reset.stream_id()), 1);
+            assert_eq!(reset.reason(), Reason::ProtocolError);
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+#[test]
+fn connect_tunnel() {
+    use h2_test_support::prelude::tokio_io::io::{read_exact, read_to_end, shutdown, write_all};
+
+    let _ = ::env_logger::init();
+    let (io, client) = mock::new();
+
+    let srv = Server::handshake(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (request, mut stream) = req.unwrap();
+
+            assert_eq!(request.method(), Method::CONNECT);
+            assert_eq!(request.uri(), "example.com:443");
+
+            let response = Response::builder()
+                .status(StatusCode::OK)
+                .body(()).unwrap();
+            stream.send_response(response, false).unwrap();
+
+            let tunnel = read_exact(stream.into_tunnel(), [0; 5])
+                .and_then(|(tunnel, buf)| {
+                    assert_eq!(&buf, b"hello");
+                    write_all(tunnel, "world")
+                })
+                .and_then(|(tunnel, _)| read_to_end(tunnel, vec![]))
+                .and_then(|(tunnel, buf)| {
+                    assert!(buf.is_empty());
+                    shutdown(tunnel)
+                })
+                .map_err(|e| panic!("unexpected error; {:?}", e));
+
+            srv.into_future().unwrap().join(tunnel)
+        })
+        .map(|((req, _), _)| assert!(req.is_none()));
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, mut client)| {
+            // CONNECT requests only carry :method and :authority
+            let pseudo = frame::Pseudo::request(Method::CONNECT, "example.com:443".parse().unwrap());
+            let headers = frame::Headers::new(1.into(), pseudo, HeaderMap::new());
+            client.send(headers.into()).unwrap();
+
+            let data = frame::Data::new(1.into(), Bytes::from_static(b"hello").into_buf());
+            client.send(data.into()).unwrap();
+
+            client.into_future().unwrap()
+        })
+        .and_then(|(frame, client)| {
+            let headers = assert_headers!(frame.unwrap());
+            assert_eq!(headers.pseudo().status, Some(StatusCode::OK));
+            assert!(!headers.is_end_stream());
+
+            client.into_future().unwrap()
+        })
+        .and_then(|(frame, client)| {
+            // Reading from the tunnel released the capacity of the data
+            match frame.unwrap() {
+                frame::Frame::WindowUpdate(v) => {
+                    assert_eq!(u32::from(v.stream_id()), 0);
+                    assert_eq!(v.size_increment(), 5);
+                }
+                frame => panic!("unexpected frame; {:?}", frame),
+            }
+
+            client.into_future().unwrap()
+        })
+        .and_then(|(frame, mut client)| {
+            let data = assert_data!(frame.unwrap());
+            assert_eq!(data.payload(), &b"world"[..]);
+            assert!(!data.is_end_stream());
+
+            let mut data = frame::Data::new(1.into(), Bytes::new().into_buf());
+            data.set_end_stream(true);
+            client.send(data.into()).unwrap();
+
+            client.into_future().unwrap()
+        })
+        .map(|(frame, mut client)| {
+            let data = assert_data!(frame.unwrap());
+            assert!(data.payload().is_empty());
+            assert!(data.is_end_stream());
+
+            let go_away = frame::GoAway::new(1.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+        });
+
+    let _ = srv.join(client)
+        .wait().unwrap();
+}
+
+/// Sends a response with two DATA frames on streams 1 and 3, returning the
+/// stream ID and payload length of the first `num_frames` frames received by
+/// the client.
+fn send_two_responses(builder: &server::Builder, num_frames: usize) -> Vec<(u32, usize)> {
+    let (io, client) = mock::new();
+
+    let srv = builder.handshake::<_, Bytes>(io).unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(req, srv)| {
+            let (_, stream1) = req.unwrap();
+
+            srv.into_future().unwrap()
+                .map(move |(req, srv)| (stream1, req.unwrap().1, srv))
+        })
+        .and_then(|(mut stream1, mut stream3, srv)| {
+            assert_eq!(stream1.stream_id(), StreamId::from(1));
+            assert_eq!(stream3.stream_id(), StreamId::from(3));
+
+            for stream in vec![&mut stream1, &mut stream3] {
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .body(()).unwrap();
+                stream.send_response(response, false).unwrap();
+                stream.send_data("hello".into(), false).unwrap();
+                stream.send_data("world".into(), true).unwrap();
+            }
+
+            // The connection closes once the client sends GOAWAY
+            srv.into_future().unwrap()
+        })
+        .map(|(req, _)| assert!(req.is_none()));
+
+    let client = client.assert_server_handshake().unwrap()
+        .and_then(|(_, mut client)| {
+            for &id in &[1, 3] {
+                let uri = "https://example.com/".parse().unwrap();
+                let mut request = frame::Headers::new(
+                    id.into(),
+                    frame::Pseudo::request(Method::GET, uri),
+                    HeaderMap::new());
+                request.set_end_stream();
+                client.send(request.into()).unwrap();
+            }
+
+            client.recv_frames(num_frames)
+        })
+        .map(|(frames, mut client)| {
+            let go_away = frame::GoAway::new(3.into(), Reason::NoError);
+            client.send(go_away.into()).unwrap();
+
+            frames.iter()
+                .map(|frame| match *frame {
+                    frame::Frame::Headers(ref v) => (u32::from(v.stream_id()), 0),
+                    frame::Frame::Data(ref v) => {
+                        (u32::from(v.stream_id()), v.payload().len())
+                    }
+                    ref f => panic!("unexpected frame; frame={:?}", f),
+                })
+                .collect()
+        });
+
+    srv.join(client)
+        .wait().unwrap().1
+}
@@ -0,0 +1,129 @@
+#[macro_use]
+extern crate h2_test_support;
+use h2_test_support::prelude::*;
+
+#[test]
+fn settings_ack_timeout_sends_go_away() {
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let h2 = Client::builder()
+        .settings_timeout(Duration::from_millis(50))
+        .handshake::<_, Bytes>(io)
+        .unwrap()
+        .and_then(|conn| {
+            conn.then(|res| {
+                // The connection is closed with an error
+                assert!(res.is_err());
+                Ok::<_, ()>(())
+            })
+        });
+
+    let srv = srv.read_preface().unwrap()
+        .and_then(|srv| srv.into_future().unwrap())
+        .and_then(|(frame, srv)| {
+            // Receive the client's SETTINGS, but never acknowledge it
+            let settings = assert_settings!(frame.unwrap());
+            assert!(!settings.is_ack());
+
+            srv.into_future().unwrap()
+        })
+        .map(|(frame, _)| {
+            let go_away = assert_go_away!(frame.unwrap());
+            assert_eq!(go_away.reason(), Reason::SettingsTimeout);
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn unexpected_settings_ack_is_protocol_error() {
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let h2 = Client::handshake(io).unwrap()
+        .and_then(|conn| {
+            conn.then(|res| {
+                assert!(res.is_err());
+                Ok::<_, ()>(())
+            })
+        });
+
+    let srv = srv.assert_client_handshake().unwrap()
+        .and_then(|(_, mut srv)| {
+            // The client's SETTINGS have already been acknowledged
+            srv.send(frame::Settings::ack().into()).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .map(|(frame, _)| {
+            let go_away = assert_go_away!(frame.unwrap());
+            assert_eq!(go_away.reason(), Reason::ProtocolError);
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
+
+#[test]
+fn update_initial_window_size_after_handshake() {
+    let _ = ::env_logger::init();
+    let (io, srv) = mock::new();
+
+    let h2 = Client::handshake(io).unwrap()
+        .and_then(|mut client| {
+            let request = Request::builder()
+                .uri("https://http2.akamai.com/")
+                .body(()).unwrap();
+
+            let stream = client.request(request, true).unwrap();
+
+            // Shrink the window of the open stream
+            client.set_initial_window_size(16);
+
+            client.then(move |res| {
+                drop(stream);
+
+                // The server exceeded the acknowledged window
+                assert!(res.is_err());
+                Ok::<_, ()>(())
+            })
+        });
+
+    let srv = srv.assert_client_handshake().unwrap()
+        .and_then(|(_, srv)| srv.into_future().unwrap())
+        .and_then(|(frame, srv)| {
+            let headers = assert_headers!(frame.unwrap());
+            assert_eq!(u32::from(headers.stream_id()), 1);
+
+            srv.into_future().unwrap()
+        })
+        .and_then(|(frame, mut srv)| {
+            let settings = assert_settings!(frame.unwrap());
+            assert!(!settings.is_ack());
+            assert_eq!(settings.initial_window_size(), Some(16));
+
+            // Acknowledge the new settings
+            srv.send(frame::Settings::ack().into()).unwrap();
+
+            let response = frame::Headers::new(
+                1.into(),
+                frame::Pseudo::response(StatusCode::OK),
+                HeaderMap::new());
+            srv.send(response.into()).unwrap();
+
+            // Send more data than the new window allows
+            let data = frame::Data::new(1.into(), Bytes::from(vec![0; 32]).into_buf());
+            srv.send(data.into()).unwrap();
+
+            srv.into_future().unwrap()
+        })
+        .map(|(frame, _)| {
+            let go_away = assert_go_away!(frame.unwrap());
+            assert_eq!(go_away.reason(), Reason::FlowControlError);
+        });
+
+    let _ = h2.join(srv)
+        .wait().unwrap();
+}
@@ -6,6 +6,36 @@ macro_rules! assert_closed {
     }}
 }
 
+#[macro_export]
+macro_rules! assert_data {
+    ($frame:expr) => {{
+        match $frame {
+            ::h2::frame::Frame::Data(v) => v,
+            f => panic!("expected DATA; actual={:?}", f),
+        }
+    }}
+}
+
+#[macro_export]
+macro_rules! assert_go_away {
+    ($frame:expr) => {{
+        match $frame {
+            ::h2::frame::Frame::GoAway(v) => v,
+            f => panic!("expected GOAWAY; actual={:?}", f),
+        }
+    }}
+}
+
+#[macro_export]
+macro_rules! assert_headers {
+    ($frame:expr) => {{
+        match $frame {
+            ::h2::frame::Frame::Headers(v) => v,
+            f => panic!("expected HEADERS; actual={:?}", f),
+        }
+    }}
+}
+
 #[macro_export]
 macro_rules! assert_ping {
     ($frame:expr) => {{
@@ -16,6 +46,36 @@ macro_rules! assert_ping {
     }}
 }
 
+#[macro_export]
+macro_rules! assert_priority {
+    ($frame:expr) => {{
+        match $frame {
+            ::h2::frame::Frame::Priority(v) => v,
+            f => panic!("expected PRIORITY; actual={:?}", f),
+        }
+    }}
+}
+
+#[macro_export]
+macro_rules! assert_push_promise {
+    ($frame:expr) => {{
+        match $frame {
+            ::h2::frame::Frame::PushPromise(v) => v,
+            f => panic!("expected PUSH_PROMISE; actual={:?}", f),
+        }
+    }}
+}
+
+#[macro_export]
+macro_rules! assert_reset {
+    ($frame:expr) => {{
+        match $frame {
+            ::h2::frame::Frame::Reset(v) => v,
+            f => panic!("expected RST_STREAM; actual={:?}", f),
+        }
+    }}
+}
+
 #[macro_export]
 macro_rules! assert_settings {
     ($frame:expr) => {{
@@ -85,6 +85,35 @@ impl Handle {
         self.codec.get_mut().write(PREFACE).unwrap();
     }
 
+    /// Writes raw bytes, for frames that the codec is unable to encode
+    pub fn send_bytes(&mut self, data: &[u8]) {
+        use std::io::Write;
+
+        self.codec.get_mut().write_all(data).unwrap();
+    }
+
+    /// Receive the next `n` frames
+    pub fn recv_frames(self, n: usize)
+        -> Box<Future<Item = (Vec<Frame>, Self), Error = ()>>
+    {
+        use futures::future::{self, Loop};
+
+        let ret = future::loop_fn((vec![], self), move |(mut frames, me)| {
+            me.into_future().unwrap()
+                .map(move |(frame, me)| {
+                    frames.push(frame.expect("unexpected EOF"));
+
+                    if frames.len() == n {
+                        Loop::Break((frames, me))
+                    } else {
+                        Loop::Continue((frames, me))
+                    }
+                })
+        });
+
+        Box::new(ret)
+    }
+
     /// Read the client preface
     pub fn read_preface(self)
         -> Box<Future<Item = Self, Error = io::Error>>
@@ -100,12 +129,17 @@ impl Handle {
     }
 
     /// Perform the H2 handshake
-    pub fn assert_client_handshake(mut self)
+    pub fn assert_client_handshake(self)
+        -> Box<Future<Item = (frame::Settings, Self), Error = h2::Error>>
+    {
+        self.assert_client_handshake_with_settings(frame::Settings::default())
+    }
+
+    pub fn assert_client_handshake_with_settings(mut self, settings: frame::Settings)
         -> Box<Future<Item = (frame::Settings, Self), Error = h2::Error>>
     {
         // Send a settings frame
-        let frame = frame::Settings::default();
-        self.send(frame.into()).unwrap();
+        self.send(settings.into()).unwrap();
 
         let ret = self.read_preface().unwrap()
             .and_then(|me| me.into_future().unwrap())
@@ -146,6 +180,48 @@ impl Handle {
 
         Box::new(ret)
     }
+
+    /// Perform the H2 handshake, acting as the client
+    pub fn assert_server_handshake(self)
+        -> Box<Future<Item = (frame::Settings, Self), Error = h2::Error>>
+    {
+        self.assert_server_handshake_with_settings(frame::Settings::default())
+    }
+
+    pub fn assert_server_handshake_with_settings(mut self, settings: frame::Settings)
+        -> Box<Future<Item = (frame::Settings, Self), Error = h2::Error>>
+    {
+        // Write the preface followed by a settings frame
+        self.write_preface();
+        self.send(settings.into()).unwrap();
+
+        let ret = self.into_future().unwrap()
+            .map(|(frame, mut me)| {
+                let settings = assert_settings!(frame.unwrap());
+                assert!(!settings.is_ack());
+
+                // Send the ACK
+                let ack = frame::Settings::ack();
+                me.send(ack.into()).unwrap();
+
+                (settings, me)
+            })
+            .and_then(|(settings, me)| {
+                me.into_future().unwrap()
+                    .map(|(frame, me)| {
+                        let f = assert_settings!(frame.unwrap());
+
+                        // Is ACK
+                        assert!(f.is_ack());
+
+                        (settings, me)
+                    })
+            })
+            .map_err(|_| unimplemented!())
+            ;
+
+        Box::new(ret)
+    }
 }
 
 impl Stream for Handle {